@@ -0,0 +1,562 @@
+use super::Finding;
+use crate::dependency::DependencySource;
+use crate::lockfile::LockFile;
+use crate::package::{Package, Packument};
+use crate::registry::RegistryClient;
+use crate::security::{Advisory, SafeReason, SecurityChecker};
+use crate::tree::{DependencyPath, Importer, InstalledTree, PathStep};
+use crate::update::{rewrite_range, PlannedUpdate};
+use crate::workspace::Workspace;
+use anyhow::Result;
+use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+
+/// How a vulnerable copy goes away.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixAction {
+    /// The declared ranges already allow safe versions all the way down, so
+    /// resolving again is enough.
+    Reinstall,
+    /// Raising the range of a direct dependency pulls in a safe version.
+    Update(PlannedUpdate),
+    /// Nothing the dependents accept is safe; an entry in the root
+    /// manifest's `overrides` forces a safe version on all of them.
+    Override { name: String, range: String, version: Version },
+    /// No published version is free of the advisories.
+    Unfixable,
+}
+
+/// The fix for one vulnerable copy, reached through one dependency path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedFix {
+    pub name: String,
+    pub version: Version,
+    pub location: String,
+    /// How the copy is reached, e.g. `app > mkdirp > minimist`.
+    pub via: String,
+    pub advisories: Vec<String>,
+    pub action: FixAction,
+    /// Why the version the fix moves to was picked, when the vulnerable
+    /// package's own version is what changes.
+    pub reason: Option<SafeReason>,
+    /// Whether the fix crosses a semver-major boundary, e.g. `0.5.x` to
+    /// `1.x`, and so needs `--force`.
+    pub breaking: bool,
+}
+
+pub const FIX_HEADERS: [&str; 5] = ["Package", "Via", "Advisories", "Fix", "Breaking"];
+
+impl PlannedFix {
+    pub fn row(&self) -> Vec<String> {
+        let fix = match &self.action {
+            FixAction::Reinstall => "reinstall within the declared ranges".to_string(),
+            FixAction::Update(update) => format!(
+                "{} {} -> {} ({} -> {})",
+                update.name,
+                update.from_version.as_ref().map_or("-".to_string(), Version::to_string),
+                update.to_version,
+                update.from_range,
+                update.to_range
+            ),
+            FixAction::Override { name, range, .. } => format!("override {} to {}", name, range),
+            FixAction::Unfixable => "no safe version published".to_string(),
+        };
+        let fix = match self.reason {
+            Some(reason) => format!("{}, {}", fix, reason),
+            None => fix,
+        };
+        vec![
+            format!("{}@{}", self.name, self.version),
+            self.via.clone(),
+            self.advisories.join(", "),
+            fix,
+            if self.breaking { "yes".to_string() } else { String::new() },
+        ]
+    }
+}
+
+/// The fixes for every finding, sorted by location.
+#[derive(Debug, Clone, Default)]
+pub struct FixPlan {
+    pub fixes: Vec<PlannedFix>,
+}
+
+impl FixPlan {
+    /// The fixes `audit --fix` applies: non-breaking ones, or all with `force`.
+    pub fn applicable(&self, force: bool) -> impl Iterator<Item = &PlannedFix> {
+        self.fixes
+            .iter()
+            .filter(move |fix| fix.action != FixAction::Unfixable && (force || !fix.breaking))
+    }
+
+    /// Fixes left out because they're breaking and `force` isn't set.
+    pub fn held_back(&self, force: bool) -> usize {
+        if force {
+            return 0;
+        }
+        self.fixes
+            .iter()
+            .filter(|fix| fix.breaking && fix.action != FixAction::Unfixable)
+            .count()
+    }
+
+    /// The findings `audit --fix` leaves behind: copies with no fix planned,
+    /// or an unfixable or held-back one on some path.
+    pub fn remaining(&self, findings: &[Finding], force: bool) -> Vec<Finding> {
+        findings
+            .iter()
+            .filter(|finding| {
+                let mut fixes = self.fixes.iter().filter(|fix| fix.location == finding.location).peekable();
+                fixes.peek().is_none()
+                    || fixes.any(|fix| fix.action == FixAction::Unfixable || (fix.breaking && !force))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The range updates to apply, one per declared dependency: the highest
+    /// any fix asks for.
+    pub fn updates(&self, force: bool) -> Vec<PlannedUpdate> {
+        let mut updates: BTreeMap<(String, &'static str, String), PlannedUpdate> = BTreeMap::new();
+        for fix in self.applicable(force) {
+            let FixAction::Update(update) = &fix.action else {
+                continue;
+            };
+            let key = (update.workspace.clone(), update.field, update.name.clone());
+            match updates.get(&key) {
+                Some(existing) if existing.to_version >= update.to_version => {}
+                _ => {
+                    updates.insert(key, update.clone());
+                }
+            }
+        }
+        updates.into_values().collect()
+    }
+
+    /// The overrides to add to the root manifest, the highest per package.
+    pub fn overrides(&self, force: bool) -> BTreeMap<String, String> {
+        let mut overrides: BTreeMap<String, (&Version, &String)> = BTreeMap::new();
+        for fix in self.applicable(force) {
+            let FixAction::Override { name, range, version } = &fix.action else {
+                continue;
+            };
+            if overrides.get(name).is_none_or(|(highest, _)| *highest < version) {
+                overrides.insert(name.clone(), (version, range));
+            }
+        }
+        overrides.into_iter().map(|(name, (_, range))| (name, range.clone())).collect()
+    }
+}
+
+/// Works out, for every vulnerable copy and every path leading to it, the
+/// least disruptive fix: resolving again when the declared ranges allow a
+/// safe version, otherwise the smallest upgrade of the direct dependency the
+/// path starts at that leads to a safe version, otherwise an override. Copies
+/// nothing depends on are left to `rpm prune`.
+pub async fn plan_fixes(
+    registry: &RegistryClient,
+    checker: &SecurityChecker,
+    installed: &LockFile,
+    importers: Vec<Importer>,
+    findings: &[Finding],
+    workspace: Option<&Workspace>,
+) -> Result<FixPlan> {
+    let mut by_location: BTreeMap<&str, Vec<&Finding>> = BTreeMap::new();
+    for finding in findings {
+        by_location.entry(&finding.location).or_default().push(finding);
+    }
+
+    let tree = InstalledTree::new(installed, importers);
+    let mut fixes = Vec::new();
+
+    for (location, findings) in by_location {
+        let finding = findings[0];
+        let vulnerable = Vulnerable {
+            name: &finding.name,
+            version: Version::parse(&finding.version)?,
+            advisories: findings.iter().map(|finding| finding.advisory.clone()).collect(),
+        };
+        let directory = location.rsplit("node_modules/").next().unwrap_or(location);
+        let paths: Vec<DependencyPath> = tree
+            .explain(directory)
+            .into_iter()
+            .filter(|explanation| explanation.location == location)
+            .flat_map(|explanation| explanation.paths)
+            .filter(|path| {
+                let first = &path.steps[0];
+                workspace.is_none_or(|ws| ws.resolve_local(&first.name, &first.range).is_none())
+            })
+            .collect();
+
+        let fix = |via: String, (action, breaking, reason): Planned| PlannedFix {
+            name: finding.name.clone(),
+            version: vulnerable.version.clone(),
+            location: location.to_string(),
+            via,
+            advisories: vulnerable.advisories.iter().map(|advisory| advisory.id.clone()).collect(),
+            action,
+            reason,
+            breaking,
+        };
+
+        let mut planned = Vec::new();
+        let mut needs_override = false;
+        for path in &paths {
+            match plan_path(registry, checker, path, &vulnerable).await? {
+                Some(planned_path) => planned.push(fix(via(path), planned_path)),
+                None => needs_override = true,
+            }
+        }
+
+        if needs_override {
+            let planned_override = plan_override(registry, checker, &vulnerable).await?;
+            let via = paths.iter().map(via).collect::<Vec<_>>().join(", ");
+            planned = vec![fix(via, planned_override)];
+        }
+        fixes.extend(planned);
+    }
+
+    Ok(FixPlan { fixes })
+}
+
+/// `app > mkdirp > minimist` for a path from `app`.
+fn via(path: &DependencyPath) -> String {
+    std::iter::once(path.importer.as_str())
+        .chain(path.steps.iter().map(|step| step.name.as_str()))
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
+
+/// An installed copy and the advisories affecting it.
+struct Vulnerable<'a> {
+    /// The registry package.
+    name: &'a str,
+    version: Version,
+    advisories: Vec<Advisory>,
+}
+
+impl Vulnerable<'_> {
+    fn is_safe(&self, version: &Version) -> bool {
+        !self.advisories.iter().any(|advisory| advisory.affects(version))
+    }
+}
+
+/// A fix action, whether it's breaking, and why its version was picked.
+type Planned = (FixAction, bool, Option<SafeReason>);
+
+/// What resolving a dependency path from some version of its first package
+/// leads to.
+enum Outcome {
+    /// A step no longer depends on the next one.
+    Dropped,
+    Resolved(Version),
+    /// A step isn't a registry range, or nothing satisfies it.
+    Unknown,
+}
+
+/// The fix for one path, or `None` when only an override can help.
+async fn plan_path(
+    registry: &RegistryClient,
+    checker: &SecurityChecker,
+    path: &DependencyPath,
+    vulnerable: &Vulnerable<'_>,
+) -> Result<Option<Planned>> {
+    let first = &path.steps[0];
+    let Some((package, version_req)) = registry_range(&first.name, &first.range) else {
+        return Ok(None);
+    };
+    let packument = registry.fetch_packument(&package).await?;
+    let is_fixed = |outcome: &Outcome| match outcome {
+        Outcome::Dropped => true,
+        Outcome::Resolved(version) => vulnerable.is_safe(version),
+        Outcome::Unknown => false,
+    };
+
+    if let Some(resolved) = packument.max_satisfying(&version_req) {
+        if is_fixed(&resolve_path(registry, resolved, &path.steps[1..]).await?) {
+            return Ok(Some((FixAction::Reinstall, false, None)));
+        }
+    }
+
+    let Ok(current) = Version::parse(&first.version) else {
+        return Ok(None);
+    };
+    let update = |version: &Version| PlannedUpdate {
+        name: first.name.clone(),
+        workspace: path.importer.clone(),
+        field: first.kind.manifest_field(),
+        from_range: first.range.clone(),
+        to_range: rewrite_range(&first.range, version),
+        from_version: Some(current.clone()),
+        to_version: version.clone(),
+    };
+
+    // The vulnerable copy is the direct dependency itself.
+    if path.steps.len() == 1 {
+        let available = releases(&packument);
+        let Ok(safe) = checker.find_safe_version(&package, &current, &version_req, &vulnerable.advisories, &available)
+        else {
+            return Ok(Some((FixAction::Unfixable, false, None)));
+        };
+        // Going back within the range needs a pin, or the rewritten range
+        // resolves to the vulnerable releases above again.
+        let mut planned = update(&safe.version);
+        let resolves_safe = registry_range(&first.name, &planned.to_range)
+            .and_then(|(_, new_req)| packument.max_satisfying(&new_req).cloned())
+            .is_some_and(|resolved| vulnerable.is_safe(&resolved.version));
+        if !resolves_safe {
+            planned.to_range = exact_range(&first.range, &safe.version);
+        }
+        let breaking = is_breaking(&current, &safe.version);
+        return Ok(Some((FixAction::Update(planned), breaking, Some(safe.reason))));
+    }
+
+    // The lowest newer version whose rewritten range resolves to something
+    // that no longer leads to a vulnerable copy. The range, not the
+    // candidate, is what gets installed.
+    for candidate in releases(&packument).into_iter().filter(|version| version > &current) {
+        let planned = update(&candidate);
+        let Some((_, new_req)) = registry_range(&first.name, &planned.to_range) else {
+            continue;
+        };
+        let Some(resolved) = packument.max_satisfying(&new_req) else {
+            continue;
+        };
+        if is_fixed(&resolve_path(registry, resolved, &path.steps[1..]).await?) {
+            let breaking = is_breaking(&current, &resolved.version);
+            return Ok(Some((FixAction::Update(planned), breaking, None)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Follows `steps` down from `from`, resolving each range the way an
+/// install would.
+async fn resolve_path(registry: &RegistryClient, from: &Package, steps: &[PathStep]) -> Result<Outcome> {
+    let mut package = from.clone();
+
+    for step in steps {
+        let Some(spec) = package
+            .dependencies
+            .get(&step.name)
+            .or_else(|| package.optional_dependencies.get(&step.name))
+        else {
+            return Ok(Outcome::Dropped);
+        };
+        let Some((name, version_req)) = registry_range(&step.name, spec) else {
+            return Ok(Outcome::Unknown);
+        };
+        let packument = registry.fetch_packument(&name).await?;
+        let Some(next) = packument.max_satisfying(&version_req).cloned() else {
+            return Ok(Outcome::Unknown);
+        };
+        package = next;
+    }
+
+    Ok(Outcome::Resolved(package.version))
+}
+
+/// An override forcing the lowest safe version of the vulnerable package
+/// the installed version's caret range allows, or the lowest safe version
+/// above that. A caret range is used when everything it admits is safe, the
+/// exact version otherwise.
+async fn plan_override(
+    registry: &RegistryClient,
+    checker: &SecurityChecker,
+    vulnerable: &Vulnerable<'_>,
+) -> Result<Planned> {
+    let packument = registry.fetch_packument(vulnerable.name).await?;
+    let available = releases(&packument);
+    let compatible = VersionReq::parse(&format!("^{}", vulnerable.version))?;
+    let Ok(safe) =
+        checker.find_safe_version(vulnerable.name, &vulnerable.version, &compatible, &vulnerable.advisories, &available)
+    else {
+        return Ok((FixAction::Unfixable, false, None));
+    };
+    let reason = safe.reason;
+    let safe = safe.version;
+
+    let caret = format!("^{}", safe);
+    let caret_is_safe = VersionReq::parse(&caret)
+        .ok()
+        .and_then(|version_req| packument.max_satisfying(&version_req))
+        .is_some_and(|resolved| vulnerable.is_safe(&resolved.version));
+    let range = if caret_is_safe { caret } else { safe.to_string() };
+
+    let breaking = is_breaking(&vulnerable.version, &safe);
+    Ok((FixAction::Override { name: vulnerable.name.to_string(), range, version: safe }, breaking, Some(reason)))
+}
+
+/// The registry package and range behind a specifier, if it is one.
+fn registry_range(name: &str, spec: &str) -> Option<(String, VersionReq)> {
+    match DependencySource::parse(name, spec) {
+        Ok(DependencySource::Registry(version_req)) => Some((name.to_string(), version_req)),
+        Ok(DependencySource::Alias { package, version_req }) => Some((package, version_req)),
+        _ => None,
+    }
+}
+
+/// `range` pinned to exactly `version`, keeping an `npm:` alias.
+fn exact_range(range: &str, version: &Version) -> String {
+    match range.strip_prefix("npm:").and_then(|rest| rest.rsplit_once('@')) {
+        Some((alias, _)) => format!("npm:{}@={}", alias, version),
+        None => format!("={}", version),
+    }
+}
+
+fn releases(packument: &Packument) -> Vec<Version> {
+    packument
+        .sorted_versions()
+        .into_iter()
+        .filter(|version| version.pre.is_empty())
+        .collect()
+}
+
+/// Whether moving from `from` to `to` may break dependents under semver:
+/// a new major version, or a new minor (patch for `0.0.x`) below 1.0.0.
+fn is_breaking(from: &Version, to: &Version) -> bool {
+    match (from.major, from.minor) {
+        (0, 0) => to.major != 0 || to.minor != 0 || to.patch != from.patch,
+        (0, _) => to.major != 0 || to.minor != from.minor,
+        _ => to.major != from.major,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{audit, AdvisorySource};
+    use crate::package::PackageJson;
+    use crate::registry::mock::{packument, MockRegistry};
+    use crate::testing::{locked, locked_requiring, lockfile};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_is_breaking() {
+        let v = |text: &str| Version::parse(text).unwrap();
+        assert!(!is_breaking(&v("1.2.0"), &v("1.9.3")));
+        assert!(is_breaking(&v("1.2.0"), &v("2.0.0")));
+        assert!(!is_breaking(&v("0.5.1"), &v("0.5.6")));
+        assert!(is_breaking(&v("0.5.1"), &v("0.6.0")));
+        assert!(is_breaking(&v("0.0.8"), &v("0.0.9")));
+    }
+
+    #[tokio::test]
+    async fn test_plan_fixes() {
+        let advisories = serde_json::json!({
+            "minimist": [{
+                "id": 1179,
+                "url": "https://github.com/advisories/GHSA-vh95-rmgr-6w4m",
+                "title": "Prototype Pollution in minimist",
+                "severity": "moderate",
+                "vulnerable_versions": "<0.2.1 || >=1.0.0 <1.2.6"
+            }]
+        });
+        let documents = HashMap::from([
+            ("/-/npm/v1/security/advisories/bulk".to_string(), advisories.to_string()),
+            (
+                "/minimist".to_string(),
+                packument("minimist", &[("0.0.8", &[]), ("1.2.0", &[]), ("1.2.6", &[]), ("1.2.8", &[])]),
+            ),
+            (
+                "/mkdirp".to_string(),
+                packument(
+                    "mkdirp",
+                    &[("0.5.1", &[("minimist", "0.0.8")]), ("0.5.6", &[("minimist", "^1.2.6")]), ("1.0.4", &[])],
+                ),
+            ),
+            ("/optimist".to_string(), packument("optimist", &[("0.6.1", &[("minimist", "~0.0.1")])])),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let client = RegistryClient::with_url(registry.url.clone());
+        let checker = SecurityChecker::with_url(registry.url.clone());
+
+        // A top-level copy whose range allows a fix, one nested under a
+        // dependency pinning it, and one under a dependency with no fixed
+        // release at all.
+        let manifest: PackageJson = serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": {"minimist": "^1.2.0", "mkdirp": "=0.5.1", "optimist": "^0.6.1"}
+        }))
+        .unwrap();
+        let mut mkdirp = locked_requiring("0.5.1", serde_json::json!({"minimist": "0.0.8"}));
+        mkdirp["dependencies"] = serde_json::json!({"minimist": locked("0.0.8")});
+        let mut optimist = locked_requiring("0.6.1", serde_json::json!({"minimist": "~0.0.1"}));
+        optimist["dependencies"] = serde_json::json!({"minimist": locked("0.0.8")});
+        let installed = lockfile(serde_json::json!({
+            "minimist": locked("1.2.0"),
+            "mkdirp": mkdirp,
+            "optimist": optimist
+        }));
+
+        let report = audit(&AdvisorySource::Registry(checker), &installed).await.unwrap();
+        let checker = SecurityChecker::with_url(registry.url.clone());
+        let importers = vec![Importer::from_manifest(&manifest)];
+        let plan = plan_fixes(&client, &checker, &installed, importers, &report.findings, None)
+            .await
+            .unwrap();
+
+        let rows: Vec<Vec<String>> = plan.fixes.iter().map(PlannedFix::row).collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["minimist@1.2.0", "app > minimist", "1179", "reinstall within the declared ranges", ""],
+                vec!["minimist@0.0.8", "app > mkdirp > minimist", "1179", "mkdirp 0.5.1 -> 0.5.6 (=0.5.1 -> =0.5.6)", ""],
+                vec![
+                    "minimist@0.0.8",
+                    "app > optimist > minimist",
+                    "1179",
+                    "override minimist to ^1.2.6, no safe version in range",
+                    "yes"
+                ],
+            ]
+        );
+
+        assert_eq!(plan.held_back(false), 1);
+        assert_eq!(plan.updates(false).len(), 1);
+        assert!(plan.overrides(false).is_empty());
+        assert_eq!(plan.overrides(true)["minimist"], "^1.2.6");
+
+        let remaining = plan.remaining(&report.findings, false);
+        let locations: Vec<&str> = remaining.iter().map(|finding| finding.location.as_str()).collect();
+        assert_eq!(locations, vec!["node_modules/optimist/node_modules/minimist"]);
+        assert!(plan.remaining(&report.findings, true).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_plan_fixes_goes_back_within_range() {
+        let advisories = serde_json::json!({
+            "a": [{"id": 1, "title": "Advisory 1", "severity": "high", "vulnerable_versions": ">=1.5.0"}]
+        });
+        let documents = HashMap::from([
+            ("/-/npm/v1/security/advisories/bulk".to_string(), advisories.to_string()),
+            ("/a".to_string(), packument("a", &[("1.4.0", &[]), ("1.4.2", &[]), ("1.5.0", &[]), ("1.6.0", &[])])),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let client = RegistryClient::with_url(registry.url.clone());
+        let checker = SecurityChecker::with_url(registry.url.clone());
+
+        let manifest: PackageJson = serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": {"a": "^1.0.0"}
+        }))
+        .unwrap();
+        let installed = lockfile(serde_json::json!({"a": locked("1.5.0")}));
+
+        let report = audit(&AdvisorySource::Registry(checker), &installed).await.unwrap();
+        let checker = SecurityChecker::with_url(registry.url.clone());
+        let importers = vec![Importer::from_manifest(&manifest)];
+        let plan = plan_fixes(&client, &checker, &installed, importers, &report.findings, None)
+            .await
+            .unwrap();
+
+        // `^1.4.2` would resolve to 1.6.0 again, so the range is pinned.
+        let rows: Vec<Vec<String>> = plan.fixes.iter().map(PlannedFix::row).collect();
+        let fix = "a 1.5.0 -> 1.4.2 (^1.0.0 -> =1.4.2), highest older safe version in range";
+        assert_eq!(rows, vec![vec!["a@1.5.0", "app > a", "1", fix, ""]]);
+    }
+}
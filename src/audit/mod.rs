@@ -0,0 +1,280 @@
+use crate::dependency::DependencySource;
+use crate::lockfile::LockFile;
+use crate::osv::OsvDatabase;
+use crate::security::{Advisory, SecurityChecker, Severity};
+use crate::tree::location;
+use policy::Suppressed;
+use anyhow::Result;
+use semver::Version;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub mod fix;
+pub mod policy;
+pub mod report;
+pub mod signatures;
+
+/// Exit status when a finding is at or above `--audit-level`.
+pub const EXIT_VULNERABLE: u8 = 1;
+/// Exit status when the audit itself failed, e.g. the advisory source
+/// couldn't be reached, so CI can tell it apart from a finding.
+pub const EXIT_FAILED: u8 = 2;
+
+/// Where advisories come from.
+pub enum AdvisorySource {
+    Registry(SecurityChecker),
+    /// An imported OSV database, read without network access.
+    Offline(OsvDatabase),
+}
+
+/// How old an imported database can get before the audit warns about it.
+const STALE_DATABASE_DAYS: u64 = 7;
+
+impl AdvisorySource {
+    /// The offline database when one has been imported, the registry
+    /// otherwise. An old database is used all the same, with a warning.
+    pub async fn detect(database: OsvDatabase) -> Result<Self> {
+        Ok(match database.summary().await? {
+            Some(summary) => {
+                let age = summary.age_days();
+                log::info!(
+                    "Using the offline advisory database imported from {} {} days ago ({} advisories)",
+                    summary.source.display(),
+                    age,
+                    summary.records
+                );
+                if age > STALE_DATABASE_DAYS {
+                    log::warn!(
+                        "The offline advisory database is {} days old; import a fresh export or pass --registry",
+                        age
+                    );
+                }
+                AdvisorySource::Offline(database)
+            }
+            None => AdvisorySource::Registry(SecurityChecker::new()),
+        })
+    }
+
+    /// The imported database, failing when there is none.
+    pub async fn offline(database: OsvDatabase) -> Result<Self> {
+        if database.summary().await?.is_none() {
+            anyhow::bail!("No offline advisory database has been imported; run 'rpm audit db import' first");
+        }
+        Ok(AdvisorySource::Offline(database))
+    }
+
+    /// The advisories for the packages in `query`, keyed by package.
+    pub async fn advisories(
+        &self,
+        query: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Result<HashMap<String, Vec<Advisory>>> {
+        match self {
+            AdvisorySource::Registry(checker) => checker.fetch_advisories(query).await,
+            AdvisorySource::Offline(database) => database.advisories(query).await,
+        }
+    }
+}
+
+/// An installed copy of a registry package that was checked.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditedPackage {
+    pub name: String,
+    pub version: String,
+    pub location: String,
+}
+
+/// Everything `rpm audit` checked and what it found.
+#[derive(Debug, Clone, Default)]
+pub struct AuditReport {
+    /// Sorted by location.
+    pub audited: Vec<AuditedPackage>,
+    /// Sorted by location, then advisory.
+    pub findings: Vec<Finding>,
+    /// Findings the audit policy accepts, left out of `findings`.
+    pub suppressed: Vec<Suppressed>,
+    /// Findings only an expired ignore matches. They're in `findings` too.
+    pub expired: Vec<Suppressed>,
+}
+
+impl AuditReport {
+    /// The number of findings per severity, leaving out those without any.
+    pub fn severity_counts(&self) -> BTreeMap<Severity, usize> {
+        let mut counts = BTreeMap::new();
+        for finding in &self.findings {
+            *counts.entry(finding.advisory.severity).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Whether any finding is at least as severe as `level`; without a
+    /// level, whether there are findings at all. Findings of unknown
+    /// severity, such as OSV malware records, fail at every level.
+    pub fn fails(&self, level: Option<Severity>) -> bool {
+        match level {
+            Some(level) => self.findings.iter().any(|finding| {
+                let severity = finding.advisory.severity;
+                severity == Severity::Unknown || severity >= level
+            }),
+            None => !self.findings.is_empty(),
+        }
+    }
+}
+
+/// An advisory affecting one installed copy of a package.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    /// The registry package, which differs from the directory name for
+    /// `npm:` aliases.
+    pub name: String,
+    pub version: String,
+    pub location: String,
+    pub advisory: Advisory,
+}
+
+/// Every installed copy of a registry package in `installed`: its path
+/// through `node_modules`, the registry package and its version. Linked
+/// packages and git, tarball and local dependencies are left out.
+pub fn registry_copies(installed: &LockFile) -> Vec<(Vec<String>, String, Version)> {
+    installed
+        .nodes()
+        .into_iter()
+        .filter(|(_, entry)| !entry.is_link())
+        .filter_map(|(path, entry)| {
+            let name = path.last()?;
+            let package = match entry.from() {
+                None => name.clone(),
+                Some(spec) => match DependencySource::parse(name, spec) {
+                    Ok(DependencySource::Alias { package, .. }) => package,
+                    _ => return None,
+                },
+            };
+            let version = Version::parse(entry.version()).ok()?;
+            Some((path, package, version))
+        })
+        .collect()
+}
+
+/// Checks every installed copy in `installed` against the advisories from
+/// `source`, asking for all of them at once. Linked packages and git, tarball
+/// and local dependencies aren't published to the registry and are skipped.
+pub async fn audit(source: &AdvisorySource, installed: &LockFile) -> Result<AuditReport> {
+    let copies = registry_copies(installed);
+
+    let mut query: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (_, package, version) in &copies {
+        query.entry(package.clone()).or_default().insert(version.to_string());
+    }
+    let advisories = source.advisories(&query).await?;
+
+    let mut findings: Vec<Finding> = copies
+        .iter()
+        .flat_map(|(path, package, version)| {
+            advisories
+                .get(package)
+                .into_iter()
+                .flatten()
+                .filter(|advisory| advisory.affects(version))
+                .map(|advisory| Finding {
+                    name: package.clone(),
+                    version: version.to_string(),
+                    location: location(path),
+                    advisory: advisory.clone(),
+                })
+        })
+        .collect();
+
+    findings.sort_by(|a, b| (&a.location, &a.advisory.id).cmp(&(&b.location, &b.advisory.id)));
+
+    let mut audited: Vec<AuditedPackage> = copies
+        .into_iter()
+        .map(|(path, package, version)| AuditedPackage {
+            name: package,
+            version: version.to_string(),
+            location: location(&path),
+        })
+        .collect();
+    audited.sort_by(|a, b| a.location.cmp(&b.location));
+
+    Ok(AuditReport { audited, findings, ..Default::default() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::mock::MockRegistry;
+    use crate::testing::{locked, lockfile};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    const BULK: &str = "/-/npm/v1/security/advisories/bulk";
+
+    #[test]
+    fn test_unknown_severity_fails_every_level() {
+        let finding = Finding {
+            name: "flatmap-stream".to_string(),
+            version: "0.1.1".to_string(),
+            location: "node_modules/flatmap-stream".to_string(),
+            advisory: Advisory {
+                id: "MAL-2018-1".to_string(),
+                url: String::new(),
+                title: "Malicious code in flatmap-stream".to_string(),
+                severity: Severity::Unknown,
+                vulnerable_versions: "=0.1.1".to_string(),
+                cwe: Vec::new(),
+                cvss: None,
+            },
+        };
+        let report = AuditReport { findings: vec![finding], ..Default::default() };
+        assert!(report.fails(None));
+        assert!(report.fails(Some(Severity::Critical)));
+    }
+
+    #[tokio::test]
+    async fn test_audit_reports_each_installed_copy() {
+        let advisories = serde_json::json!({
+            "minimist": [{
+                "id": 1179,
+                "url": "https://github.com/advisories/GHSA-vh95-rmgr-6w4m",
+                "title": "Prototype Pollution in minimist",
+                "severity": "moderate",
+                "vulnerable_versions": "<0.2.1 || >=1.0.0 <1.2.3",
+                "cwe": ["CWE-1321"],
+                "cvss": {"score": 5.6, "vectorString": "CVSS:3.1/AV:N/AC:H/PR:N/UI:N/S:U/C:L/I:L/A:L"}
+            }]
+        });
+        let documents = HashMap::from([(BULK.to_string(), advisories.to_string())]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let source = AdvisorySource::Registry(SecurityChecker::with_url(registry.url.clone()));
+
+        // Three copies of minimist: one fixed, one vulnerable at the top
+        // level and one vulnerable copy nested under `mkdirp`.
+        let mut mkdirp = locked("0.5.1");
+        mkdirp["dependencies"] = serde_json::json!({"minimist": locked("0.0.8")});
+        let mut optimist = locked("0.6.1");
+        optimist["dependencies"] = serde_json::json!({"minimist": locked("1.2.8")});
+        let installed = lockfile(serde_json::json!({
+            "minimist": locked("1.2.0"),
+            "mkdirp": mkdirp,
+            "optimist": optimist
+        }));
+
+        let report = audit(&source, &installed).await.unwrap();
+        assert_eq!(report.audited.len(), 5);
+        assert!(report.fails(None));
+        assert!(report.fails(Some(Severity::Moderate)));
+        assert!(!report.fails(Some(Severity::High)));
+        let findings = report.findings;
+
+        let locations: Vec<(&str, &str)> = findings
+            .iter()
+            .map(|finding| (finding.location.as_str(), finding.version.as_str()))
+            .collect();
+        assert_eq!(
+            locations,
+            vec![("node_modules/minimist", "1.2.0"), ("node_modules/mkdirp/node_modules/minimist", "0.0.8")]
+        );
+        assert_eq!(registry.hits(BULK), 1);
+        let sent: serde_json::Value = serde_json::from_str(&registry.body(BULK).unwrap()).unwrap();
+        assert_eq!(sent["minimist"], serde_json::json!(["0.0.8", "1.2.0", "1.2.8"]));
+    }
+}
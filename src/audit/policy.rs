@@ -0,0 +1,202 @@
+use super::{AuditReport, Finding};
+use crate::date::parse_date;
+use anyhow::{bail, Context, Result};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Read from the project root; audits without one suppress nothing.
+pub const POLICY_FILE: &str = ".rpm-audit.json";
+
+/// The advisories a project has accepted for now, e.g.
+///
+/// ```json
+/// {
+///   "ignore": [{
+///     "id": "GHSA-vh95-rmgr-6w4m",
+///     "paths": ["node_modules/mkdirp/**"],
+///     "justification": "mkdirp only ever parses our own build flags",
+///     "expires": "2026-12-31"
+///   }]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditPolicy {
+    #[serde(default)]
+    pub ignore: Vec<Ignore>,
+}
+
+/// Accepts one advisory until it expires.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ignore {
+    /// The advisory's ID, or its GHSA ID when the registry numbers it.
+    pub id: String,
+    /// Globs over install locations such as `node_modules/a/**`. Empty
+    /// means everywhere.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    pub justification: String,
+    /// `YYYY-MM-DD`, the last day the ignore applies (UTC).
+    pub expires: String,
+}
+
+/// A finding and the ignore that matched it.
+#[derive(Debug, Clone)]
+pub struct Suppressed {
+    pub finding: Finding,
+    pub ignore: Ignore,
+}
+
+impl AuditPolicy {
+    pub async fn load(root: &Path) -> Result<Self> {
+        let path = root.join(POLICY_FILE);
+        let content = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        Self::parse(&content).with_context(|| format!("Invalid audit policy {}", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let policy: Self = serde_json::from_str(content)?;
+        for ignore in &policy.ignore {
+            if ignore.justification.trim().is_empty() {
+                bail!("the ignore for {} has no justification", ignore.id);
+            }
+            if parse_date(&ignore.expires).is_none() {
+                bail!("the ignore for {} expires on {:?}, expected YYYY-MM-DD", ignore.id, ignore.expires);
+            }
+            for path in &ignore.paths {
+                Pattern::new(path).with_context(|| format!("the ignore for {} has an invalid path {:?}", ignore.id, path))?;
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Moves the findings an unexpired ignore matches from
+    /// `report.findings` to `report.suppressed`. Findings only an expired
+    /// ignore matches stay where they are and are listed in
+    /// `report.expired` too. `today` is in days since the Unix epoch.
+    pub fn apply(&self, report: &mut AuditReport, today: i64) {
+        let mut findings = Vec::new();
+        for finding in std::mem::take(&mut report.findings) {
+            let matching: Vec<&Ignore> = self.ignore.iter().filter(|ignore| ignore.matches(&finding)).collect();
+            if let Some(ignore) = matching.iter().find(|ignore| !ignore.expired(today)) {
+                report.suppressed.push(Suppressed { finding, ignore: (*ignore).clone() });
+                continue;
+            }
+            if let Some(ignore) = matching.first() {
+                report.expired.push(Suppressed { finding: finding.clone(), ignore: (*ignore).clone() });
+            }
+            findings.push(finding);
+        }
+        report.findings = findings;
+    }
+}
+
+impl Ignore {
+    fn matches(&self, finding: &Finding) -> bool {
+        let advisory = &finding.advisory;
+        let id_matches = self.id == advisory.id
+            || advisory.ghsa_id().is_some_and(|ghsa| ghsa.eq_ignore_ascii_case(&self.id));
+        if !id_matches {
+            return false;
+        }
+
+        // `*` stays within one directory, `**` crosses them.
+        let options = MatchOptions { require_literal_separator: true, ..MatchOptions::new() };
+        self.paths.is_empty()
+            || self.paths.iter().any(|path| {
+                Pattern::new(path).is_ok_and(|pattern| pattern.matches_with(&finding.location, options))
+            })
+    }
+
+    fn expired(&self, today: i64) -> bool {
+        // Checked when loading.
+        parse_date(&self.expires).is_none_or(|expires| today > expires)
+    }
+}
+
+/// Today in UTC, as days since the Unix epoch.
+pub fn today() -> i64 {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    (seconds / 86_400) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::{Advisory, Severity};
+
+    fn finding(location: &str, id: &str) -> Finding {
+        Finding {
+            name: "minimist".to_string(),
+            version: "0.0.8".to_string(),
+            location: location.to_string(),
+            advisory: Advisory {
+                id: id.to_string(),
+                url: "https://github.com/advisories/GHSA-vh95-rmgr-6w4m".to_string(),
+                title: "Prototype Pollution in minimist".to_string(),
+                severity: Severity::Moderate,
+                vulnerable_versions: "<0.2.1".to_string(),
+                cwe: Vec::new(),
+                cvss: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_apply_policy() {
+        let policy = AuditPolicy::parse(
+            r#"{
+              "ignore": [
+                {
+                  "id": "ghsa-vh95-rmgr-6w4m",
+                  "paths": ["node_modules/mkdirp/**"],
+                  "justification": "mkdirp only parses our own flags",
+                  "expires": "2024-06-30"
+                },
+                {
+                  "id": "1179",
+                  "paths": ["node_modules/optimist/*/minimist"],
+                  "justification": "optimist is only used by a dev script",
+                  "expires": "2024-01-31"
+                }
+              ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut report = AuditReport {
+            findings: vec![
+                finding("node_modules/minimist", "1179"),
+                finding("node_modules/mkdirp/node_modules/minimist", "1179"),
+                finding("node_modules/optimist/node_modules/minimist", "1179"),
+            ],
+            ..Default::default()
+        };
+        policy.apply(&mut report, parse_date("2024-06-30").unwrap());
+
+        let locations = |findings: Vec<&Finding>| findings.iter().map(|f| f.location.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            locations(report.findings.iter().collect()),
+            vec!["node_modules/minimist", "node_modules/optimist/node_modules/minimist"]
+        );
+        assert_eq!(
+            locations(report.suppressed.iter().map(|s| &s.finding).collect()),
+            vec!["node_modules/mkdirp/node_modules/minimist"]
+        );
+        assert_eq!(report.expired.len(), 1);
+        assert_eq!(report.expired[0].ignore.expires, "2024-01-31");
+
+        let error = AuditPolicy::parse(r#"{"ignore": [{"id": "1179", "justification": " ", "expires": "2024-01-31"}]}"#)
+            .unwrap_err();
+        assert!(error.to_string().contains("no justification"));
+        assert!(AuditPolicy::parse(r#"{"ignore": [{"id": "1179", "justification": "x", "expires": "soon"}]}"#).is_err());
+        assert!(AuditPolicy::parse(r#"{"ignore": [{"id": "1179", "justification": "x"}]}"#).is_err());
+    }
+}
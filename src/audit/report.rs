@@ -0,0 +1,720 @@
+use super::policy::{Ignore, POLICY_FILE};
+use super::{AuditReport, Finding};
+use crate::lockfile::LOCKFILE_NAME;
+use crate::security::Severity;
+use crate::table;
+use crate::workspace::Workspace;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+/// Bumped whenever a field of the JSON report changes meaning or goes away.
+/// The schema is in `docs/audit-report.schema.json`.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+const MANIFEST_FIELDS: [&str; 4] = ["dependencies", "devDependencies", "optionalDependencies", "peerDependencies"];
+
+pub const AUDIT_HEADERS: [&str; 6] = ["Severity", "Package", "Version", "Vulnerable", "Title", "Location"];
+
+pub const SUPPRESSED_HEADERS: [&str; 7] =
+    ["Severity", "Package", "Version", "Advisory", "Location", "Expires", "Justification"];
+
+/// One row per finding and a summary line, then the expired ignores and
+/// what the audit policy suppressed.
+pub fn render_table(report: &AuditReport) -> String {
+    let mut output = render_findings_table(report);
+
+    for expired in &report.expired {
+        output.push_str(&format!(
+            "\nThe ignore for {} at {} expired on {} and no longer applies\n",
+            expired.ignore.id, expired.finding.location, expired.ignore.expires
+        ));
+    }
+
+    if !report.suppressed.is_empty() {
+        let rows: Vec<Vec<String>> = report
+            .suppressed
+            .iter()
+            .map(|suppressed| {
+                vec![
+                    suppressed.finding.advisory.severity.to_string(),
+                    suppressed.finding.name.clone(),
+                    suppressed.finding.version.clone(),
+                    suppressed.finding.advisory.id.clone(),
+                    suppressed.finding.location.clone(),
+                    suppressed.ignore.expires.clone(),
+                    suppressed.ignore.justification.clone(),
+                ]
+            })
+            .collect();
+        output.push_str(&format!(
+            "\n{} suppressed by {}:\n{}",
+            report.suppressed.len(),
+            POLICY_FILE,
+            table::render(&SUPPRESSED_HEADERS, &rows)
+        ));
+    }
+    output
+}
+
+fn render_findings_table(report: &AuditReport) -> String {
+    if report.findings.is_empty() {
+        return format!("No vulnerabilities found in {} packages\n", report.audited.len());
+    }
+
+    let rows: Vec<Vec<String>> = report
+        .findings
+        .iter()
+        .map(|finding| {
+            vec![
+                finding.advisory.severity.to_string(),
+                finding.name.clone(),
+                finding.version.clone(),
+                finding.advisory.vulnerable_versions.clone(),
+                finding.advisory.title.clone(),
+                finding.location.clone(),
+            ]
+        })
+        .collect();
+    let counts = report.severity_counts();
+    let by_severity = Severity::DESCENDING
+        .iter()
+        .filter_map(|severity| Some(format!("{} {}", counts.get(severity)?, severity)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "{}\n{} vulnerabilities ({}) in {} of {} packages\n",
+        table::render(&AUDIT_HEADERS, &rows),
+        report.findings.len(),
+        by_severity,
+        vulnerable_packages(report),
+        report.audited.len()
+    )
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonReport<'a> {
+    schema_version: u32,
+    summary: JsonSummary,
+    findings: Vec<JsonFinding<'a>>,
+    suppressed: Vec<JsonSuppressed<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSummary {
+    audited: usize,
+    vulnerabilities: usize,
+    vulnerable_packages: usize,
+    severities: JsonSeverities,
+    suppressed: usize,
+}
+
+/// Every level is always present so consumers don't need defaults.
+#[derive(Serialize)]
+struct JsonSeverities {
+    critical: usize,
+    high: usize,
+    moderate: usize,
+    low: usize,
+    info: usize,
+    unknown: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonFinding<'a> {
+    id: &'a str,
+    package: &'a str,
+    version: &'a str,
+    location: &'a str,
+    severity: Severity,
+    title: &'a str,
+    url: &'a str,
+    vulnerable_versions: &'a str,
+    cwe: &'a [String],
+    cvss_score: Option<f64>,
+    /// When an expired ignore would have suppressed this finding, the day it
+    /// expired.
+    ignore_expired: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSuppressed<'a> {
+    #[serde(flatten)]
+    finding: JsonFinding<'a>,
+    justification: &'a str,
+    expires: &'a str,
+}
+
+fn json_finding<'a>(finding: &'a Finding, ignore_expired: Option<&'a str>) -> JsonFinding<'a> {
+    JsonFinding {
+        id: &finding.advisory.id,
+        package: &finding.name,
+        version: &finding.version,
+        location: &finding.location,
+        severity: finding.advisory.severity,
+        title: &finding.advisory.title,
+        url: &finding.advisory.url,
+        vulnerable_versions: &finding.advisory.vulnerable_versions,
+        cwe: &finding.advisory.cwe,
+        cvss_score: finding.advisory.cvss.as_ref().map(|cvss| cvss.score),
+        ignore_expired,
+    }
+}
+
+/// The report in the documented JSON shape. Field names and order don't
+/// depend on where the advisories came from.
+pub fn render_json(report: &AuditReport) -> Result<String> {
+    let json = JsonReport {
+        schema_version: JSON_SCHEMA_VERSION,
+        summary: JsonSummary {
+            audited: report.audited.len(),
+            vulnerabilities: report.findings.len(),
+            vulnerable_packages: vulnerable_packages(report),
+            severities: {
+                let counts = report.severity_counts();
+                let count = |severity| counts.get(&severity).copied().unwrap_or(0);
+                JsonSeverities {
+                    critical: count(Severity::Critical),
+                    high: count(Severity::High),
+                    moderate: count(Severity::Moderate),
+                    low: count(Severity::Low),
+                    info: count(Severity::Info),
+                    unknown: count(Severity::Unknown),
+                }
+            },
+            suppressed: report.suppressed.len(),
+        },
+        findings: report
+            .findings
+            .iter()
+            .map(|finding| {
+                let expired = report
+                    .expired
+                    .iter()
+                    .find(|expired| expired.finding.location == finding.location && expired.finding.advisory == finding.advisory);
+                json_finding(finding, expired.map(|expired| expired.ignore.expires.as_str()))
+            })
+            .collect(),
+        suppressed: report
+            .suppressed
+            .iter()
+            .map(|suppressed| JsonSuppressed {
+                finding: json_finding(&suppressed.finding, None),
+                justification: &suppressed.ignore.justification,
+                expires: &suppressed.ignore.expires,
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&json)? + "\n")
+}
+
+/// A SARIF 2.1.0 log with one rule per advisory and one result per finding.
+/// Direct dependencies point at their line in each `package.json` declaring
+/// them, everything else at its entry in the lockfile. Suppressed findings
+/// are included with their justification, so code scanning shows them as
+/// dismissed rather than fixed.
+pub fn render_sarif(report: &AuditReport, sources: &SourceFiles) -> Result<String> {
+    let all_findings = report
+        .findings
+        .iter()
+        .map(|finding| (finding, None))
+        .chain(report.suppressed.iter().map(|suppressed| (&suppressed.finding, Some(&suppressed.ignore))));
+
+    let mut rules = BTreeMap::new();
+    for (finding, _) in all_findings.clone() {
+        let advisory = &finding.advisory;
+        rules.entry(advisory.id.as_str()).or_insert_with(|| {
+            let mut tags = vec!["security".to_string()];
+            tags.extend(advisory.cwe.iter().cloned());
+            let mut help = format!("Vulnerable versions: {}.", advisory.vulnerable_versions);
+            if !advisory.url.is_empty() {
+                help.push_str(&format!(" See {}", advisory.url));
+            }
+            let mut rule = serde_json::json!({
+                "id": advisory.id,
+                "shortDescription": {"text": advisory.title},
+                "help": {"text": help},
+                "defaultConfiguration": {"level": sarif_level(advisory.severity)},
+                "properties": {
+                    "security-severity": security_severity(finding),
+                    "tags": tags
+                }
+            });
+            // Offline records may have no link; SARIF requires a valid URI.
+            if !advisory.url.is_empty() {
+                rule["helpUri"] = serde_json::json!(advisory.url);
+            }
+            rule
+        });
+    }
+
+    let results: Vec<serde_json::Value> = all_findings
+        .map(|(finding, ignore): (&Finding, Option<&Ignore>)| {
+            let locations: Vec<serde_json::Value> = sources
+                .locate(finding)
+                .into_iter()
+                .map(|(uri, line)| {
+                    let mut physical = serde_json::json!({"artifactLocation": {"uri": uri}});
+                    if let Some(line) = line {
+                        physical["region"] = serde_json::json!({"startLine": line});
+                    }
+                    serde_json::json!({"physicalLocation": physical})
+                })
+                .collect();
+            let mut result = serde_json::json!({
+                "ruleId": finding.advisory.id,
+                "level": sarif_level(finding.advisory.severity),
+                "message": {
+                    "text": format!(
+                        "{}@{} at {} is vulnerable: {} (affected versions {})",
+                        finding.name,
+                        finding.version,
+                        finding.location,
+                        finding.advisory.title,
+                        finding.advisory.vulnerable_versions
+                    )
+                },
+                "locations": locations,
+                "partialFingerprints": {"installedPackage": format!("{}@{}", finding.location, finding.version)}
+            });
+            if let Some(ignore) = ignore {
+                result["suppressions"] = serde_json::json!([{
+                    "kind": "external",
+                    "justification": format!("{} (until {})", ignore.justification, ignore.expires)
+                }]);
+            }
+            result
+        })
+        .collect();
+
+    let log = serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "rpm audit",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.into_values().collect::<Vec<_>>()
+                }
+            },
+            "results": results
+        }]
+    });
+    Ok(serde_json::to_string_pretty(&log)? + "\n")
+}
+
+/// A JUnit XML report with one test case per audited package, failing for
+/// vulnerable ones and skipped for those with only suppressed findings.
+pub fn render_junit(report: &AuditReport) -> String {
+    let mut by_location: HashMap<&str, Vec<&Finding>> = HashMap::new();
+    for finding in &report.findings {
+        by_location.entry(&finding.location).or_default().push(finding);
+    }
+    let mut suppressed_at: HashMap<&str, Vec<&Ignore>> = HashMap::new();
+    for suppressed in &report.suppressed {
+        if !by_location.contains_key(suppressed.finding.location.as_str()) {
+            suppressed_at.entry(&suppressed.finding.location).or_default().push(&suppressed.ignore);
+        }
+    }
+    let failures = vulnerable_packages(report);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"rpm audit\" tests=\"{}\" failures=\"{}\">\n",
+        report.audited.len(),
+        failures
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"rpm audit\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">\n",
+        report.audited.len(),
+        failures,
+        suppressed_at.len()
+    ));
+    for package in &report.audited {
+        let name = format!("{}@{}", package.name, package.version);
+        if let Some(ignores) = suppressed_at.get(package.location.as_str()) {
+            let message = ignores
+                .iter()
+                .map(|ignore| format!("{}: {}", ignore.id, ignore.justification))
+                .collect::<Vec<_>>()
+                .join("; ");
+            xml.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\">\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                escape_xml(&package.location),
+                escape_xml(&name),
+                escape_xml(&message)
+            ));
+            continue;
+        }
+        let Some(findings) = by_location.get(package.location.as_str()) else {
+            xml.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\"/>\n",
+                escape_xml(&package.location),
+                escape_xml(&name)
+            ));
+            continue;
+        };
+
+        let message = findings
+            .iter()
+            .map(|finding| format!("{} ({})", finding.advisory.title, finding.advisory.severity))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let details = findings
+            .iter()
+            .map(|finding| {
+                format!(
+                    "{} {}: {} (vulnerable versions {}) {}",
+                    finding.advisory.severity,
+                    finding.advisory.id,
+                    finding.advisory.title,
+                    finding.advisory.vulnerable_versions,
+                    finding.advisory.url
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        xml.push_str(&format!(
+            "    <testcase classname=\"{}\" name=\"{}\">\n      <failure type=\"vulnerability\" message=\"{}\">{}</failure>\n    </testcase>\n",
+            escape_xml(&package.location),
+            escape_xml(&name),
+            escape_xml(&message),
+            escape_xml(&details)
+        ));
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn vulnerable_packages(report: &AuditReport) -> usize {
+    report
+        .findings
+        .iter()
+        .map(|finding| finding.location.as_str())
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// GitHub code scanning ranks alerts by this score, falling back to `level`.
+fn security_severity(finding: &Finding) -> String {
+    if let Some(cvss) = &finding.advisory.cvss {
+        return format!("{:.1}", cvss.score);
+    }
+    match finding.advisory.severity {
+        // Unrated records include malware, which mustn't rank as harmless.
+        Severity::Critical | Severity::Unknown => "9.5",
+        Severity::High => "8.0",
+        Severity::Moderate => "5.5",
+        Severity::Low => "2.0",
+        Severity::Info => "0.0",
+    }
+    .to_string()
+}
+
+fn sarif_level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical | Severity::High | Severity::Unknown => "error",
+        Severity::Moderate => "warning",
+        Severity::Low | Severity::Info => "note",
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// A JSON file findings can point into, with the line of every key.
+struct SourceFile {
+    /// Relative to the project root, with forward slashes.
+    uri: String,
+    lines: HashMap<Vec<String>, usize>,
+}
+
+/// The manifests and lockfile of a project, for SARIF locations.
+pub struct SourceFiles {
+    manifests: Vec<SourceFile>,
+    lockfile: Option<SourceFile>,
+}
+
+impl SourceFiles {
+    /// Reads the root `package.json`, every member's and the lockfile if
+    /// there is one.
+    pub async fn load(root: &Path, workspace: Option<&Workspace>) -> Result<Self> {
+        let mut uris = vec!["package.json".to_string()];
+        if let Some(workspace) = workspace {
+            uris.extend(workspace.members.iter().map(|member| {
+                let relative = member.relative_path.to_string_lossy().replace('\\', "/");
+                format!("{}/package.json", relative)
+            }));
+        }
+
+        let mut manifests = Vec::new();
+        for uri in uris {
+            let content = tokio::fs::read_to_string(root.join(&uri)).await?;
+            manifests.push(SourceFile { lines: key_lines(&content), uri });
+        }
+
+        let lockfile = match tokio::fs::read_to_string(root.join(LOCKFILE_NAME)).await {
+            Ok(content) => Some(SourceFile { uri: LOCKFILE_NAME.to_string(), lines: key_lines(&content) }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self { manifests, lockfile })
+    }
+
+    /// Where `finding` shows up: the declaring lines of a direct dependency,
+    /// otherwise its lockfile entry, otherwise the root manifest as a whole.
+    fn locate(&self, finding: &Finding) -> Vec<(String, Option<usize>)> {
+        let path: Vec<String> = finding
+            .location
+            .strip_prefix("node_modules/")
+            .unwrap_or(&finding.location)
+            .split("/node_modules/")
+            .map(str::to_string)
+            .collect();
+
+        if let [name] = path.as_slice() {
+            let declared: Vec<(String, Option<usize>)> = self
+                .manifests
+                .iter()
+                .flat_map(|manifest| {
+                    MANIFEST_FIELDS.iter().filter_map(|field| {
+                        let line = manifest.lines.get(&vec![field.to_string(), name.clone()])?;
+                        Some((manifest.uri.clone(), Some(*line)))
+                    })
+                })
+                .collect();
+            if !declared.is_empty() {
+                return declared;
+            }
+        }
+
+        if let Some(lockfile) = &self.lockfile {
+            let key: Vec<String> = path
+                .iter()
+                .flat_map(|name| ["dependencies".to_string(), name.clone()])
+                .collect();
+            if let Some(line) = lockfile.lines.get(&key) {
+                return vec![(lockfile.uri.clone(), Some(*line))];
+            }
+        }
+
+        vec![("package.json".to_string(), None)]
+    }
+}
+
+/// The 1-based line of every object key in a JSON document, keyed by the
+/// chain of keys leading to it. Array elements aren't addressed.
+fn key_lines(content: &str) -> HashMap<Vec<String>, usize> {
+    let mut lines = HashMap::new();
+    // The key each open object or array was opened under.
+    let mut open: Vec<Option<String>> = Vec::new();
+    let mut key: Option<String> = None;
+    let mut string: Option<(String, usize)> = None;
+    let mut line = 1;
+
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '"' => {
+                let start = line;
+                let mut text = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => text.extend(chars.next()),
+                        '"' => break,
+                        c => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            text.push(c);
+                        }
+                    }
+                }
+                string = Some((text, start));
+            }
+            ':' => {
+                if let Some((text, at)) = string.take() {
+                    let mut path: Vec<String> = open.iter().flatten().cloned().collect();
+                    path.push(text.clone());
+                    lines.entry(path).or_insert(at);
+                    key = Some(text);
+                }
+            }
+            '{' | '[' => {
+                open.push(key.take());
+                string = None;
+            }
+            '}' | ']' => {
+                open.pop();
+                key = None;
+                string = None;
+            }
+            ',' => {
+                key = None;
+                string = None;
+            }
+            _ => {}
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::policy::Suppressed;
+    use crate::audit::AuditedPackage;
+    use crate::security::Advisory;
+
+    fn finding(name: &str, version: &str, location: &str, severity: Severity) -> Finding {
+        Finding {
+            name: name.to_string(),
+            version: version.to_string(),
+            location: location.to_string(),
+            advisory: Advisory {
+                id: format!("GHSA-{}", name),
+                url: format!("https://github.com/advisories/GHSA-{}", name),
+                title: format!("Prototype Pollution in {}", name),
+                severity,
+                vulnerable_versions: "<9.9.9".to_string(),
+                cwe: vec!["CWE-1321".to_string()],
+                cvss: None,
+            },
+        }
+    }
+
+    fn report() -> AuditReport {
+        let findings = vec![
+            finding("lodash", "4.17.20", "node_modules/lodash", Severity::High),
+            finding("minimist", "0.0.8", "node_modules/mkdirp/node_modules/minimist", Severity::Moderate),
+        ];
+        let mut audited: Vec<AuditedPackage> = findings
+            .iter()
+            .map(|finding| AuditedPackage {
+                name: finding.name.clone(),
+                version: finding.version.clone(),
+                location: finding.location.clone(),
+            })
+            .collect();
+        audited.push(AuditedPackage {
+            name: "mkdirp".to_string(),
+            version: "0.5.1".to_string(),
+            location: "node_modules/mkdirp".to_string(),
+        });
+        AuditReport { audited, findings, ..Default::default() }
+    }
+
+    #[test]
+    fn test_key_lines() {
+        let content = "{\n  \"name\": \"app\",\n  \"dependencies\": {\n    \"a\": {\n      \"version\": \"1.0.0\",\n      \"dependencies\": {\n        \"b\": {\"version\": \"2.0.0\"}\n      }\n    },\n    \"b\": {\"version\": \"1.0.0\", \"tags\": [\"x\", \"y\"]}\n  }\n}\n";
+        let lines = key_lines(content);
+        let key = |path: &[&str]| lines.get(&path.iter().map(|s| s.to_string()).collect::<Vec<_>>()).copied();
+
+        assert_eq!(key(&["name"]), Some(2));
+        assert_eq!(key(&["dependencies", "a"]), Some(4));
+        assert_eq!(key(&["dependencies", "a", "dependencies", "b"]), Some(7));
+        assert_eq!(key(&["dependencies", "b"]), Some(10));
+        assert_eq!(key(&["dependencies", "b", "tags"]), Some(10));
+        assert_eq!(key(&["x"]), None);
+    }
+
+    #[test]
+    fn test_render_sarif() {
+        let manifest = "{\n  \"name\": \"app\",\n  \"version\": \"1.0.0\",\n  \"dependencies\": {\n    \"lodash\": \"^4.17.0\",\n    \"mkdirp\": \"^0.5.0\"\n  }\n}\n";
+        let lockfile = "{\n  \"dependencies\": {\n    \"mkdirp\": {\n      \"version\": \"0.5.1\",\n      \"dependencies\": {\n        \"minimist\": {\n          \"version\": \"0.0.8\"\n        }\n      }\n    }\n  }\n}\n";
+        let sources = SourceFiles {
+            manifests: vec![SourceFile { uri: "package.json".to_string(), lines: key_lines(manifest) }],
+            lockfile: Some(SourceFile { uri: LOCKFILE_NAME.to_string(), lines: key_lines(lockfile) }),
+        };
+
+        let sarif: serde_json::Value = serde_json::from_str(&render_sarif(&report(), &sources).unwrap()).unwrap();
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), 2);
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results[0]["level"], "error");
+        let direct = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(direct["artifactLocation"]["uri"], "package.json");
+        assert_eq!(direct["region"]["startLine"], 5);
+        let nested = &results[1]["locations"][0]["physicalLocation"];
+        assert_eq!(nested["artifactLocation"]["uri"], "rpm-lock.json");
+        assert_eq!(nested["region"]["startLine"], 6);
+
+        let mut report = report();
+        report.findings[1].advisory.url = String::new();
+        let sarif: serde_json::Value = serde_json::from_str(&render_sarif(&report, &sources).unwrap()).unwrap();
+        let rules = sarif["runs"][0]["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules[0]["helpUri"], "https://github.com/advisories/GHSA-lodash");
+        assert!(rules[1].get("helpUri").is_none());
+        assert_eq!(rules[1]["help"]["text"], "Vulnerable versions: <9.9.9.");
+    }
+
+    #[test]
+    fn test_render_json_and_junit() {
+        let report = report();
+
+        let json: serde_json::Value = serde_json::from_str(&render_json(&report).unwrap()).unwrap();
+        assert_eq!(json["schemaVersion"], JSON_SCHEMA_VERSION);
+        assert_eq!(json["summary"]["vulnerablePackages"], 2);
+        assert_eq!(
+            json["summary"]["severities"],
+            serde_json::json!({"critical": 0, "high": 1, "moderate": 1, "low": 0, "info": 0, "unknown": 0})
+        );
+        assert_eq!(json["findings"][0]["severity"], "high");
+        assert_eq!(json["findings"][1]["location"], "node_modules/mkdirp/node_modules/minimist");
+        assert_eq!(json["findings"][0]["vulnerableVersions"], "<9.9.9");
+
+        assert!(render_table(&report).ends_with("2 vulnerabilities (1 high, 1 moderate) in 2 of 3 packages\n"));
+
+        let junit = render_junit(&report);
+        assert!(junit.contains("<testsuite name=\"rpm audit\" tests=\"3\" failures=\"2\" skipped=\"0\">"));
+        assert!(junit.contains("<testcase classname=\"node_modules/mkdirp\" name=\"mkdirp@0.5.1\"/>"));
+        assert!(junit.contains("<failure type=\"vulnerability\" message=\"Prototype Pollution in lodash (high)\">"));
+    }
+
+    #[test]
+    fn test_render_suppressed() {
+        let mut report = report();
+        let ignore = Ignore {
+            id: "GHSA-lodash".to_string(),
+            paths: Vec::new(),
+            justification: "Only called with our own objects".to_string(),
+            expires: "2099-01-01".to_string(),
+        };
+        let finding = report.findings.remove(0);
+        report.suppressed.push(Suppressed { finding, ignore });
+
+        let json: serde_json::Value = serde_json::from_str(&render_json(&report).unwrap()).unwrap();
+        assert_eq!(json["summary"]["vulnerabilities"], 1);
+        assert_eq!(json["summary"]["suppressed"], 1);
+        assert_eq!(json["suppressed"][0]["package"], "lodash");
+        assert_eq!(json["suppressed"][0]["justification"], "Only called with our own objects");
+
+        let table = render_table(&report);
+        assert!(table.contains("1 suppressed by .rpm-audit.json:"));
+
+        let sources = SourceFiles { manifests: Vec::new(), lockfile: None };
+        let sarif: serde_json::Value = serde_json::from_str(&render_sarif(&report, &sources).unwrap()).unwrap();
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1]["suppressions"][0]["kind"], "external");
+
+        let junit = render_junit(&report);
+        assert!(junit.contains("failures=\"1\" skipped=\"1\""));
+        assert!(junit.contains("<skipped message=\"GHSA-lodash: Only called with our own objects\"/>"));
+    }
+}
@@ -0,0 +1,178 @@
+use super::registry_copies;
+use crate::lockfile::LockFile;
+use crate::registry::RegistryClient;
+use crate::table;
+use crate::tree::location;
+use crate::verification::{RegistryKeys, SignatureStatus};
+use anyhow::Result;
+use futures::future::try_join_all;
+
+pub const SIGNATURE_HEADERS: [&str; 4] = ["Package", "Version", "Location", "Problem"];
+
+/// An installed copy whose registry signature is missing or doesn't verify.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignatureProblem {
+    pub name: String,
+    pub version: String,
+    pub location: String,
+    pub reason: String,
+}
+
+/// What `rpm audit signatures` found, problems sorted by location.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureReport {
+    pub verified: usize,
+    pub unsigned: Vec<SignatureProblem>,
+    pub invalid: Vec<SignatureProblem>,
+}
+
+impl SignatureReport {
+    pub fn fails(&self) -> bool {
+        !self.unsigned.is_empty() || !self.invalid.is_empty()
+    }
+}
+
+/// Verifies the registry signature of every installed copy of a registry
+/// package against the metadata the registry serves for it now.
+pub async fn audit_signatures(
+    registry: &RegistryClient,
+    keys: &RegistryKeys,
+    installed: &LockFile,
+) -> Result<SignatureReport> {
+    let copies = registry_copies(installed);
+    let packuments = try_join_all(copies.iter().map(|(_, package, _)| registry.fetch_packument(package))).await?;
+
+    let mut report = SignatureReport::default();
+    for ((path, package, version), packument) in copies.iter().zip(packuments) {
+        let problem = |reason: String| SignatureProblem {
+            name: package.clone(),
+            version: version.to_string(),
+            location: location(path),
+            reason,
+        };
+        let Some(metadata) = packument.versions.get(version) else {
+            report.invalid.push(problem("not published to the registry".to_string()));
+            continue;
+        };
+        match keys.verify_published(registry, metadata).await? {
+            SignatureStatus::Verified { .. } => report.verified += 1,
+            SignatureStatus::Unsigned => report.unsigned.push(problem("missing signature".to_string())),
+            SignatureStatus::Invalid(reason) => report.invalid.push(problem(reason)),
+        }
+    }
+
+    report.unsigned.sort_by(|a, b| a.location.cmp(&b.location));
+    report.invalid.sort_by(|a, b| a.location.cmp(&b.location));
+    Ok(report)
+}
+
+/// A summary line per outcome, then the packages with a problem.
+pub fn render_signatures(report: &SignatureReport) -> String {
+    let mut output = format!("{} packages have verified registry signatures\n", report.verified);
+    if !report.unsigned.is_empty() {
+        output.push_str(&format!("{} packages have missing registry signatures\n", report.unsigned.len()));
+    }
+    if !report.invalid.is_empty() {
+        output.push_str(&format!("{} packages have invalid registry signatures\n", report.invalid.len()));
+    }
+
+    let rows: Vec<Vec<String>> = report
+        .invalid
+        .iter()
+        .chain(&report.unsigned)
+        .map(|problem| {
+            vec![problem.name.clone(), problem.version.clone(), problem.location.clone(), problem.reason.clone()]
+        })
+        .collect();
+    if !rows.is_empty() {
+        output.push_str(&format!("\n{}", table::render(&SIGNATURE_HEADERS, &rows)));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::mock::MockRegistry;
+    use crate::testing::{locked, lockfile};
+    use crate::verification::KEYS_PATH;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+    use p256::pkcs8::EncodePublicKey;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn version(name: &str, version: &str, signatures: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "name": name,
+            "version": version,
+            "dist": {
+                "tarball": format!("https://registry.example/{}/-/{}.tgz", name, version),
+                "shasum": "0000",
+                "integrity": "sha512-deadbeef",
+                "signatures": signatures
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_audit_signatures() {
+        let key = SigningKey::from_slice(&[7; 32]).unwrap();
+        let sign = |message: &str| {
+            let signature: Signature = key.sign(message.as_bytes());
+            serde_json::json!([{"keyid": "SHA256:test", "sig": STANDARD.encode(signature.to_der().as_bytes())}])
+        };
+        let keys = serde_json::json!({
+            "keys": [{
+                "keyid": "SHA256:test",
+                "keytype": "ecdsa-sha2-nistp256",
+                "scheme": "ecdsa-sha2-nistp256",
+                "key": STANDARD.encode(key.verifying_key().to_public_key_der().unwrap().as_bytes())
+            }]
+        });
+        let minimist = serde_json::json!({
+            "name": "minimist",
+            "versions": {
+                "1.2.8": version("minimist", "1.2.8", sign("minimist@1.2.8:sha512-deadbeef")),
+                // Signed over different metadata than what's served.
+                "1.2.6": version("minimist", "1.2.6", sign("minimist@1.2.6:sha512-cafebabe"))
+            }
+        });
+        let left_pad = serde_json::json!({
+            "name": "left-pad",
+            "versions": {"1.3.0": version("left-pad", "1.3.0", serde_json::json!([]))}
+        });
+        let documents = HashMap::from([
+            (KEYS_PATH.to_string(), keys.to_string()),
+            ("/minimist".to_string(), minimist.to_string()),
+            ("/left-pad".to_string(), left_pad.to_string()),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let client = RegistryClient::with_url(registry.url.clone());
+
+        let cache = tempfile::tempdir().unwrap();
+        let keys = RegistryKeys::load(&client, cache.path()).await.unwrap().unwrap();
+        assert_eq!(keys.keys.len(), 1);
+        // Cached keys are used without asking the registry again.
+        let cached = RegistryKeys::load(&client, cache.path()).await.unwrap().unwrap();
+        assert_eq!(cached.keys[0].keyid, "SHA256:test");
+        assert_eq!(registry.hits(KEYS_PATH), 1);
+
+        let mut nested = locked("1.3.0");
+        nested["dependencies"] = serde_json::json!({"minimist": locked("1.2.6")});
+        let installed = lockfile(serde_json::json!({"minimist": locked("1.2.8"), "left-pad": nested}));
+
+        let report = audit_signatures(&client, &keys, &installed).await.unwrap();
+        assert_eq!(report.verified, 1);
+        assert_eq!(report.unsigned.len(), 1);
+        assert_eq!(report.unsigned[0].location, "node_modules/left-pad");
+        assert_eq!(report.invalid.len(), 1);
+        assert_eq!(report.invalid[0].location, "node_modules/left-pad/node_modules/minimist");
+        assert_eq!(report.invalid[0].reason, "signature doesn't match key SHA256:test");
+        assert!(report.fails());
+
+        let rendered = render_signatures(&report);
+        assert!(rendered.starts_with("1 packages have verified registry signatures\n"));
+        assert!(rendered.contains("1 packages have invalid registry signatures"));
+    }
+}
@@ -1,11 +1,11 @@
 use crate::error::{RpmError, RpmResult};
-use async_trait::async_trait;
 use sha2::{Sha256, Digest};
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use log::{debug, info, warn};
 use std::time::{SystemTime, Duration};
 
+#[derive(Clone)]
 pub struct CacheConfig {
     pub cache_dir: PathBuf,
     pub max_size: u64,        // Maximum cache size in bytes
@@ -28,9 +28,9 @@ impl Default for CacheConfig {
     }
 }
 
+#[derive(Clone)]
 pub struct PackageCache {
     config: CacheConfig,
-    last_cleanup: SystemTime,
 }
 
 impl PackageCache {
@@ -38,10 +38,7 @@ impl PackageCache {
         fs::create_dir_all(&config.cache_dir).await
             .map_err(|e| RpmError::CacheError(format!("Failed to create cache directory: {}", e)))?;
 
-        let cache = Self {
-            config,
-            last_cleanup: SystemTime::now(),
-        };
+        let cache = Self { config };
 
         cache.init_cleanup_task();
         Ok(cache)
@@ -59,6 +56,10 @@ impl PackageCache {
         });
     }
 
+    pub fn cache_dir(&self) -> &Path {
+        &self.config.cache_dir
+    }
+
     pub async fn get(&self, package: &str, version: &str) -> RpmResult<Option<PathBuf>> {
         let cache_key = self.generate_cache_key(package, version);
         let cache_path = self.config.cache_dir.join(cache_key);
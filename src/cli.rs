@@ -1,11 +1,39 @@
 use crate::{
     AppContext,
-    error::RpmResult,
+    audit::{
+        audit,
+        fix::{plan_fixes, PlannedFix, FIX_HEADERS},
+        policy::{today, AuditPolicy},
+        report::{render_json, render_junit, render_sarif, render_table, SourceFiles},
+        signatures::{audit_signatures, render_signatures},
+        AdvisorySource, AuditReport, EXIT_FAILED, EXIT_VULNERABLE,
+    },
+    dedupe::{apply_dedupe, plan_dedupe, reclaimable_bytes},
+    error::{RpmResult, WorkspaceError},
+    filter::{apply_filters, WorkspaceFilter},
     install::PackageInstaller,
+    lockfile::{LockFile, LOCKFILE_NAME},
+    osv::OsvDatabase,
+    outdated::{find_outdated, OUTDATED_HEADERS},
     package::PackageJson,
+    prune::prune,
+    registry::RegistryClient,
+    scripts::ScriptRunner,
+    security::{SecurityChecker, Severity},
+    table,
+    tree::{importers, EdgeKind, InstalledTree, ListOptions, Omit},
+    update::{apply_updates, plan_updates, PlannedUpdate, UpdatePolicy, UPDATE_HEADERS},
+    upgrade::{choose_upgrades, upgrade_candidates},
+    verification::RegistryKeys,
+    workspace::Workspace,
 };
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use indicatif::HumanBytes;
 use log::{debug, info};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::fs;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -15,6 +43,19 @@ pub struct Cli {
 
     #[arg(short, long, global = true)]
     pub verbose: bool,
+
+    /// Run the command in the given workspace member (repeatable)
+    #[arg(short = 'w', long = "workspace", value_name = "NAME", global = true)]
+    pub workspace: Vec<String>,
+
+    /// Run the command in every workspace member
+    #[arg(long, global = true, conflicts_with = "workspace")]
+    pub workspaces: bool,
+
+    /// Use this directory as the project root instead of looking for one
+    /// above the working directory
+    #[arg(long, value_name = "DIR", global = true)]
+    pub prefix: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -23,114 +64,415 @@ pub enum Commands {
         packages: Vec<String>,
         #[arg(short, long)]
         global: bool,
+        /// Dependency types to leave out; defaults to dev when NODE_ENV=production
+        #[arg(long, value_enum, value_delimiter = ',')]
+        omit: Vec<DependencyGroup>,
+        /// Dependency types to install even if omitted
+        #[arg(long, value_enum, value_delimiter = ',')]
+        include: Vec<DependencyGroup>,
+        /// Resolve each range to its newest version without known advisories
+        #[arg(long)]
+        prefer_safe: bool,
+        /// Fail when packages from these scopes, e.g. @myorg, have no
+        /// verified provenance attestation
+        #[arg(long, value_delimiter = ',', value_parser = parse_scope)]
+        require_provenance: Vec<String>,
+        /// Install packages without registry signatures, or when the
+        /// registry's keys can't be fetched
+        #[arg(long)]
+        skip_signature_verification: bool,
     },
+    /// Update dependencies, within their declared ranges unless told otherwise
     Update {
         packages: Vec<String>,
+        /// Move to the `latest` dist-tag, rewriting ranges that exclude it
+        #[arg(long, group = "policy")]
+        latest: bool,
+        /// Allow at most patch-level bumps from the installed version
+        #[arg(long, group = "policy")]
+        patch: bool,
+        /// Allow at most minor-level bumps from the installed version
+        #[arg(long, group = "policy")]
+        minor: bool,
     },
+    /// Pick outdated dependencies to upgrade from a checklist
+    UpgradeInteractive,
     Remove {
         packages: Vec<String>,
         #[arg(short, long)]
         global: bool,
     },
+    /// Show the installed dependency tree
     List {
         #[arg(short, long)]
         global: bool,
+        /// Levels of transitive dependencies to show
+        #[arg(long)]
+        depth: Option<usize>,
+        /// Only dependencies, optionalDependencies and peerDependencies
+        #[arg(long, conflicts_with = "dev")]
+        prod: bool,
+        /// Only devDependencies
+        #[arg(long)]
+        dev: bool,
+        /// Print a JSON array with one tree per project or workspace member
+        #[arg(long, conflicts_with = "parseable")]
+        json: bool,
+        /// Print the directory of each installed package, one per line
+        #[arg(long)]
+        parseable: bool,
     },
+    /// Check installed packages for known vulnerabilities
     Audit {
+        /// Upgrade or override dependencies to get rid of vulnerable copies
         #[arg(long)]
         fix: bool,
+        /// With --fix, also apply fixes crossing a semver-major version
+        #[arg(long, requires = "fix")]
+        force: bool,
+        /// With --fix, only print the planned fixes
+        #[arg(long, requires = "fix")]
+        dry_run: bool,
+        /// How to print the report
+        #[arg(long, value_enum, default_value_t = AuditFormat::Table)]
+        format: AuditFormat,
+        /// Only exit with a failure for findings at least this severe
+        #[arg(long, value_enum)]
+        audit_level: Option<AuditLevel>,
+        /// Only use the imported offline advisory database
+        #[arg(long, conflicts_with = "registry")]
+        offline: bool,
+        /// Query the registry even if an offline database has been imported
+        #[arg(long)]
+        registry: bool,
+        #[command(subcommand)]
+        command: Option<AuditCommand>,
+    },
+    /// Run a package.json script, optionally across workspace members
+    Run {
+        script: String,
+        /// Run in every selected workspace member, dependencies first
+        #[arg(short, long)]
+        recursive: bool,
+        /// Select members by name glob, `[git-ref]`, `name...` or `...name`
+        #[arg(long = "filter", value_name = "SELECTOR")]
+        filter: Vec<WorkspaceFilter>,
+        /// Maximum number of scripts running at once
+        #[arg(long, default_value_t = 4)]
+        concurrency: usize,
+    },
+    /// Show dependencies with newer versions available
+    Outdated {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show every dependency path leading to an installed package
+    Why {
+        package: String,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Remove duplicate copies of packages one installed version can serve
+    Dedupe {
+        /// Only report duplicates, exiting with status 1 if any could go
+        #[arg(long)]
+        check: bool,
+    },
+    /// Remove installed packages that nothing depends on
+    Prune {
+        /// Also remove packages only needed through these dependency types;
+        /// defaults to dev when NODE_ENV=production
+        #[arg(long, value_enum, value_delimiter = ',')]
+        omit: Vec<DependencyGroup>,
+        /// Dependency types to keep even if omitted
+        #[arg(long, value_enum, value_delimiter = ',')]
+        include: Vec<DependencyGroup>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommand {
+    /// Manage the offline advisory database
+    Db {
+        #[command(subcommand)]
+        command: AuditDbCommand,
     },
+    /// Verify the registry signatures of installed packages
+    Signatures,
+}
+
+#[derive(Subcommand)]
+pub enum AuditDbCommand {
+    /// Import a directory or zip of OSV records for npm; audits use it
+    /// instead of the registry from then on
+    Import { path: PathBuf },
+}
+
+/// Output formats of `rpm audit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AuditFormat {
+    Table,
+    /// The schema in docs/audit-report.schema.json
+    Json,
+    /// SARIF 2.1.0, for code scanning
+    Sarif,
+    /// JUnit XML, one test case per package
+    Junit,
+}
+
+/// Thresholds for `rpm audit --audit-level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AuditLevel {
+    Low,
+    Moderate,
+    High,
+    Critical,
+}
+
+impl From<AuditLevel> for Severity {
+    fn from(level: AuditLevel) -> Self {
+        match level {
+            AuditLevel::Low => Severity::Low,
+            AuditLevel::Moderate => Severity::Moderate,
+            AuditLevel::High => Severity::High,
+            AuditLevel::Critical => Severity::Critical,
+        }
+    }
+}
+
+/// Dependency types that can be left out of `node_modules`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DependencyGroup {
+    Dev,
+    Optional,
+    Peer,
+}
+
+impl From<DependencyGroup> for EdgeKind {
+    fn from(group: DependencyGroup) -> Self {
+        match group {
+            DependencyGroup::Dev => EdgeKind::Dev,
+            DependencyGroup::Optional => EdgeKind::Optional,
+            DependencyGroup::Peer => EdgeKind::Peer,
+        }
+    }
+}
+
+fn omit_from_flags(omit: Vec<DependencyGroup>, include: Vec<DependencyGroup>) -> Omit {
+    let omit: Vec<EdgeKind> = omit.into_iter().map(EdgeKind::from).collect();
+    let include: Vec<EdgeKind> = include.into_iter().map(EdgeKind::from).collect();
+    Omit::from_env(&omit, &include)
+}
+
+/// `@myorg` or `@myorg/`, as accepted by `--require-provenance`.
+fn parse_scope(scope: &str) -> Result<String, String> {
+    let scope = scope.trim_end_matches('/');
+    if !scope.starts_with('@') || scope.len() < 2 || scope.contains('/') {
+        return Err(format!("{:?} is not a scope such as @myorg", scope));
+    }
+    Ok(scope.to_string())
 }
 
 impl Cli {
-    pub async fn execute(self, context: AppContext) -> RpmResult<()> {
+    /// Whether the command works on the global packages rather than a
+    /// project.
+    pub fn is_global(&self) -> bool {
+        matches!(
+            self.command,
+            Commands::Install { global: true, .. }
+                | Commands::Remove { global: true, .. }
+                | Commands::List { global: true, .. }
+        )
+    }
+
+    pub async fn execute(mut self, context: AppContext) -> RpmResult<ExitCode> {
+        // Started inside a workspace member, commands apply to that member
+        // unless told otherwise, like npm.
+        if let Some(member) = &context.project.member {
+            if self.workspace.is_empty() && !self.workspaces {
+                self.workspace.push(member.clone());
+            }
+        }
+
         match self.command {
-            Commands::Install { packages, global } => {
+            Commands::Install {
+                packages,
+                global,
+                omit,
+                include,
+                prefer_safe,
+                require_provenance,
+                skip_signature_verification,
+            } => {
                 debug!("Installing packages: {:?}", packages);
-                let installer = PackageInstaller::new(
+                let database = OsvDatabase::new(context.package_cache.cache_dir().join("osv"));
+                let mut installer = PackageInstaller::new(
                     global,
+                    &context.project.root,
                     context.package_cache,
                     context.memory_profile,
-                );
-                installer.install_packages(&packages).await?;
-                info!("Successfully installed packages: {:?}", packages);
-            }
-            Commands::Update { packages } => {
-                let package_json = PackageJson::load().await?;
-                let registry = Arc::new(RegistryClient::new());
-                let resolver = DependencyResolver::new(registry);
-
-                match packages {
-                    Some(packages) => {
-                        for package in packages {
-                            if let Some(deps) = &package_json.dependencies {
-                                if let Some(current_version) = deps.get(&package) {
-                                    println!(
-                                        "Updating {} from version {}",
-                                        package, current_version
-                                    );
-
-                                    let latest = resolver
-                                        .resolve_single_dependency(
-                                            &package,
-                                            &semver::VersionReq::parse("*").unwrap(),
-                                        )
-                                        .await?;
-
-                                    if latest.version
-                                        > semver::Version::parse(current_version).unwrap()
-                                    {
-                                        let package_name = package.clone();
-                                        let installer = PackageInstaller::new(false);
-                                        installer.install_packages(&[package_name]).await?;
-                                        println!(
-                                            "Updated {} to version {}",
-                                            package, latest.version
-                                        );
-                                    } else {
-                                        println!("{} is already at the latest version", package);
-                                    }
-                                }
-                            }
-                        }
+                )
+                .with_omit(omit_from_flags(omit, include))
+                .with_required_provenance(require_provenance)
+                .with_signature_verification(!skip_signature_verification);
+                if prefer_safe {
+                    installer = installer.with_advisories(AdvisorySource::detect(database).await?);
+                }
+
+                if global {
+                    installer.install_packages(&packages).await?;
+                    info!("Successfully installed packages: {:?}", packages);
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                let root = context.project.root.clone();
+                let workspace = Workspace::discover(&root).await?.map(Arc::new);
+                if workspace.is_none() && (self.workspaces || !self.workspace.is_empty()) {
+                    return Err(WorkspaceError::NotAWorkspace.into());
+                }
+
+                match (&workspace, packages.is_empty()) {
+                    (Some(workspace), true) => {
+                        let selected = workspace.select(&self.workspace, self.workspaces)?;
+                        let manifests = workspace.manifests(&selected);
+                        installer
+                            .install_project(&workspace.root, &manifests, Some(Arc::clone(workspace)))
+                            .await?;
+                    }
+                    (None, true) => {
+                        let package_json = PackageJson::load(&root).await?;
+                        installer
+                            .install_project(&root, &[(root.as_path(), &package_json)], None)
+                            .await?;
                     }
+                    (Some(workspace), false) => {
+                        let selected = workspace.select(&self.workspace, self.workspaces)?;
+                        let installed = installer.install_packages(&packages).await?;
+                        workspace.link_members().await?;
 
-                    None => {
-                        if let Some(deps) = &package_json.dependencies {
-                            for (package, current_version) in deps {
-                                println!(
-                                    "Checking updates for {} (current: {})",
-                                    package, current_version
-                                );
-
-                                let latest = resolver
-                                    .resolve_single_dependency(
-                                        package,
-                                        &semver::VersionReq::parse("*").unwrap(),
-                                    )
-                                    .await?;
-
-                                if latest.version > semver::Version::parse(current_version).unwrap()
-                                {
-                                    let package_name = package.clone();
-                                    let installer = PackageInstaller::new(false);
-                                    installer.install_packages(&[package_name]).await?;
-                                    println!("Updated {} to version {}", package, latest.version);
-                                } else {
-                                    println!("{} is already at the latest version", package);
-                                }
+                        // Like npm, `install <pkg> -w <name>` records the
+                        // dependency in the member's manifest.
+                        for member in selected {
+                            let mut manifest = member.manifest.clone();
+                            for package in &installed {
+                                manifest.add_dependency(&package.name, &format!("^{}", package.version), false);
                             }
+                            manifest.save_to(member.path.join("package.json")).await?;
                         }
+                        info!("Successfully installed packages: {:?}", packages);
+                    }
+                    (None, false) => {
+                        installer.install_packages(&packages).await?;
+                        info!("Successfully installed packages: {:?}", packages);
                     }
                 }
             }
+            Commands::Update { packages, latest, patch, minor } => {
+                let policy = if latest {
+                    UpdatePolicy::Latest
+                } else if minor {
+                    UpdatePolicy::Minor
+                } else if patch {
+                    UpdatePolicy::Patch
+                } else {
+                    UpdatePolicy::Range
+                };
+
+                let root = context.project.root.clone();
+                let mut package_json = PackageJson::load(&root).await?;
+                let workspace = Workspace::discover(&root).await?;
+                let installed = LockFile::from_node_modules(&package_json, &root).await?;
+
+                let mut importers = importers(&package_json, workspace.as_ref());
+                if self.workspaces || !self.workspace.is_empty() {
+                    let workspace = workspace.as_ref().ok_or(WorkspaceError::NotAWorkspace)?;
+                    let selected = workspace.select(&self.workspace, self.workspaces)?;
+                    importers.retain(|importer| selected.iter().any(|member| member.name == importer.name));
+                }
+
+                let registry = RegistryClient::new();
+                let updates =
+                    plan_updates(&registry, &importers, &installed, workspace.as_ref(), policy, &packages).await?;
+                if updates.is_empty() {
+                    println!("All dependencies are up to date");
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                write_and_reinstall(&root, &mut package_json, workspace.as_ref(), &updates, context).await?;
+
+                let rows: Vec<Vec<String>> = updates.iter().map(|update| update.row()).collect();
+                print!("{}", table::render(&UPDATE_HEADERS, &rows));
+            }
+            Commands::UpgradeInteractive => {
+                let root = context.project.root.clone();
+                let mut package_json = PackageJson::load(&root).await?;
+                let workspace = Workspace::discover(&root).await?;
+                let installed = LockFile::from_node_modules(&package_json, &root).await?;
+
+                let mut importers = importers(&package_json, workspace.as_ref());
+                if self.workspaces || !self.workspace.is_empty() {
+                    let workspace = workspace.as_ref().ok_or(WorkspaceError::NotAWorkspace)?;
+                    let selected = workspace.select(&self.workspace, self.workspaces)?;
+                    importers.retain(|importer| selected.iter().any(|member| member.name == importer.name));
+                }
+
+                let registry = RegistryClient::new();
+                let outdated = find_outdated(&registry, &importers, &installed, workspace.as_ref()).await?;
+                let candidates = upgrade_candidates(&registry, outdated).await;
+                if candidates.is_empty() {
+                    println!("All dependencies are up to date");
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                let Some(chosen) = choose_upgrades(&candidates)? else {
+                    println!("Upgrade cancelled");
+                    return Ok(ExitCode::SUCCESS);
+                };
+                if chosen.is_empty() {
+                    println!("Nothing selected");
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                let updates: Vec<PlannedUpdate> = chosen.iter().map(|candidate| candidate.planned()).collect();
+                write_and_reinstall(&root, &mut package_json, workspace.as_ref(), &updates, context).await?;
+
+                let rows: Vec<Vec<String>> = updates.iter().map(|update| update.row()).collect();
+                print!("{}", table::render(&UPDATE_HEADERS, &rows));
+            }
             Commands::Remove { packages, global } => {
+                let root = context.project.root.clone();
                 let base_path = if global {
                     PathBuf::from("/usr/local/lib/node_modules")
                 } else {
-                    PathBuf::from("node_modules")
+                    root.join("node_modules")
                 };
 
+                if !global && (self.workspaces || !self.workspace.is_empty()) {
+                    let workspace = Workspace::discover(&root)
+                        .await?
+                        .ok_or(WorkspaceError::NotAWorkspace)?;
+
+                    // Hoisted packages may still be used by other members, so
+                    // only the selected manifests are edited.
+                    for member in workspace.select(&self.workspace, self.workspaces)? {
+                        let mut manifest = member.manifest.clone();
+                        for package in &packages {
+                            manifest.remove_dependency(package);
+                        }
+                        manifest.save_to(member.path.join("package.json")).await?;
+                        println!("Removed {:?} from workspace {}", packages, member.name);
+                    }
+
+                    // Whatever no member needs any more goes too.
+                    let workspace = Workspace::discover(&root).await?;
+                    let package_json = PackageJson::load(&root).await?;
+                    let pruned = prune(&root, &package_json, workspace.as_ref(), &[]).await?;
+                    if !pruned.is_empty() {
+                        println!("Pruned {} extraneous packages", pruned.len());
+                    }
+                    return Ok(ExitCode::SUCCESS);
+                }
+
                 for package in packages {
                     let package_path = base_path.join(&package);
                     if package_path.exists() {
@@ -138,36 +480,65 @@ impl Cli {
                         println!("Successfully removed package: {}", package);
 
                         if !global {
-                            if let Ok(mut package_json) = PackageJson::load().await {
-                                package_json.remove_dependency(&package);
-                                package_json.save().await?;
-                            }
+                            let mut package_json = PackageJson::load(&root).await?;
+                            package_json.remove_dependency(&package);
+                            package_json.save(&root).await?;
                         }
                     } else {
                         println!("Package not found: {}", package);
                     }
                 }
+
+                // Transitive dependencies of the removed packages are only
+                // deleted once nothing else needs them.
+                if !global {
+                    let package_json = PackageJson::load(&root).await?;
+                    let workspace = Workspace::discover(&root).await?;
+                    let pruned = prune(&root, &package_json, workspace.as_ref(), &[]).await?;
+                    if !pruned.is_empty() {
+                        println!("Pruned {} extraneous packages", pruned.len());
+                    }
+                }
             }
-            Commands::List => {
-                println!("Installed packages:");
-                
-                // Read package.json for local packages
-                if let Ok(package_json) = PackageJson::load().await {
-                    println!("\nLocal packages:");
-                    if let Some(deps) = &package_json.dependencies {
-                        for (name, version) in deps {
-                            println!("  {} @ {}", name, version);
-                        }
+            Commands::List { global, depth, prod, dev, json, parseable } => {
+                if !global {
+                    let root = context.project.root.clone();
+                    let package_json = PackageJson::load(&root).await?;
+                    let workspace = Workspace::discover(&root).await?;
+                    let installed = LockFile::from_node_modules(&package_json, &root).await?;
+
+                    let tree = InstalledTree::new(&installed, importers(&package_json, workspace.as_ref()));
+                    let mut trees = tree.list(&ListOptions { depth, prod, dev });
+
+                    if self.workspaces || !self.workspace.is_empty() {
+                        let workspace = workspace.as_ref().ok_or(WorkspaceError::NotAWorkspace)?;
+                        let selected: Vec<&str> = workspace
+                            .select(&self.workspace, self.workspaces)?
+                            .iter()
+                            .map(|member| member.name.as_str())
+                            .collect();
+                        trees.retain(|tree| selected.contains(&tree.name.as_str()));
                     }
-                    
-                    if let Some(dev_deps) = &package_json.dev_dependencies {
-                        println!("\nDev dependencies:");
-                        for (name, version) in dev_deps {
-                            println!("  {} @ {}", name, version);
+
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&trees)?);
+                    } else if parseable {
+                        println!("{}", root.display());
+                        for tree in &trees {
+                            for location in tree.locations() {
+                                println!("{}", root.join(location).display());
+                            }
+                        }
+                    } else {
+                        for tree in &trees {
+                            print!("{}", tree);
                         }
                     }
+                    return Ok(ExitCode::SUCCESS);
                 }
-                
+
+                println!("Installed packages:");
+
                 // List global packages
                 let global_dir = PathBuf::from("/usr/local/lib/node_modules");
                 if global_dir.exists() {
@@ -184,72 +555,340 @@ impl Cli {
                     }
                 }
             }
-            Commands::Audit { fix } => {
-                println!("Auditing packages for security vulnerabilities...");
-                
-                let mut package_json = PackageJson::load().await?;
-                let mut security_checker = SecurityChecker::new();
-                let mut vulnerabilities_found = false;
-                let mut fixes_applied = false;
-
-                if let Some(deps) = &mut package_json.dependencies {
-                    let mut updates = Vec::new();
-
-                    for (name, version_str) in deps.iter() {
-                        let version = Version::parse(version_str)?;
-                        let vulns = security_checker.check_package(name, &version).await?;
-
-                        if !vulns.is_empty() {
-                            vulnerabilities_found = true;
-                            println!("\nVulnerabilities found in {}", name);
-                            
-                            for vuln in &vulns {
-                                println!("\nID: {}", vuln.id);
-                                println!("Title: {}", vuln.title);
-                                println!("Severity: {}", vuln.severity);
-                                println!("Description: {}", vuln.description);
-                                
-                                if let Some(patched) = &vuln.patched_version {
-                                    println!("Patched version: {}", patched);
-                                }
-                            }
+            Commands::Audit { fix, force, dry_run, format, audit_level, offline, registry, command } => {
+                let database = OsvDatabase::new(context.package_cache.cache_dir().join("osv"));
+                if let Some(AuditCommand::Db { command: AuditDbCommand::Import { path } }) = command {
+                    let summary = match database.import(&path).await {
+                        Ok(summary) => summary,
+                        Err(e) => {
+                            eprintln!("Error: advisory database import failed: {:#}", e);
+                            return Ok(ExitCode::from(EXIT_FAILED));
+                        }
+                    };
+                    println!(
+                        "Imported {} advisories affecting {} npm packages from {}",
+                        summary.records,
+                        summary.packages,
+                        summary.source.display()
+                    );
+                    return Ok(ExitCode::SUCCESS);
+                }
 
-                            if fix {
-                                // Get all available versions from registry
-                                let registry = Arc::new(RegistryClient::new());
-                                let package_info = registry.fetch_package_info(name, None).await?;
-                                let available_versions = vec![package_info.version];  // Simplified for now
-
-                                if let Ok(safe_version) = security_checker
-                                    .find_safe_version(name, &version, &available_versions)
-                                    .await
-                                {
-                                    updates.push((name.clone(), safe_version.to_string()));
-                                    fixes_applied = true;
-                                }
+                let root = context.project.root.clone();
+                if let Some(AuditCommand::Signatures) = command {
+                    let verified = async {
+                        let registry = RegistryClient::new();
+                        let Some(keys) = RegistryKeys::load(&registry, context.package_cache.cache_dir()).await? else {
+                            anyhow::bail!("The registry publishes no signing keys");
+                        };
+                        let package_json = PackageJson::load(&root).await?;
+                        let installed = LockFile::from_node_modules(&package_json, &root).await?;
+                        audit_signatures(&registry, &keys, &installed).await
+                    };
+                    match verified.await {
+                        Ok(report) => {
+                            print!("{}", render_signatures(&report));
+                            if report.fails() {
+                                return Ok(ExitCode::from(EXIT_VULNERABLE));
                             }
                         }
+                        Err(e) => {
+                            eprintln!("Error: signature audit could not run: {:#}", e);
+                            return Ok(ExitCode::from(EXIT_FAILED));
+                        }
                     }
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                let audited = async {
+                    let package_json = PackageJson::load(&root).await?;
+                    let workspace = Workspace::discover(&root).await?;
+                    let installed = LockFile::from_node_modules(&package_json, &root).await?;
 
-                    // Apply fixes if requested
-                    if fix && fixes_applied {
-                        for (name, new_version) in updates {
-                            deps.insert(name.clone(), new_version.clone());
-                            println!("Updated {} to version {}", name, new_version);
+                    let source = if offline {
+                        AdvisorySource::offline(database).await?
+                    } else if registry {
+                        AdvisorySource::Registry(SecurityChecker::new())
+                    } else {
+                        AdvisorySource::detect(database).await?
+                    };
+                    let mut report = audit(&source, &installed).await?;
+                    AuditPolicy::load(&root).await?.apply(&mut report, today());
+                    let rendered = match format {
+                        AuditFormat::Table => render_table(&report),
+                        AuditFormat::Json => render_json(&report)?,
+                        AuditFormat::Sarif => {
+                            let sources = SourceFiles::load(&root, workspace.as_ref()).await?;
+                            render_sarif(&report, &sources)?
                         }
-                        package_json.save().await?;
-                        println!("\nUpdated package.json with security fixes");
+                        AuditFormat::Junit => render_junit(&report),
+                    };
+                    anyhow::Ok((package_json, workspace, installed, report, rendered))
+                }
+                .await;
+                // A failed audit must not look like a clean or a vulnerable
+                // tree to CI.
+                let (mut package_json, workspace, installed, report, rendered) = match audited {
+                    Ok(audited) => audited,
+                    Err(e) => {
+                        eprintln!("Error: audit could not run: {:#}", e);
+                        return Ok(ExitCode::from(EXIT_FAILED));
                     }
+                };
+                print!("{}", rendered);
+                if report.findings.is_empty() {
+                    return Ok(ExitCode::SUCCESS);
                 }
 
-                if !vulnerabilities_found {
-                    println!("No vulnerabilities found!");
-                } else if !fix {
-                    println!("\nRun 'rpm audit --fix' to automatically fix these issues");
+                let level = audit_level.map(Severity::from);
+                if !fix {
+                    if !report.fails(level) {
+                        return Ok(ExitCode::SUCCESS);
+                    }
+                    if format == AuditFormat::Table {
+                        println!("\nRun 'rpm audit --fix --dry-run' to see how they can be fixed");
+                    }
+                    return Ok(ExitCode::from(EXIT_VULNERABLE));
                 }
+
+                // Like the audit itself, a fix that fails to plan or apply
+                // must be told apart from one leaving findings behind.
+                let fixed = async {
+                    let registry = RegistryClient::new();
+                    let checker = SecurityChecker::new();
+                    let importers = importers(&package_json, workspace.as_ref());
+                    let plan = plan_fixes(&registry, &checker, &installed, importers, &report.findings, workspace.as_ref()).await?;
+
+                    // Keep machine-readable output on stdout parseable.
+                    let say = |text: String| {
+                        if format == AuditFormat::Table {
+                            print!("{}", text);
+                        } else {
+                            eprint!("{}", text);
+                        }
+                    };
+                    let rows: Vec<Vec<String>> = plan.fixes.iter().map(PlannedFix::row).collect();
+                    say(format!("\n{}", table::render(&FIX_HEADERS, &rows)));
+                    let held_back = plan.held_back(force);
+                    if held_back > 0 {
+                        say(format!("\n{} fixes cross a major version and need --force\n", held_back));
+                    }
+
+                    // What the fixes leave behind decides the status, as
+                    // the report does without --fix.
+                    let remaining = AuditReport {
+                        findings: plan.remaining(&report.findings, force),
+                        ..Default::default()
+                    };
+                    let exit_code = if remaining.fails(level) {
+                        ExitCode::from(EXIT_VULNERABLE)
+                    } else {
+                        ExitCode::SUCCESS
+                    };
+                    if dry_run {
+                        return Ok(exit_code);
+                    }
+
+                    let applicable = plan.applicable(force).count();
+                    if applicable == 0 {
+                        eprintln!("\nNothing can be fixed{}", if held_back > 0 { " without --force" } else { "" });
+                        return Ok(exit_code);
+                    }
+
+                    let overrides = plan.overrides(force);
+                    for (name, range) in &overrides {
+                        package_json.set_override(name, range);
+                    }
+                    if !overrides.is_empty() {
+                        package_json.save(&root).await?;
+                    }
+                    write_and_reinstall(&root, &mut package_json, workspace.as_ref(), &plan.updates(force), context).await?;
+                    say(format!("\nApplied {} of {} fixes\n", applicable, plan.fixes.len()));
+                    anyhow::Ok(exit_code)
+                }
+                .await;
+                return match fixed {
+                    Ok(exit_code) => Ok(exit_code),
+                    Err(e) => {
+                        eprintln!("Error: audit fix failed: {:#}", e);
+                        Ok(ExitCode::from(EXIT_FAILED))
+                    }
+                };
+            }
+            Commands::Run { script, recursive, filter, concurrency } => {
+                let root = context.project.root.clone();
+                let runner = ScriptRunner::new(concurrency);
+                let targeted = recursive || !filter.is_empty() || self.workspaces || !self.workspace.is_empty();
+
+                if !targeted {
+                    let package_json = PackageJson::load(&root).await?;
+                    runner.run(&root, &package_json, &script).await?;
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                let workspace = Workspace::discover(&root)
+                    .await?
+                    .ok_or(WorkspaceError::NotAWorkspace)?;
+
+                let selected = if !filter.is_empty() {
+                    apply_filters(&workspace, &filter).await?
+                } else if self.workspace.is_empty() {
+                    workspace.members.iter().collect()
+                } else {
+                    workspace.select(&self.workspace, false)?
+                };
+
+                if selected.is_empty() {
+                    println!("No workspaces matched");
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                runner.run_recursive(&workspace, &selected, &script).await?;
+            }
+            Commands::Outdated { json } => {
+                let root = context.project.root.clone();
+                let package_json = PackageJson::load(&root).await?;
+                let workspace = Workspace::discover(&root).await?;
+                let installed = LockFile::from_node_modules(&package_json, &root).await?;
+
+                let mut importers = importers(&package_json, workspace.as_ref());
+                if self.workspaces || !self.workspace.is_empty() {
+                    let workspace = workspace.as_ref().ok_or(WorkspaceError::NotAWorkspace)?;
+                    let selected = workspace.select(&self.workspace, self.workspaces)?;
+                    importers.retain(|importer| selected.iter().any(|member| member.name == importer.name));
+                }
+
+                let registry = RegistryClient::new();
+                let outdated = find_outdated(&registry, &importers, &installed, workspace.as_ref()).await?;
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&outdated)?);
+                } else if !outdated.is_empty() {
+                    let rows: Vec<Vec<String>> = outdated.iter().map(|dependency| dependency.row()).collect();
+                    print!("{}", table::render(&OUTDATED_HEADERS, &rows));
+                }
+
+                if !outdated.is_empty() {
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+            Commands::Why { package, json } => {
+                let root = context.project.root.clone();
+                let lockfile = LockFile::load(&root.join(LOCKFILE_NAME)).await?;
+                let package_json = PackageJson::load(&root).await?;
+                let workspace = Workspace::discover(&root).await?;
+
+                let tree = InstalledTree::new(&lockfile, importers(&package_json, workspace.as_ref()));
+                let explanations = tree.explain(&package);
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&explanations)?);
+                } else {
+                    for explanation in &explanations {
+                        println!("{}@{} ({})", explanation.name, explanation.version, explanation.location);
+                        for path in &explanation.paths {
+                            println!("  {}", path);
+                        }
+                    }
+                }
+
+                if explanations.is_empty() {
+                    eprintln!("{} is not installed", package);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+            Commands::Dedupe { check } => {
+                let root = context.project.root.clone();
+                let lockfile_path = root.join(LOCKFILE_NAME);
+                let lockfile = LockFile::load(&lockfile_path).await?;
+                let package_json = PackageJson::load(&root).await?;
+                let workspace = Workspace::discover(&root).await?;
+
+                let plan = plan_dedupe(lockfile, &importers(&package_json, workspace.as_ref()));
+                if plan.changes.is_empty() {
+                    println!("No duplicate packages to remove");
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                for change in &plan.changes {
+                    println!("{}@{}", change.name, change.version);
+                    if let Some(from) = &change.hoisted_from {
+                        println!("  hoisted from {}", from);
+                    }
+                    for copy in &change.removed {
+                        println!("  removed {} ({})", copy.location, copy.version);
+                    }
+                }
+
+                if check {
+                    let bytes = reclaimable_bytes(&root, &plan.changes).await?;
+                    println!(
+                        "\n{} packages could be removed, freeing {}",
+                        plan.packages_removed,
+                        HumanBytes(bytes)
+                    );
+                    return Ok(ExitCode::FAILURE);
+                }
+
+                let bytes = apply_dedupe(&root, &plan.changes).await?;
+                plan.lockfile.save(&lockfile_path).await?;
+                println!("\nRemoved {} packages, freeing {}", plan.packages_removed, HumanBytes(bytes));
+            }
+            Commands::Prune { omit, include } => {
+                let root = context.project.root.clone();
+                let package_json = PackageJson::load(&root).await?;
+                let workspace = Workspace::discover(&root).await?;
+                let omit = omit_from_flags(omit, include);
+
+                let pruned = prune(&root, &package_json, workspace.as_ref(), omit.kinds()).await?;
+                for package in &pruned {
+                    println!("removed {} ({})", package.location, package.version);
+                }
+                println!("Pruned {} packages", pruned.len());
             }
         }
 
-        Ok(())
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Writes `updates` into the root and member manifests, then resolves and
+/// reinstalls from them, which also regenerates the lockfile. Packages
+/// already in the cache aren't downloaded again.
+async fn write_and_reinstall(
+    root: &Path,
+    package_json: &mut PackageJson,
+    workspace: Option<&Workspace>,
+    updates: &[PlannedUpdate],
+    context: AppContext,
+) -> RpmResult<()> {
+    if apply_updates(package_json, updates) {
+        package_json.save_to(root.join("package.json")).await?;
+    }
+    for member in workspace.iter().flat_map(|workspace| &workspace.members) {
+        let mut manifest = member.manifest.clone();
+        if apply_updates(&mut manifest, updates) {
+            manifest.save_to(member.path.join("package.json")).await?;
+        }
     }
+
+    let installer = PackageInstaller::new(false, root, context.package_cache, context.memory_profile)
+        .with_omit(Omit::from_env(&[], &[]))
+        .with_fresh_resolution();
+    match Workspace::discover(root).await?.map(Arc::new) {
+        Some(workspace) => {
+            let selected = workspace.select(&[], true)?;
+            let manifests = workspace.manifests(&selected);
+            installer
+                .install_project(&workspace.root, &manifests, Some(Arc::clone(&workspace)))
+                .await?;
+        }
+        None => {
+            installer
+                .install_project(root, &[(root, &*package_json)], None)
+                .await?;
+        }
+    }
+
+    Ok(())
 }
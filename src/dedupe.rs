@@ -0,0 +1,214 @@
+use crate::lockfile::LockFile;
+use crate::tree::{location, Importer, InstalledTree, NodePath};
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// A package collapsed to a single copy.
+#[derive(Debug, Clone, Serialize)]
+pub struct Deduplicated {
+    pub name: String,
+    /// The version every dependent now shares.
+    pub version: String,
+    /// Where the surviving copy was, when it's moved to the top level.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hoisted_from: Option<String>,
+    pub removed: Vec<RemovedCopy>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemovedCopy {
+    pub location: String,
+    pub version: String,
+}
+
+/// The lockfile after deduplication and the changes leading to it.
+pub struct DedupePlan {
+    pub lockfile: LockFile,
+    pub changes: Vec<Deduplicated>,
+    /// Packages dropped from the tree, including everything nested under a
+    /// removed copy.
+    pub packages_removed: usize,
+}
+
+/// Collapses duplicated packages one name at a time, so each decision sees
+/// the tree left by the previous ones.
+pub fn plan_dedupe(mut lockfile: LockFile, importers: &[Importer]) -> DedupePlan {
+    let mut changes = Vec::new();
+    let mut packages_removed = 0;
+
+    let names = InstalledTree::new(&lockfile, importers.to_vec()).duplicated();
+    for name in names {
+        let Some(collapse) = InstalledTree::new(&lockfile, importers.to_vec()).collapse(&name) else {
+            continue;
+        };
+
+        // Copies nested inside another removed copy go away with it.
+        let outermost: Vec<&NodePath> = collapse
+            .remove
+            .iter()
+            .map(|(path, _)| path)
+            .filter(|path| !collapse.remove.iter().any(|(other, _)| other != *path && path.starts_with(other)))
+            .collect();
+        for path in outermost {
+            packages_removed += lockfile.remove(path).map_or(0, |entry| entry.package_count());
+        }
+
+        let hoisted_from = (collapse.keep.len() > 1).then(|| location(&collapse.keep));
+        if hoisted_from.is_some() {
+            if let Some(entry) = lockfile.remove(&collapse.keep) {
+                lockfile.hoist(collapse.name.clone(), entry);
+            }
+        }
+
+        changes.push(Deduplicated {
+            name: collapse.name,
+            version: collapse.version,
+            hoisted_from,
+            removed: collapse
+                .remove
+                .iter()
+                .map(|(path, version)| RemovedCopy { location: location(path), version: version.clone() })
+                .collect(),
+        });
+    }
+
+    DedupePlan { lockfile, changes, packages_removed }
+}
+
+/// Carries out `changes` in `root/node_modules`, returning the bytes freed.
+pub async fn apply_dedupe(root: &Path, changes: &[Deduplicated]) -> Result<u64> {
+    let root = root.to_path_buf();
+    let changes = changes.to_vec();
+    tokio::task::spawn_blocking(move || {
+        let mut freed = 0;
+        for change in &changes {
+            for dir in removed_dirs(&root, change) {
+                freed += disk_usage(&dir)?;
+                std::fs::remove_dir_all(&dir)?;
+            }
+            if let Some(from) = &change.hoisted_from {
+                std::fs::rename(root.join(from), root.join(location(std::slice::from_ref(&change.name))))?;
+            }
+        }
+        Ok(freed)
+    })
+    .await?
+}
+
+/// The bytes `changes` would free, for `--check`.
+pub async fn reclaimable_bytes(root: &Path, changes: &[Deduplicated]) -> Result<u64> {
+    let root = root.to_path_buf();
+    let changes = changes.to_vec();
+    tokio::task::spawn_blocking(move || {
+        let mut total = 0;
+        for change in &changes {
+            for dir in removed_dirs(&root, change) {
+                total += disk_usage(&dir)?;
+            }
+        }
+        Ok(total)
+    })
+    .await?
+}
+
+/// Directories to delete for `change`, skipping those inside another one.
+fn removed_dirs(root: &Path, change: &Deduplicated) -> Vec<PathBuf> {
+    change
+        .removed
+        .iter()
+        .filter(|copy| {
+            !change
+                .removed
+                .iter()
+                .any(|other| copy.location.starts_with(&format!("{}/", other.location)))
+        })
+        .map(|copy| root.join(&copy.location))
+        .filter(|dir| dir.exists())
+        .collect()
+}
+
+fn disk_usage(path: &Path) -> std::io::Result<u64> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        total += disk_usage(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::PackageJson;
+    use crate::testing::{locked, locked_requiring, lockfile};
+
+    #[test]
+    fn test_plan_dedupe() {
+        // `a` and `b` each got their own lodash although 4.17.21 fits both,
+        // while `c` really needs lodash 3.
+        let mut a = locked_requiring("1.0.0", serde_json::json!({"lodash": "^4.17.0"}));
+        a["dependencies"] = serde_json::json!({"lodash": locked("4.17.15")});
+        let mut b = locked_requiring("1.0.0", serde_json::json!({"lodash": "^4.17.20"}));
+        b["dependencies"] = serde_json::json!({"lodash": locked("4.17.20")});
+        let mut c = locked_requiring("1.0.0", serde_json::json!({"lodash": "^3.0.0"}));
+        c["dependencies"] = serde_json::json!({"lodash": locked("3.10.1")});
+
+        let lockfile = lockfile(serde_json::json!({
+            "a": a,
+            "b": b,
+            "c": c,
+            "lodash": locked("4.17.21")
+        }));
+        let manifest: PackageJson = serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": {"a": "^1.0.0", "b": "^1.0.0", "c": "^1.0.0", "lodash": "^4.0.0"}
+        }))
+        .unwrap();
+
+        let plan = plan_dedupe(lockfile, &[Importer::from_manifest(&manifest)]);
+
+        assert_eq!(plan.packages_removed, 2);
+        assert_eq!(plan.changes.len(), 1);
+        assert_eq!(plan.changes[0].version, "4.17.21");
+        let mut removed: Vec<&str> = plan.changes[0].removed.iter().map(|copy| copy.location.as_str()).collect();
+        removed.sort();
+        assert_eq!(removed, vec!["node_modules/a/node_modules/lodash", "node_modules/b/node_modules/lodash"]);
+
+        let dependencies = plan.lockfile.dependencies();
+        assert!(dependencies["a"].dependencies().is_none());
+        assert!(dependencies["b"].dependencies().is_none());
+        assert_eq!(dependencies["c"].dependencies().unwrap()["lodash"].version(), "3.10.1");
+    }
+
+    #[test]
+    fn test_plan_dedupe_hoists_nested_copy() {
+        // The top-level copy is too old for `a`, but the copy nested under
+        // `a` also satisfies the project's own range.
+        let mut a = locked_requiring("1.0.0", serde_json::json!({"debug": "^4.3.0"}));
+        a["dependencies"] = serde_json::json!({"debug": locked("4.3.4")});
+
+        let lockfile = lockfile(serde_json::json!({
+            "a": a,
+            "debug": locked("4.1.0")
+        }));
+        let manifest: PackageJson = serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": {"a": "^1.0.0", "debug": "^4.0.0"}
+        }))
+        .unwrap();
+
+        let plan = plan_dedupe(lockfile, &[Importer::from_manifest(&manifest)]);
+
+        assert_eq!(plan.changes[0].hoisted_from.as_deref(), Some("node_modules/a/node_modules/debug"));
+        assert_eq!(plan.changes[0].removed[0].location, "node_modules/debug");
+        assert_eq!(plan.lockfile.dependencies()["debug"].version(), "4.3.4");
+        assert!(plan.lockfile.dependencies()["a"].dependencies().is_none());
+    }
+}
@@ -1,111 +1,992 @@
+use crate::audit::AdvisorySource;
 use crate::error::DependencyError;
-use crate::package::{Package, PackageJson};
+use crate::package::{Package, PackageJson, Packument};
 use crate::registry::RegistryClient;
+use crate::security::Advisory;
+use crate::workspace::Workspace;
+use crate::version::VersionManager;
 use semver::{Version, VersionReq};
-use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+use futures::stream::{FuturesUnordered, StreamExt};
 
 #[derive(Debug, Clone)]
 pub struct Dependency {
     pub name: String,
-    pub version_req: VersionReq,
+    /// The specifier exactly as written in `package.json`.
+    pub spec: String,
+    pub source: DependencySource,
     pub resolved_version: Option<Version>,
 }
 
+/// Where a dependency comes from, parsed from its `package.json` specifier.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DependencySource {
+    /// A semver range resolved against the registry, e.g. `^4.17.1`.
+    Registry(VersionReq),
+    /// `npm:other-name@^2`, installed under the alias.
+    Alias { package: String, version_req: VersionReq },
+    /// `git+ssh://...`, `git+https://...`, `github:user/repo` or `user/repo`.
+    Git { url: String, reference: GitReference },
+    /// A tarball downloaded over HTTP(S).
+    Tarball(String),
+    /// `file:../lib`, copied into `node_modules`.
+    File(PathBuf),
+    /// `link:../lib`, symlinked into `node_modules`.
+    Link(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GitReference {
+    /// The remote's default branch.
+    Head,
+    /// A branch, tag or (possibly abbreviated) commit after `#`.
+    Committish(String),
+    /// The highest tag satisfying `#semver:<range>`.
+    Semver(VersionReq),
+}
+
+impl DependencySource {
+    pub fn parse(name: &str, spec: &str) -> Result<Self, DependencyError> {
+        let invalid = || DependencyError::InvalidSpecifier {
+            package: name.to_string(),
+            spec: spec.to_string(),
+        };
+        let spec = spec.trim();
+
+        if let Some(aliased) = spec.strip_prefix("npm:") {
+            // The package name may itself be scoped, so split on the last `@`.
+            let (package, range) = match aliased.rfind('@') {
+                Some(at) if at > 0 => (&aliased[..at], &aliased[at + 1..]),
+                _ => (aliased, "*"),
+            };
+            let version_req = parse_range(range).ok_or_else(invalid)?;
+            return Ok(Self::Alias {
+                package: package.to_string(),
+                version_req,
+            });
+        }
+
+        if let Some(path) = spec.strip_prefix("file:") {
+            return Ok(Self::File(PathBuf::from(path)));
+        }
+        if let Some(path) = spec.strip_prefix("link:") {
+            return Ok(Self::Link(PathBuf::from(path)));
+        }
+        if spec.starts_with("./") || spec.starts_with("../") || spec.starts_with('/') {
+            return Ok(Self::File(PathBuf::from(spec)));
+        }
+
+        if let Some((url, reference)) = parse_git(spec) {
+            let reference = match reference {
+                None | Some("") => GitReference::Head,
+                Some(fragment) => match fragment.strip_prefix("semver:") {
+                    Some(range) => GitReference::Semver(parse_range(range).ok_or_else(invalid)?),
+                    None => GitReference::Committish(fragment.to_string()),
+                },
+            };
+            return Ok(Self::Git { url, reference });
+        }
+
+        if spec.starts_with("https://") || spec.starts_with("http://") {
+            return Ok(Self::Tarball(spec.to_string()));
+        }
+
+        parse_range(spec).map(Self::Registry).ok_or_else(invalid)
+    }
+
+    pub fn is_registry(&self) -> bool {
+        matches!(self, Self::Registry(_))
+    }
+}
+
+fn parse_range(range: &str) -> Option<VersionReq> {
+    match range {
+        "" | "latest" => Some(VersionReq::STAR),
+        range => VersionReq::parse(range).ok(),
+    }
+}
+
+/// Splits a git specifier into a clone URL and the optional `#` fragment.
+fn parse_git(spec: &str) -> Option<(String, Option<&str>)> {
+    let (location, fragment) = match spec.split_once('#') {
+        Some((location, fragment)) => (location, Some(fragment)),
+        None => (spec, None),
+    };
+
+    let url = if let Some(url) = location.strip_prefix("git+") {
+        url.to_string()
+    } else if location.starts_with("git://") || location.starts_with("git@") {
+        location.to_string()
+    } else if let Some(path) = location.strip_prefix("github:") {
+        format!("https://github.com/{}.git", path)
+    } else if let Some(path) = location.strip_prefix("gitlab:") {
+        format!("https://gitlab.com/{}.git", path)
+    } else if let Some(path) = location.strip_prefix("bitbucket:") {
+        format!("https://bitbucket.org/{}.git", path)
+    } else if is_github_shorthand(location) {
+        format!("https://github.com/{}.git", location)
+    } else if location.ends_with(".git") && location.contains("://") {
+        location.to_string()
+    } else {
+        return None;
+    };
+
+    Some((url, fragment))
+}
+
+/// `user/repo`, which npm resolves against GitHub.
+fn is_github_shorthand(location: &str) -> bool {
+    let mut parts = location.split('/');
+    let (Some(user), Some(repo), None) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    let valid = |s: &str| {
+        !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    };
+    !user.starts_with('.') && valid(user) && valid(repo)
+}
+
+/// Result of resolving a project: every package version in the graph and
+/// the version each requested `name@range` resolved to.
+#[derive(Debug, Default, Clone)]
+pub struct ResolvedGraph {
+    /// Keyed by `name@version`.
+    pub packages: HashMap<String, Package>,
+    /// Keyed by `name@range`.
+    pub resolutions: HashMap<String, Version>,
+    /// Ranges requested directly by each importer, keyed by the importer's
+    /// name in `node_modules`: `PROJECT` for the project itself.
+    pub roots: BTreeMap<String, HashMap<String, VersionReq>>,
+    /// Packages resolved with advisories where no version in range was
+    /// free of them, keyed by `name@version`, with the advisories affecting
+    /// the version picked.
+    pub vulnerable: HashMap<String, Vec<Advisory>>,
+}
+
+impl ResolvedGraph {
+    /// The package a `name@range` edge resolved to.
+    pub fn resolve(&self, name: &str, version_req: &VersionReq) -> Option<&Package> {
+        let version = self.resolutions.get(&edge_key(name, version_req))?;
+        self.packages.get(&package_key(name, version))
+    }
+
+    /// Places every package in a `node_modules` tree. Packages are hoisted to
+    /// the top level unless another version of the same name is already
+    /// there, in which case the copy is nested under its dependent: a
+    /// package, or for the ranges importers declare, the importer.
+    pub fn layout(&self) -> Layout {
+        let mut layout = Layout::default();
+        let mut queue: VecDeque<Vec<String>> = VecDeque::new();
+
+        // The project's own ranges come first, so they get the top level.
+        for (importer, roots) in &self.roots {
+            let mut roots: Vec<_> = roots.iter().collect();
+            roots.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, version_req) in roots {
+                let Some(package) = self.resolve(name, version_req) else {
+                    continue;
+                };
+                let path = match layout.tree.get(name) {
+                    Some(existing) if existing.package.version == package.version => continue,
+                    Some(_) => vec![importer.clone(), name.clone()],
+                    None => vec![name.clone()],
+                };
+                scope_mut(&mut layout, &path[..path.len() - 1]).insert(name.clone(), InstallNode::new(package.clone()));
+                queue.push_back(path);
+            }
+        }
+
+        while let Some(path) = queue.pop_front() {
+            let package = scope(&layout, &path[..path.len() - 1])[&path[path.len() - 1]].package.clone();
+            let mut dependencies: Vec<(&String, &Package)> = package
+                .installed_dependencies()
+                .filter_map(|(dep, spec)| match DependencySource::parse(dep, spec) {
+                    Ok(DependencySource::Registry(version_req)) => Some((dep, self.resolve(dep, &version_req)?)),
+                    _ => None,
+                })
+                .collect();
+            dependencies.sort_by(|a, b| a.0.cmp(b.0));
+
+            let mut placed = Vec::new();
+            for (dep, target) in dependencies {
+                // The copy `dep` would see from here, nearest first.
+                let visible = (0..=path.len())
+                    .rev()
+                    .find_map(|depth| scope(&layout, &path[..depth]).get(dep));
+
+                let depth = match visible {
+                    Some(existing) if existing.package.version == target.version => continue,
+                    Some(_) => path.len(),
+                    None => 0,
+                };
+                scope_mut(&mut layout, &path[..depth]).insert(dep.clone(), InstallNode::new(target.clone()));
+                let mut child = path[..depth].to_vec();
+                child.push(dep.clone());
+                placed.push(child);
+            }
+            queue.extend(placed);
+        }
+
+        layout
+    }
+
+    /// Collapses duplicate versions where a single version already in the
+    /// graph satisfies several ranges, then drops whatever is no longer
+    /// reachable. Ranges that can't share a version keep their own copy.
+    fn deduplicate(&mut self) {
+        let mut edges: BTreeMap<String, Vec<(VersionReq, String)>> = BTreeMap::new();
+        for (importer, roots) in &self.roots {
+            let required_by = if importer == PROJECT { "the project" } else { importer };
+            for (name, version_req) in roots {
+                edges.entry(name.clone()).or_default().push((version_req.clone(), required_by.to_string()));
+            }
+        }
+        for (key, package) in &self.packages {
+            for (dep, spec) in package.installed_dependencies() {
+                if let Ok(DependencySource::Registry(version_req)) = DependencySource::parse(dep, spec) {
+                    if self.resolutions.contains_key(&edge_key(dep, &version_req)) {
+                        edges.entry(dep.clone()).or_default().push((version_req, key.clone()));
+                    }
+                }
+            }
+        }
+
+        for (name, mut requirements) in edges {
+            // A version picked because nothing in one range was safe isn't
+            // shared with ranges that had a safe one.
+            let available: Vec<Version> = self
+                .packages
+                .values()
+                .filter(|package| package.name == name)
+                .filter(|package| !self.vulnerable.contains_key(&package_key(&package.name, &package.version)))
+                .map(|package| package.version.clone())
+                .collect();
+            if available.len() < 2 {
+                continue;
+            }
+
+            requirements.sort_by(|a, b| (a.0.to_string(), &a.1).cmp(&(b.0.to_string(), &b.1)));
+            let mut groups: Vec<VersionManager> = Vec::new();
+            for (version_req, required_by) in requirements {
+                let index = match groups.iter().position(|group| group.is_compatible(&name, &version_req)) {
+                    Some(index) => index,
+                    None => {
+                        groups.push(VersionManager::new());
+                        groups.len() - 1
+                    }
+                };
+                // The range came from a parsed `VersionReq`, so it parses again.
+                let _ = groups[index].add_constraint(name.clone(), &version_req.to_string(), &required_by);
+            }
+
+            for mut group in groups {
+                match group.resolve_version(&name, &available) {
+                    Ok(version) => {
+                        for constraint in group.constraints(&name) {
+                            self.resolutions.insert(edge_key(&name, &constraint.requirement), version.clone());
+                        }
+                    }
+                    Err(e) => log::debug!("Keeping duplicate versions: {}", e),
+                }
+            }
+        }
+
+        self.prune();
+        let packages = &self.packages;
+        self.vulnerable.retain(|key, _| packages.contains_key(key));
+    }
+
+    /// Removes packages no longer reachable from the roots.
+    fn prune(&mut self) {
+        let mut reachable = HashSet::new();
+        let mut stack: Vec<String> = self
+            .roots
+            .values()
+            .flatten()
+            .filter_map(|(name, version_req)| self.resolutions.get(&edge_key(name, version_req)).map(|v| package_key(name, v)))
+            .collect();
+
+        while let Some(key) = stack.pop() {
+            if !reachable.insert(key.clone()) {
+                continue;
+            }
+            let Some(package) = self.packages.get(&key) else {
+                continue;
+            };
+            for (dep, spec) in package.installed_dependencies() {
+                if let Ok(DependencySource::Registry(version_req)) = DependencySource::parse(dep, spec) {
+                    if let Some(version) = self.resolutions.get(&edge_key(dep, &version_req)) {
+                        stack.push(package_key(dep, version));
+                    }
+                }
+            }
+        }
+
+        self.packages.retain(|key, _| reachable.contains(key));
+    }
+}
+
+/// The importer key of the project itself in `ResolvedGraph::roots`.
+pub const PROJECT: &str = "";
+
+/// Where every package goes in `node_modules`.
+#[derive(Debug, Default, Clone)]
+pub struct Layout {
+    /// The top level of `node_modules`.
+    pub tree: BTreeMap<String, InstallNode>,
+    /// Copies in the `node_modules` of importers other than the project,
+    /// e.g. workspace members, because they conflict with the hoisted
+    /// version. Keyed by the importer's name in `node_modules`.
+    pub importers: BTreeMap<String, BTreeMap<String, InstallNode>>,
+}
+
+/// A package's place in `node_modules`, with the copies nested in its own
+/// `node_modules` because they conflict with the hoisted version.
+#[derive(Debug, Clone)]
+pub struct InstallNode {
+    pub package: Package,
+    pub children: BTreeMap<String, InstallNode>,
+}
+
+impl InstallNode {
+    fn new(package: Package) -> Self {
+        Self {
+            package,
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+/// Every package in `layout` with its directory relative to the top-level
+/// `node_modules`, e.g. `express/node_modules/debug`.
+pub fn placements(layout: &Layout) -> Vec<(PathBuf, &Package)> {
+    let mut placed = Vec::new();
+    let mut stack: Vec<(PathBuf, &BTreeMap<String, InstallNode>)> = vec![(PathBuf::new(), &layout.tree)];
+    stack.extend(
+        layout
+            .importers
+            .iter()
+            .map(|(importer, scope)| (Path::new(importer).join("node_modules"), scope)),
+    );
+
+    while let Some((base, scope)) = stack.pop() {
+        for (name, node) in scope {
+            let path = base.join(name);
+            placed.push((path.clone(), &node.package));
+            stack.push((path.join("node_modules"), &node.children));
+        }
+    }
+
+    placed
+}
+
+/// The packages at `path`. Importers aren't in the tree themselves, so a
+/// path whose first name isn't there starts at an importer.
+fn scope<'a>(layout: &'a Layout, path: &[String]) -> &'a BTreeMap<String, InstallNode> {
+    let (scope, path) = match path.split_first() {
+        Some((first, rest)) if !layout.tree.contains_key(first) => (&layout.importers[first], rest),
+        _ => (&layout.tree, path),
+    };
+    path.iter().fold(scope, |scope, name| &scope[name].children)
+}
+
+fn scope_mut<'a>(layout: &'a mut Layout, path: &[String]) -> &'a mut BTreeMap<String, InstallNode> {
+    let (mut scope, path) = match path.split_first() {
+        Some((first, rest)) if !layout.tree.contains_key(first) => {
+            (layout.importers.entry(first.clone()).or_default(), rest)
+        }
+        _ => (&mut layout.tree, path),
+    };
+    for name in path {
+        scope = &mut scope.get_mut(name).expect("placed packages stay in the tree").children;
+    }
+    scope
+}
+
+fn edge_key(name: &str, version_req: &VersionReq) -> String {
+    format!("{}@{}", name, version_req)
+}
+
+fn package_key(name: &str, version: &Version) -> String {
+    format!("{}@{}", name, version)
+}
+
 pub struct DependencyResolver {
     registry: Arc<RegistryClient>,
-    resolved_deps: Arc<Mutex<HashMap<String, Version>>>,
-    processing: Arc<Mutex<HashSet<String>>>,
+    workspace: Option<Arc<Workspace>>,
+    overrides: HashMap<String, VersionReq>,
+    advisory_source: Option<Arc<AdvisorySource>>,
+    /// Fetched once per package, however many ranges ask for it.
+    advisories: Mutex<HashMap<String, Arc<OnceCell<Vec<Advisory>>>>>,
 }
 
 impl DependencyResolver {
     pub fn new(registry: Arc<RegistryClient>) -> Self {
         Self {
             registry,
-            resolved_deps: Arc::new(Mutex::new(HashMap::new())),
-            processing: Arc::new(Mutex::new(HashSet::new())),
+            workspace: None,
+            overrides: HashMap::new(),
+            advisory_source: None,
+            advisories: Mutex::new(HashMap::new()),
         }
     }
 
-    pub async fn resolve_dependencies(&self, package_json: &PackageJson) -> Result<Vec<Package>, DependencyError> {
-        let mut resolved_packages = Vec::new();
-        let deps = self.collect_all_dependencies(package_json);
+    /// Satisfies dependencies on workspace members locally instead of
+    /// fetching them from the registry.
+    pub fn with_workspace(mut self, workspace: Arc<Workspace>) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
 
-        for (name, version_req) in deps {
-            let package = self.resolve_single_dependency(&name, &version_req).await?;
-            resolved_packages.push(package);
+    /// Resolves every dependency on a package named in `overrides`, the
+    /// root manifest's field of that name, to the override's range instead
+    /// of the declared one. Only registry ranges can be used as overrides.
+    pub fn with_overrides(mut self, overrides: &HashMap<String, String>) -> Result<Self, DependencyError> {
+        for (name, spec) in overrides {
+            match DependencySource::parse(name, spec)? {
+                DependencySource::Registry(version_req) => {
+                    self.overrides.insert(name.clone(), version_req);
+                }
+                _ => log::warn!("Ignoring the override {}@{}: only registry ranges can be used", name, spec),
+            }
         }
+        Ok(self)
+    }
 
-        Ok(resolved_packages)
+    /// Resolves each range to the newest version no advisory from `source`
+    /// affects, falling back to the newest version when all of them are
+    /// affected. Those are listed in `ResolvedGraph::vulnerable`.
+    pub fn with_advisories(mut self, source: Arc<AdvisorySource>) -> Self {
+        self.advisory_source = Some(source);
+        self
     }
 
-    pub async fn resolve_single_dependency(
+    pub async fn resolve_dependencies(&self, package_json: &PackageJson) -> Result<ResolvedGraph, DependencyError> {
+        self.resolve_manifests(package_json, &[]).await
+    }
+
+    /// Resolves the dependencies declared by the project's `manifest` and by
+    /// `importers`, each paired with its name in `node_modules`, e.g. the
+    /// members of a workspace. Every importer keeps its own ranges.
+    pub async fn resolve_manifests(
         &self,
-        name: &str,
-        version_req: &VersionReq,
-    ) -> Result<Package, DependencyError> {
-        {
-            let mut processing = self.processing.lock().await;
-            if processing.contains(name) {
-                return Err(DependencyError::CircularDependency(name.to_string()));
+        manifest: &PackageJson,
+        importers: &[(&str, &PackageJson)],
+    ) -> Result<ResolvedGraph, DependencyError> {
+        let mut roots = BTreeMap::from([(PROJECT.to_string(), self.collect_all_dependencies(manifest)?)]);
+        for (name, manifest) in importers {
+            roots.insert(name.to_string(), self.collect_all_dependencies(manifest)?);
+        }
+
+        self.resolve_importers(roots).await
+    }
+
+    /// Resolves the project's `roots` and everything they depend on.
+    pub async fn resolve_graph(&self, roots: HashMap<String, VersionReq>) -> Result<ResolvedGraph, DependencyError> {
+        self.resolve_importers(BTreeMap::from([(PROJECT.to_string(), roots)])).await
+    }
+
+    /// Resolves the `roots` of every importer and everything they depend on.
+    /// Each distinct `name@range` is resolved once and all of them are in
+    /// flight together; the `RegistryClient` bounds and de-duplicates the
+    /// metadata requests.
+    pub async fn resolve_importers(
+        &self,
+        roots: BTreeMap<String, HashMap<String, VersionReq>>,
+    ) -> Result<ResolvedGraph, DependencyError> {
+        let mut graph = ResolvedGraph {
+            roots: roots.clone(),
+            ..Default::default()
+        };
+        let mut requested = HashSet::new();
+        // Edges only ever requested as optional dependencies; failing to
+        // resolve one of them is not an error.
+        let mut optional = HashSet::new();
+        let mut skipped = HashMap::new();
+        let mut pending = FuturesUnordered::new();
+
+        for (name, version_req) in roots.into_values().flatten() {
+            if requested.insert(edge_key(&name, &version_req)) {
+                pending.push(self.resolve_edge(name, version_req));
             }
-            processing.insert(name.to_string());
         }
 
-        {
-            let resolved = self.resolved_deps.lock().await;
-            if let Some(version) = resolved.get(name) {
-                if version_req.matches(version) {
-                    let mut processing = self.processing.lock().await;
-                    processing.remove(name);
-                    return Ok(self.registry.fetch_package_info(name, Some(&version.to_string())).await?);
+        while let Some((name, version_req, result)) = pending.next().await {
+            let edge = edge_key(&name, &version_req);
+            let (package, advisories) = match result {
+                Ok(resolved) => resolved,
+                Err(e) if optional.contains(&edge) => {
+                    skipped.insert(edge, e);
+                    continue;
                 }
+                Err(e) => return Err(e),
+            };
+            graph.resolutions.insert(edge, package.version.clone());
+
+            let key = package_key(&name, &package.version);
+            if !advisories.is_empty() {
+                graph.vulnerable.insert(key.clone(), advisories);
+            }
+            if graph.packages.contains_key(&key) {
+                continue;
             }
-        }
 
-        let package = self.registry.fetch_package_info(name, None).await?;
-        let nearest_safe_version = self.find_nearest_safe_version(&package).await?;
+            for (dep, spec) in package.installed_dependencies() {
+                if self.is_local(dep, spec) {
+                    continue;
+                }
+                match DependencySource::parse(dep, spec) {
+                    Ok(DependencySource::Registry(dep_req)) => {
+                        let dep_edge = edge_key(dep, &dep_req);
+                        if package.optional_dependencies.contains_key(dep) {
+                            if !requested.contains(&dep_edge) {
+                                optional.insert(dep_edge.clone());
+                            }
+                        } else {
+                            optional.remove(&dep_edge);
+                        }
+                        if requested.insert(dep_edge) {
+                            pending.push(self.resolve_edge(dep.clone(), dep_req));
+                        }
+                    }
+                    _ => log::warn!("Skipping {}@{} required by {}: only registry ranges are resolved transitively", dep, spec, key),
+                }
+            }
 
-        {
-            let mut resolved = self.resolved_deps.lock().await;
-            resolved.insert(name.to_string(), nearest_safe_version.clone());
+            graph.packages.insert(key, package);
         }
 
-        {
-            let mut processing = self.processing.lock().await;
-            processing.remove(name);
+        // A skipped edge may have been required by something else since.
+        for (edge, e) in skipped {
+            if !optional.contains(&edge) {
+                return Err(e);
+            }
+            log::warn!("Skipping optional dependency {}: {}", edge, e);
         }
 
-        Ok(self.registry.fetch_package_info(name, Some(&nearest_safe_version.to_string())).await?)
+        graph.deduplicate();
+        Ok(graph)
     }
 
-    async fn find_nearest_safe_version(&self, package: &Package) -> Result<Version, DependencyError> {
-        // Implement logic to check for security issues and find the nearest safe version
-        // This may involve querying a vulnerability database or API
-        // For now, let's assume the current version is safe
-        Ok(package.version.clone())
+    async fn resolve_edge(
+        &self,
+        name: String,
+        version_req: VersionReq,
+    ) -> (String, VersionReq, Result<(Package, Vec<Advisory>), DependencyError>) {
+        // The edge stays keyed by the declared range, so the layout finds
+        // the overridden version where it looks for the declared one.
+        let effective = self.overrides.get(&name).unwrap_or(&version_req);
+        let result = self.resolve_version(&name, effective).await;
+        (name, version_req, result)
     }
 
-    fn collect_all_dependencies(&self, package_json: &PackageJson) -> HashMap<String, VersionReq> {
+    pub async fn resolve_single_dependency(
+        &self,
+        name: &str,
+        version_req: &VersionReq,
+    ) -> Result<Package, DependencyError> {
+        Ok(self.resolve_version(name, version_req).await?.0)
+    }
+
+    /// The version `version_req` resolves to, and the advisories affecting
+    /// it when no safe version was found.
+    pub async fn resolve_version(
+        &self,
+        name: &str,
+        version_req: &VersionReq,
+    ) -> Result<(Package, Vec<Advisory>), DependencyError> {
+        let packument = self.registry.fetch_packument(name).await?;
+        let no_match = || DependencyError::NoMatchingVersion {
+            package: name.to_string(),
+            requirement: version_req.to_string(),
+        };
+
+        let package = packument.max_satisfying(version_req).ok_or_else(no_match)?;
+        let (nearest_safe_version, advisories) = self.find_nearest_safe_version(&packument, package, version_req).await?;
+
+        let package = packument
+            .versions
+            .get(&nearest_safe_version)
+            .cloned()
+            .ok_or_else(no_match)?;
+        Ok((package, advisories))
+    }
+
+    /// `package` if nothing affects it, otherwise the newest version in
+    /// `version_req` that no advisory affects. When there is none,
+    /// `package`'s version and the advisories affecting it.
+    async fn find_nearest_safe_version(
+        &self,
+        packument: &Packument,
+        package: &Package,
+        version_req: &VersionReq,
+    ) -> Result<(Version, Vec<Advisory>), DependencyError> {
+        if self.advisory_source.is_none() {
+            return Ok((package.version.clone(), Vec::new()));
+        }
+        let advisories = self.package_advisories(packument).await?;
+        let affecting = |version: &Version| -> Vec<Advisory> {
+            advisories.iter().filter(|advisory| advisory.affects(version)).cloned().collect()
+        };
+
+        let current = affecting(&package.version);
+        if current.is_empty() {
+            return Ok((package.version.clone(), current));
+        }
+        let safe = packument
+            .sorted_versions()
+            .into_iter()
+            .rev()
+            .find(|version| version_req.matches(version) && affecting(version).is_empty());
+        match safe {
+            Some(version) => Ok((version, Vec::new())),
+            None => Ok((package.version.clone(), current)),
+        }
+    }
+
+    /// Every advisory affecting some published version of the package.
+    async fn package_advisories(&self, packument: &Packument) -> Result<Vec<Advisory>, DependencyError> {
+        let Some(source) = &self.advisory_source else {
+            return Ok(Vec::new());
+        };
+        let cell = {
+            let mut advisories = self.advisories.lock().expect("advisory cache poisoned");
+            Arc::clone(advisories.entry(packument.name.clone()).or_default())
+        };
+
+        let advisories = cell
+            .get_or_try_init(|| async {
+                let versions = packument.versions.keys().map(Version::to_string).collect();
+                let query = BTreeMap::from([(packument.name.clone(), versions)]);
+                let mut found = source
+                    .advisories(&query)
+                    .await
+                    .map_err(|e| DependencyError::AdvisoryCheckFailed {
+                        package: packument.name.clone(),
+                        details: e.to_string(),
+                    })?;
+                Ok::<_, DependencyError>(found.remove(&packument.name).unwrap_or_default())
+            })
+            .await?;
+        Ok(advisories.clone())
+    }
+
+    /// Registry ranges declared by `package_json`. Other kinds of specifier
+    /// are handled by `collect_external_dependencies`.
+    fn collect_all_dependencies(&self, package_json: &PackageJson) -> Result<HashMap<String, VersionReq>, DependencyError> {
         let mut all_deps = HashMap::new();
 
-        if let Some(deps) = &package_json.dependencies {
-            for (name, version) in deps {
-                if let Ok(version_req) = VersionReq::parse(version) {
-                    all_deps.insert(name.clone(), version_req);
-                }
+        for dependency in self.collect_sources(package_json, Path::new("."))? {
+            if let DependencySource::Registry(version_req) = dependency.source {
+                all_deps.insert(dependency.name, version_req);
             }
         }
 
-        if let Some(dev_deps) = &package_json.dev_dependencies {
-            for (name, version) in dev_deps {
-                if let Ok(version_req) = VersionReq::parse(version) {
-                    all_deps.insert(name.clone(), version_req);
-                }
+        Ok(all_deps)
+    }
+
+    /// Git, tarball, `file:`, `link:` and aliased dependencies declared by the
+    /// manifest in `dir`. Local paths are made relative to `dir`.
+    pub fn collect_external_dependencies(
+        &self,
+        dir: &Path,
+        package_json: &PackageJson,
+    ) -> Result<Vec<Dependency>, DependencyError> {
+        Ok(self
+            .collect_sources(package_json, dir)?
+            .into_iter()
+            .filter(|dependency| !dependency.source.is_registry())
+            .collect())
+    }
+
+    fn collect_sources(&self, package_json: &PackageJson, dir: &Path) -> Result<Vec<Dependency>, DependencyError> {
+        let mut all_deps: HashMap<String, Dependency> = HashMap::new();
+        let groups = [
+            &package_json.dependencies,
+            &package_json.dev_dependencies,
+            &package_json.optional_dependencies,
+        ];
+
+        for (name, spec) in groups.into_iter().flatten().flatten() {
+            if self.is_local(name, spec) {
+                continue;
             }
+
+            let source = match DependencySource::parse(name, spec)? {
+                DependencySource::File(path) => DependencySource::File(dir.join(path)),
+                DependencySource::Link(path) => DependencySource::Link(dir.join(path)),
+                source => source,
+            };
+
+            all_deps.insert(name.clone(), Dependency {
+                name: name.clone(),
+                spec: spec.clone(),
+                source,
+                resolved_version: None,
+            });
         }
 
-        all_deps
+        Ok(all_deps.into_values().collect())
+    }
+
+    fn is_local(&self, name: &str, spec: &str) -> bool {
+        self.workspace
+            .as_ref()
+            .is_some_and(|workspace| workspace.resolve_local(name, spec).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::mock::{packument, MockRegistry};
+    use crate::security::SecurityChecker;
+    use std::time::Duration;
+
+    fn parse(spec: &str) -> DependencySource {
+        DependencySource::parse("dep", spec).unwrap()
+    }
+
+    #[test]
+    fn test_parse_registry_and_alias() {
+        assert_eq!(parse("^4.17.1"), DependencySource::Registry(VersionReq::parse("^4.17.1").unwrap()));
+        assert_eq!(parse("latest"), DependencySource::Registry(VersionReq::STAR));
+        assert_eq!(
+            parse("npm:@scope/other@^2"),
+            DependencySource::Alias {
+                package: "@scope/other".to_string(),
+                version_req: VersionReq::parse("^2").unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_git() {
+        assert_eq!(
+            parse("git+ssh://git@github.com/user/repo.git#semver:^1"),
+            DependencySource::Git {
+                url: "ssh://git@github.com/user/repo.git".to_string(),
+                reference: GitReference::Semver(VersionReq::parse("^1").unwrap()),
+            }
+        );
+        assert_eq!(
+            parse("github:user/repo#main"),
+            DependencySource::Git {
+                url: "https://github.com/user/repo.git".to_string(),
+                reference: GitReference::Committish("main".to_string()),
+            }
+        );
+        assert_eq!(
+            parse("user/repo"),
+            DependencySource::Git {
+                url: "https://github.com/user/repo.git".to_string(),
+                reference: GitReference::Head,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_tarball_and_local() {
+        assert_eq!(
+            parse("https://example.com/x-1.0.0.tgz"),
+            DependencySource::Tarball("https://example.com/x-1.0.0.tgz".to_string())
+        );
+        assert_eq!(parse("file:../lib"), DependencySource::File(PathBuf::from("../lib")));
+        assert_eq!(parse("link:../lib"), DependencySource::Link(PathBuf::from("../lib")));
+        assert_eq!(parse("./vendor/x"), DependencySource::File(PathBuf::from("./vendor/x")));
+        assert!(DependencySource::parse("dep", "not a range").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_graph_concurrently() {
+        let documents = HashMap::from([
+            ("/a".to_string(), packument("a", &[("1.0.0", &[("shared", "^1.0.0")])])),
+            ("/c".to_string(), packument("c", &[("1.2.0", &[("shared", "~1.1.0")])])),
+            (
+                "/shared".to_string(),
+                packument("shared", &[("1.0.0", &[]), ("1.1.3", &[]), ("1.4.0", &[]), ("2.0.0", &[])]),
+            ),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::from_millis(20)).await;
+        let resolver = DependencyResolver::new(Arc::new(RegistryClient::with_url(registry.url.clone())));
+
+        let roots = HashMap::from([
+            ("a".to_string(), VersionReq::parse("^1").unwrap()),
+            ("c".to_string(), VersionReq::parse("^1").unwrap()),
+        ]);
+        let graph = resolver.resolve_graph(roots).await.unwrap();
+
+        let mut keys: Vec<_> = graph.packages.keys().cloned().collect();
+        keys.sort();
+        // `^1.0.0` alone would pick 1.4.0, but 1.1.3 satisfies both ranges.
+        assert_eq!(keys, vec!["a@1.0.0", "c@1.2.0", "shared@1.1.3"]);
+        assert_eq!(
+            graph.resolve("shared", &VersionReq::parse("^1.0.0").unwrap()).unwrap().version,
+            Version::new(1, 1, 3)
+        );
+        // Two ranges on `shared`, one metadata request.
+        assert_eq!(registry.hits("/shared"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_missing_version() {
+        let documents = HashMap::from([("/a".to_string(), packument("a", &[("1.0.0", &[])]))]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let resolver = DependencyResolver::new(Arc::new(RegistryClient::with_url(registry.url.clone())));
+
+        let roots = HashMap::from([("a".to_string(), VersionReq::parse("^2").unwrap())]);
+        let result = resolver.resolve_graph(roots).await;
+
+        assert!(matches!(result, Err(DependencyError::NoMatchingVersion { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_optional_dependencies() {
+        let mut a: serde_json::Value = serde_json::from_str(&packument("a", &[("1.0.0", &[])])).unwrap();
+        a["versions"]["1.0.0"]["optionalDependencies"] = serde_json::json!({"opt": "^1.0.0", "fsevents": "^2.0.0"});
+        let documents = HashMap::from([
+            ("/a".to_string(), a.to_string()),
+            ("/opt".to_string(), packument("opt", &[("1.2.0", &[])])),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let resolver = DependencyResolver::new(Arc::new(RegistryClient::with_url(registry.url.clone())));
+
+        let roots = HashMap::from([("a".to_string(), VersionReq::parse("^1").unwrap())]);
+        let graph = resolver.resolve_graph(roots).await.unwrap();
+
+        // `fsevents` isn't published; being optional, it's skipped.
+        let mut keys: Vec<_> = graph.packages.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["a@1.0.0", "opt@1.2.0"]);
+    }
+
+    #[tokio::test]
+    async fn test_layout_nests_conflicting_versions() {
+        let documents = HashMap::from([
+            ("/a".to_string(), packument("a", &[("1.0.0", &[("shared", "^1.0.0")])])),
+            ("/shared".to_string(), packument("shared", &[("1.4.0", &[]), ("2.1.0", &[])])),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let resolver = DependencyResolver::new(Arc::new(RegistryClient::with_url(registry.url.clone())));
+
+        let roots = HashMap::from([
+            ("a".to_string(), VersionReq::parse("^1").unwrap()),
+            ("shared".to_string(), VersionReq::parse("^2").unwrap()),
+        ]);
+        let layout = resolver.resolve_graph(roots).await.unwrap().layout();
+        let tree = &layout.tree;
+
+        assert_eq!(tree["shared"].package.version, Version::new(2, 1, 0));
+        assert_eq!(tree["a"].children["shared"].package.version, Version::new(1, 4, 0));
+
+        let mut paths: Vec<_> = placements(&layout).into_iter().map(|(path, _)| path).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("a"),
+                PathBuf::from("a/node_modules/shared"),
+                PathBuf::from("shared"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_members_keep_conflicting_ranges() {
+        let documents = HashMap::from([(
+            "/shared".to_string(),
+            packument("shared", &[("1.4.0", &[]), ("2.1.0", &[])]),
+        )]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let resolver = DependencyResolver::new(Arc::new(RegistryClient::with_url(registry.url.clone())));
+        let manifest = |name: &str, range: &str| -> PackageJson {
+            serde_json::from_value(serde_json::json!({
+                "name": name,
+                "version": "1.0.0",
+                "dependencies": { "shared": range }
+            }))
+            .unwrap()
+        };
+        let (root, a, b) = (manifest("root", "^1.0.0"), manifest("a", "^1.0.0"), manifest("b", "^2.0.0"));
+
+        let graph = resolver.resolve_manifests(&root, &[("a", &a), ("b", &b)]).await.unwrap();
+
+        assert_eq!(graph.roots["b"]["shared"], VersionReq::parse("^2.0.0").unwrap());
+        assert_eq!(
+            graph.resolve("shared", &VersionReq::parse("^2.0.0").unwrap()).unwrap().version,
+            Version::new(2, 1, 0)
+        );
+        // `b`'s copy goes in its own `node_modules`.
+        let layout = graph.layout();
+        assert_eq!(layout.tree["shared"].package.version, Version::new(1, 4, 0));
+        assert_eq!(layout.importers["b"]["shared"].package.version, Version::new(2, 1, 0));
+        assert!(!layout.importers.contains_key("a"));
+
+        let mut paths: Vec<_> = placements(&layout).into_iter().map(|(path, _)| path).collect();
+        paths.sort();
+        assert_eq!(paths, vec![PathBuf::from("b/node_modules/shared"), PathBuf::from("shared")]);
+    }
+
+    #[tokio::test]
+    async fn test_overrides_replace_declared_ranges() {
+        let documents = HashMap::from([
+            ("/a".to_string(), packument("a", &[("1.0.0", &[("minimist", "~0.0.1")])])),
+            ("/minimist".to_string(), packument("minimist", &[("0.0.8", &[]), ("1.2.6", &[]), ("1.2.8", &[])])),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let overrides = HashMap::from([("minimist".to_string(), "^1.2.6".to_string())]);
+        let resolver = DependencyResolver::new(Arc::new(RegistryClient::with_url(registry.url.clone())))
+            .with_overrides(&overrides)
+            .unwrap();
+
+        let roots = HashMap::from([("a".to_string(), VersionReq::parse("^1").unwrap())]);
+        let tree = resolver.resolve_graph(roots).await.unwrap().layout().tree;
+
+        // Still found through `a`'s declared `~0.0.1`.
+        assert_eq!(tree["minimist"].package.version, Version::new(1, 2, 8));
+    }
+
+    #[tokio::test]
+    async fn test_prefer_safe_versions() {
+        let advisories = serde_json::json!({
+            "minimist": [{
+                "id": 1179,
+                "title": "Prototype Pollution in minimist",
+                "severity": "moderate",
+                "vulnerable_versions": "<1.2.6 || >=1.2.8"
+            }],
+            "left-pad": [{
+                "id": 2000,
+                "title": "Unpatched",
+                "severity": "high",
+                "vulnerable_versions": "*"
+            }]
+        });
+        let documents = HashMap::from([
+            ("/-/npm/v1/security/advisories/bulk".to_string(), advisories.to_string()),
+            ("/a".to_string(), packument("a", &[("1.0.0", &[("minimist", "^1.2.0"), ("left-pad", "^1.0.0")])])),
+            ("/minimist".to_string(), packument("minimist", &[("1.2.0", &[]), ("1.2.6", &[]), ("1.2.8", &[])])),
+            ("/left-pad".to_string(), packument("left-pad", &[("1.0.0", &[]), ("1.3.0", &[])])),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let source = AdvisorySource::Registry(SecurityChecker::with_url(registry.url.clone()));
+        let resolver = DependencyResolver::new(Arc::new(RegistryClient::with_url(registry.url.clone())))
+            .with_advisories(Arc::new(source));
+
+        let roots = HashMap::from([
+            ("a".to_string(), VersionReq::parse("^1").unwrap()),
+            ("minimist".to_string(), VersionReq::parse("^1.2.0").unwrap()),
+        ]);
+        let graph = resolver.resolve_graph(roots).await.unwrap();
+
+        // The newest safe version, not the newest.
+        assert_eq!(graph.layout().tree["minimist"].package.version, Version::new(1, 2, 6));
+        // Nothing safe: the newest version, reported.
+        assert_eq!(graph.layout().tree["left-pad"].package.version, Version::new(1, 3, 0));
+        assert_eq!(graph.vulnerable.keys().collect::<Vec<_>>(), vec!["left-pad@1.3.0"]);
+        assert_eq!(graph.vulnerable["left-pad@1.3.0"][0].id, "2000");
     }
 }
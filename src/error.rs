@@ -51,6 +51,16 @@ pub enum RpmError {
 
     #[error("Verification failed: {0}")]
     VerificationError(String),
+
+    #[error("Workspace error: {0}")]
+    WorkspaceError(#[from] WorkspaceError),
+
+    #[error("Registry error: {0}")]
+    RegistryError(#[from] RegistryError),
+
+    /// A failure from one of the `anyhow`-based modules.
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
 }
 
 pub type RpmResult<T> = Result<T, RpmError>;
@@ -68,6 +78,10 @@ pub enum RegistryError {
 
     #[error("Failed to deserialize package data: {0}")]
     DeserializationError(String),
+
+    /// A failure of a request shared by several callers.
+    #[error(transparent)]
+    Shared(#[from] std::sync::Arc<RegistryError>),
 }
 
 #[derive(Error, Debug)]
@@ -89,6 +103,15 @@ pub enum DependencyError {
     
     #[error("Registry error: {0}")]
     RegistryError(#[from] RegistryError),
+
+    #[error("Unsupported dependency specifier for {package}: {spec}")]
+    InvalidSpecifier { package: String, spec: String },
+
+    #[error("No version of {package} satisfies {requirement}")]
+    NoMatchingVersion { package: String, requirement: String },
+
+    #[error("Failed to check advisories for {package}: {details}")]
+    AdvisoryCheckFailed { package: String, details: String },
 }
 
 #[derive(Error, Debug)]
@@ -128,4 +151,70 @@ pub enum SecurityError {
     
     #[error("Failed to check security: {0}")]
     CheckFailed(String),
-}
\ No newline at end of file
+}
+#[derive(Error, Debug)]
+pub enum WorkspaceError {
+    #[error("Invalid workspace pattern {pattern}: {details}")]
+    InvalidPattern { pattern: String, details: String },
+
+    #[error("Failed to read workspace manifest {path}: {details}")]
+    ManifestError { path: PathBuf, details: String },
+
+    #[error("Workspace {0} is declared more than once")]
+    DuplicateMember(String),
+
+    #[error("No workspace named {0}")]
+    UnknownMember(String),
+
+    #[error("Workspace flags require a package.json with a \"workspaces\" field")]
+    NotAWorkspace,
+
+    #[error("Invalid filter {filter}: {details}")]
+    InvalidFilter { filter: String, details: String },
+
+    #[error("Failed to list changes since {reference}: {details}")]
+    GitError { reference: String, details: String },
+
+    #[error("Circular dependency between workspaces: {0}")]
+    DependencyCycle(String),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error("git {command} failed for {url}: {details}")]
+    GitError {
+        command: String,
+        url: String,
+        details: String,
+    },
+
+    #[error("Invalid git URL {0:?}")]
+    InvalidGitUrl(String),
+
+    #[error("No git reference in {url} matches {reference}")]
+    ReferenceNotFound { url: String, reference: String },
+
+    #[error("Failed to download {url}: {details}")]
+    DownloadError { url: String, details: String },
+
+    #[error("{url} doesn't match the lockfile: expected sha256 {expected}, got {actual}")]
+    IntegrityMismatch { url: String, expected: String, actual: String },
+
+    #[error("No version of {package} satisfies {requirement}")]
+    NoMatchingVersion { package: String, requirement: String },
+
+    #[error("{0} is a registry dependency and must be resolved, not fetched")]
+    RegistryDependency(String),
+
+    #[error("Invalid package at {path}: {details}")]
+    InvalidPackage { path: PathBuf, details: String },
+
+    #[error("Registry error: {0}")]
+    RegistryError(#[from] RegistryError),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
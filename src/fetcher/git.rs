@@ -0,0 +1,211 @@
+use crate::dependency::GitReference;
+use crate::error::FetchError;
+use semver::Version;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command;
+
+/// Fetches git dependencies with the local `git` binary. Checkouts are cached
+/// by resolved commit, so a pinned commit is only cloned once.
+pub struct GitFetcher {
+    cache_dir: PathBuf,
+}
+
+impl GitFetcher {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self { cache_dir }
+    }
+
+    /// Returns the checkout directory and the full commit hash it contains.
+    pub async fn fetch(&self, url: &str, reference: &GitReference) -> Result<(PathBuf, String), FetchError> {
+        // git would read a leading dash as an option such as --upload-pack.
+        if url.starts_with('-') {
+            return Err(FetchError::InvalidGitUrl(url.to_string()));
+        }
+        let commit = self.resolve_commit(url, reference).await?;
+        let checkout = self.cache_dir.join(hex::encode(Sha256::digest(url.as_bytes()))).join(&commit);
+
+        if checkout.join("package.json").is_file() {
+            log::debug!("Using cached checkout of {} at {}", url, commit);
+            return Ok((checkout, commit));
+        }
+
+        fs::create_dir_all(&self.cache_dir).await?;
+        let staging = tempfile::tempdir_in(&self.cache_dir)?;
+        let clone_dir = staging.path().join("checkout");
+
+        run_git(url, None, &["clone", "--quiet", "--", url, &clone_dir.to_string_lossy()]).await?;
+        run_git(url, Some(&clone_dir), &["checkout", "--quiet", "--detach", &commit]).await?;
+
+        // An abbreviated commit is only known in full once it is cloned.
+        let commit = run_git(url, Some(&clone_dir), &["rev-parse", "HEAD"]).await?;
+        let checkout = checkout.with_file_name(&commit);
+
+        fs::remove_dir_all(clone_dir.join(".git")).await?;
+        if !checkout.exists() {
+            fs::create_dir_all(checkout.parent().unwrap_or(&self.cache_dir)).await?;
+            fs::rename(&clone_dir, &checkout).await?;
+        }
+
+        Ok((checkout, commit))
+    }
+
+    async fn resolve_commit(&self, url: &str, reference: &GitReference) -> Result<String, FetchError> {
+        if let GitReference::Committish(committish) = reference {
+            if is_commit_hash(committish) {
+                return Ok(committish.to_lowercase());
+            }
+        }
+
+        let refs = ls_remote(url).await?;
+        let not_found = |reference: String| FetchError::ReferenceNotFound {
+            url: url.to_string(),
+            reference,
+        };
+
+        match reference {
+            GitReference::Head => refs.get("HEAD").cloned().ok_or_else(|| not_found("HEAD".to_string())),
+            GitReference::Committish(name) => [format!("refs/tags/{}", name), format!("refs/heads/{}", name)]
+                .iter()
+                .find_map(|r| refs.get(r).cloned())
+                // Abbreviated commits are expanded after cloning.
+                .or_else(|| is_abbreviated_hash(name).then(|| name.to_lowercase()))
+                .ok_or_else(|| not_found(name.clone())),
+            GitReference::Semver(version_req) => refs
+                .iter()
+                .filter_map(|(name, commit)| {
+                    let tag = name.strip_prefix("refs/tags/")?;
+                    let version = Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()?;
+                    version_req.matches(&version).then_some((version, commit))
+                })
+                .max_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, commit)| commit.clone())
+                .ok_or_else(|| not_found(format!("semver:{}", version_req))),
+        }
+    }
+}
+
+pub(super) fn is_commit_hash(committish: &str) -> bool {
+    committish.len() == 40 && is_abbreviated_hash(committish)
+}
+
+fn is_abbreviated_hash(committish: &str) -> bool {
+    (7..=40).contains(&committish.len()) && committish.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Maps ref names to commits. Annotated tags are reported by their peeled
+/// `^{}` entry, which names the commit rather than the tag object.
+async fn ls_remote(url: &str) -> Result<HashMap<String, String>, FetchError> {
+    let output = run_git(url, None, &["ls-remote", "--", url]).await?;
+    let mut refs = HashMap::new();
+    let mut peeled = HashMap::new();
+
+    for line in output.lines() {
+        let Some((commit, name)) = line.split_once('\t') else {
+            continue;
+        };
+        match name.strip_suffix("^{}") {
+            Some(tag) => peeled.insert(tag.to_string(), commit.to_string()),
+            None => refs.insert(name.to_string(), commit.to_string()),
+        };
+    }
+
+    refs.extend(peeled);
+    Ok(refs)
+}
+
+async fn run_git(url: &str, dir: Option<&Path>, args: &[&str]) -> Result<String, FetchError> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    // Never block on a credential prompt in the middle of an install.
+    command.args(args).env("GIT_TERMINAL_PROMPT", "0");
+
+    let git_error = |details: String| FetchError::GitError {
+        command: args[0].to_string(),
+        url: url.to_string(),
+        details,
+    };
+
+    let output = command.output().await.map_err(|e| git_error(e.to_string()))?;
+    if !output.status.success() {
+        return Err(git_error(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::create_bare_repo;
+    use semver::VersionReq;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_fetch_semver_range() {
+        let temp_dir = tempdir().unwrap();
+        let (url, commits) = create_bare_repo(temp_dir.path()).await;
+        let fetcher = GitFetcher::new(temp_dir.path().join("cache"));
+
+        let reference = GitReference::Semver(VersionReq::parse("^1.0.0").unwrap());
+        let (checkout, commit) = fetcher.fetch(&url, &reference).await.unwrap();
+
+        assert_eq!(commit, commits["v1.1.0"]);
+        let manifest = fs::read_to_string(checkout.join("package.json")).await.unwrap();
+        assert!(manifest.contains("1.1.0"));
+        assert!(!checkout.join(".git").exists());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_tag_head_and_cached_commit() {
+        let temp_dir = tempdir().unwrap();
+        let (url, commits) = create_bare_repo(temp_dir.path()).await;
+        let fetcher = GitFetcher::new(temp_dir.path().join("cache"));
+
+        let (_, commit) = fetcher
+            .fetch(&url, &GitReference::Committish("v1.0.0".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(commit, commits["v1.0.0"]);
+
+        let (_, head) = fetcher.fetch(&url, &GitReference::Head).await.unwrap();
+        assert_eq!(head, commits["v2.0.0"]);
+
+        // A pinned commit is served from the cache even if the remote is gone.
+        fs::remove_dir_all(temp_dir.path().join("lib.git")).await.unwrap();
+        let (checkout, pinned) = fetcher
+            .fetch(&url, &GitReference::Committish(commits["v1.0.0"].clone()))
+            .await
+            .unwrap();
+        assert_eq!(pinned, commits["v1.0.0"]);
+        assert!(checkout.join("package.json").is_file());
+    }
+
+    #[tokio::test]
+    async fn test_missing_reference() {
+        let temp_dir = tempdir().unwrap();
+        let (url, _) = create_bare_repo(temp_dir.path()).await;
+        let fetcher = GitFetcher::new(temp_dir.path().join("cache"));
+
+        let result = fetcher
+            .fetch(&url, &GitReference::Semver(VersionReq::parse("^3.0.0").unwrap()))
+            .await;
+        assert!(matches!(result, Err(FetchError::ReferenceNotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_reject_option_like_url() {
+        let temp_dir = tempdir().unwrap();
+        let fetcher = GitFetcher::new(temp_dir.path().join("cache"));
+        let marker = temp_dir.path().join("pwned");
+        let url = format!("--upload-pack=touch {}", marker.display());
+
+        let result = fetcher.fetch(&url, &GitReference::Head).await;
+        assert!(matches!(result, Err(FetchError::InvalidGitUrl(_))));
+        assert!(!marker.exists());
+    }
+}
@@ -0,0 +1,98 @@
+use super::TarballFetcher;
+use crate::error::FetchError;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+
+/// Fetches `file:` and `link:` dependencies from the local filesystem.
+pub struct LocalFetcher {
+    project_root: PathBuf,
+}
+
+impl LocalFetcher {
+    pub fn new(project_root: &Path) -> Self {
+        Self {
+            project_root: project_root.to_path_buf(),
+        }
+    }
+
+    /// Returns the package directory, the lockfile pin, written relative to
+    /// the project root so the lockfile stays portable, and for a path to a
+    /// packed tarball, which is unpacked first, its integrity.
+    pub async fn fetch(
+        &self,
+        path: &Path,
+        tarballs: &TarballFetcher,
+        protocol: &str,
+    ) -> Result<(PathBuf, String, String), FetchError> {
+        let location = fs::canonicalize(path).await.map_err(|e| FetchError::InvalidPackage {
+            path: path.to_path_buf(),
+            details: e.to_string(),
+        })?;
+        let root = fs::canonicalize(&self.project_root).await?;
+        let resolved = format!("{}{}", protocol, relative_path(&root, &location).display());
+
+        if location.is_file() {
+            let (unpacked, integrity) = tarballs.fetch_file(&location).await?;
+            return Ok((unpacked, resolved, integrity));
+        }
+
+        Ok((location, resolved, String::new()))
+    }
+}
+
+fn relative_path(base: &Path, target: &Path) -> PathBuf {
+    let base: Vec<Component> = base.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    let common = base.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &target[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+/// Recursively copies a package directory, skipping `node_modules` and VCS
+/// metadata.
+pub fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "node_modules" || name == ".git" {
+            continue;
+        }
+
+        let file_type = entry.file_type()?;
+        let target = to.join(&name);
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(
+            relative_path(Path::new("/repo/app"), Path::new("/repo/lib")),
+            PathBuf::from("../lib")
+        );
+        assert_eq!(
+            relative_path(Path::new("/repo"), Path::new("/repo/packages/lib")),
+            PathBuf::from("packages/lib")
+        );
+    }
+}
@@ -0,0 +1,214 @@
+mod git;
+mod local;
+mod tarball;
+
+pub use git::GitFetcher;
+pub use local::{copy_dir, LocalFetcher};
+pub use tarball::TarballFetcher;
+
+use crate::dependency::{Dependency, DependencySource, GitReference};
+use crate::error::FetchError;
+use crate::lockfile::LockFile;
+use crate::package::PackageJson;
+use crate::registry::RegistryClient;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A non-registry dependency that has been fetched to a local directory.
+#[derive(Debug, Clone)]
+pub struct FetchedPackage {
+    /// Name it is installed under in `node_modules`.
+    pub name: String,
+    pub version: String,
+    /// The original `package.json` specifier.
+    pub from: String,
+    /// Precise pin recorded in the lockfile: commit, tarball URL or path.
+    pub resolved: String,
+    pub integrity: String,
+    pub location: PathBuf,
+    /// Symlink `location` instead of copying it.
+    pub link: bool,
+    /// The package's manifest, without `devDependencies`.
+    pub manifest: PackageJson,
+}
+
+impl FetchedPackage {
+    pub fn dependencies(&self) -> HashMap<String, String> {
+        self.manifest.dependencies.clone().unwrap_or_default()
+    }
+}
+
+/// Dispatches each kind of `DependencySource` to its fetcher.
+pub struct Fetcher {
+    registry: Arc<RegistryClient>,
+    git: GitFetcher,
+    tarball: TarballFetcher,
+    local: LocalFetcher,
+    pins: HashMap<String, Pin>,
+}
+
+/// What the lockfile recorded for a dependency, and the specifier it was
+/// recorded for.
+struct Pin {
+    from: String,
+    resolved: String,
+    integrity: String,
+}
+
+impl Fetcher {
+    pub fn new(registry: Arc<RegistryClient>, cache_dir: &Path, project_root: &Path) -> Self {
+        Self {
+            registry,
+            git: GitFetcher::new(cache_dir.join("git")),
+            tarball: TarballFetcher::new(cache_dir.join("tarballs")),
+            local: LocalFetcher::new(project_root),
+            pins: HashMap::new(),
+        }
+    }
+
+    /// Fetches the commit or tarball `lockfile` pinned for each dependency
+    /// whose specifier hasn't changed since, rather than resolving it again.
+    pub fn with_lockfile(mut self, lockfile: &LockFile) -> Self {
+        for (name, entry) in lockfile.dependencies() {
+            if let Some(from) = entry.from() {
+                self.pins.insert(name.clone(), Pin {
+                    from: from.to_string(),
+                    resolved: entry.resolved().to_string(),
+                    integrity: entry.integrity().to_string(),
+                });
+            }
+        }
+        self
+    }
+
+    /// The lockfile's pin for `dependency`, if it was made for the same
+    /// specifier.
+    fn pin(&self, dependency: &Dependency) -> Option<&Pin> {
+        self.pins.get(&dependency.name).filter(|pin| pin.from == dependency.spec)
+    }
+
+    /// The locked tarball URL and integrity, when the lockfile has them.
+    fn pinned_tarball(&self, dependency: &Dependency) -> Option<(&str, &str)> {
+        self.pin(dependency)
+            .filter(|pin| tarball::is_integrity(&pin.integrity))
+            .map(|pin| (pin.resolved.as_str(), pin.integrity.as_str()))
+    }
+
+    pub async fn fetch(&self, dependency: &Dependency) -> Result<FetchedPackage, FetchError> {
+        let pinned = self.pinned_tarball(dependency);
+        let (location, resolved, integrity, link) = match &dependency.source {
+            DependencySource::Registry(_) => {
+                return Err(FetchError::RegistryDependency(dependency.name.clone()));
+            }
+            DependencySource::Alias { .. } | DependencySource::Tarball(_) if pinned.is_some() => {
+                let (url, integrity) = pinned.unwrap_or_default();
+                let (location, integrity) = self.tarball.fetch_pinned(url, integrity).await?;
+                (location, url.to_string(), integrity, false)
+            }
+            DependencySource::Alias { package, version_req } => {
+                let packument = self.registry.fetch_packument(package).await?;
+                let info = packument.max_satisfying(version_req).ok_or_else(|| FetchError::NoMatchingVersion {
+                    package: package.clone(),
+                    requirement: version_req.to_string(),
+                })?;
+                let (location, integrity) = self
+                    .tarball
+                    .fetch(&info.dist.tarball, Some(&info.dist.shasum))
+                    .await?;
+                (location, info.dist.tarball.clone(), integrity, false)
+            }
+            DependencySource::Git { url, reference } => {
+                let locked = self.pin(dependency).and_then(|pin| locked_commit(url, &pin.resolved));
+                let reference = match locked {
+                    Some(commit) => &GitReference::Committish(commit.to_string()),
+                    None => reference,
+                };
+                let (location, commit) = self.git.fetch(url, reference).await?;
+                (location, format!("git+{}#{}", url, commit), String::new(), false)
+            }
+            DependencySource::Tarball(url) => {
+                let (location, integrity) = self.tarball.fetch(url, None).await?;
+                (location, url.clone(), integrity, false)
+            }
+            DependencySource::File(path) => {
+                let (location, resolved, integrity) = self.local.fetch(path, &self.tarball, "file:").await?;
+                (location, resolved, integrity, false)
+            }
+            DependencySource::Link(path) => {
+                let (location, resolved, integrity) = self.local.fetch(path, &self.tarball, "link:").await?;
+                (location, resolved, integrity, true)
+            }
+        };
+
+        let manifest_path = location.join("package.json");
+        let mut manifest = PackageJson::load_from(&manifest_path)
+            .await
+            .map_err(|e| FetchError::InvalidPackage {
+                path: manifest_path,
+                details: e.to_string(),
+            })?;
+        manifest.dev_dependencies = None;
+
+        Ok(FetchedPackage {
+            name: dependency.name.clone(),
+            version: manifest.version.clone(),
+            from: dependency.spec.clone(),
+            resolved,
+            integrity,
+            location,
+            link,
+            manifest,
+        })
+    }
+}
+
+/// The commit in a `git+<url>#<commit>` pin recorded for `url`.
+fn locked_commit<'a>(url: &str, resolved: &'a str) -> Option<&'a str> {
+    let commit = resolved.strip_prefix("git+")?.strip_prefix(url)?.strip_prefix('#')?;
+    git::is_commit_hash(commit).then_some(commit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{create_bare_repo, lockfile};
+    use tempfile::tempdir;
+
+    fn dependency(name: &str, spec: &str) -> Dependency {
+        Dependency {
+            name: name.to_string(),
+            spec: spec.to_string(),
+            source: DependencySource::parse(name, spec).unwrap(),
+            resolved_version: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_locked_commit() {
+        let temp_dir = tempdir().unwrap();
+        let (url, commits) = create_bare_repo(temp_dir.path()).await;
+        let spec = format!("git+{}", url);
+        let locked = lockfile(serde_json::json!({
+            "lib": {
+                "version": "1.0.0",
+                "resolved": format!("git+{}#{}", url, commits["v1.0.0"]),
+                "integrity": "",
+                "requires": null,
+                "from": spec,
+            }
+        }));
+        let fetcher = Fetcher::new(Arc::new(RegistryClient::new()), &temp_dir.path().join("cache"), temp_dir.path())
+            .with_lockfile(&locked);
+
+        // HEAD has moved on to v2.0.0 since the lockfile was written.
+        let package = fetcher.fetch(&dependency("lib", &spec)).await.unwrap();
+        assert_eq!(package.resolved, format!("git+{}#{}", url, commits["v1.0.0"]));
+        assert_eq!(package.version, "1.0.0");
+
+        // A changed specifier is resolved again.
+        let spec = format!("git+{}#v1.1.0", url);
+        let package = fetcher.fetch(&dependency("lib", &spec)).await.unwrap();
+        assert_eq!(package.resolved, format!("git+{}#{}", url, commits["v1.1.0"]));
+    }
+}
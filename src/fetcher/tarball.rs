@@ -0,0 +1,205 @@
+use crate::error::FetchError;
+use crate::verification::{ChecksumIntegrityChecker, Verification};
+use flate2::read::GzDecoder;
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use tar::Archive;
+use tokio::fs;
+
+/// Downloads and unpacks package tarballs. Unpacked packages are stored by
+/// the SHA-256 of the tarball, which is also the integrity pinned in the
+/// lockfile.
+pub struct TarballFetcher {
+    client: Client,
+    cache_dir: PathBuf,
+}
+
+impl TarballFetcher {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self {
+            client: Client::new(),
+            cache_dir,
+        }
+    }
+
+    pub async fn fetch(&self, url: &str, expected_shasum: Option<&str>) -> Result<(PathBuf, String), FetchError> {
+        let data = self.download(url).await?;
+
+        if let Some(expected) = expected_shasum {
+            ChecksumIntegrityChecker::verify_package(&data, expected).map_err(|e| FetchError::DownloadError {
+                url: url.to_string(),
+                details: e.to_string(),
+            })?;
+        }
+
+        self.unpack(data).await
+    }
+
+    /// Fetches a tarball the lockfile pinned to `integrity`, failing if it
+    /// has changed since. A copy already unpacked is used without
+    /// downloading it again.
+    pub async fn fetch_pinned(&self, url: &str, integrity: &str) -> Result<(PathBuf, String), FetchError> {
+        let cached = self.cache_dir.join(integrity);
+        if cached.join("package.json").is_file() {
+            return Ok((cached, integrity.to_string()));
+        }
+
+        let data = self.download(url).await?;
+        check_integrity(url, &data, integrity)?;
+        self.unpack(data).await
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, FetchError> {
+        let download_error = |details: String| FetchError::DownloadError {
+            url: url.to_string(),
+            details,
+        };
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| download_error(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(download_error(format!("HTTP {}", response.status())));
+        }
+        let data = response.bytes().await.map_err(|e| download_error(e.to_string()))?;
+        Ok(data.to_vec())
+    }
+
+    /// Unpacks a tarball from disk, e.g. a `file:../lib.tgz` dependency.
+    pub async fn fetch_file(&self, path: &Path) -> Result<(PathBuf, String), FetchError> {
+        let data = fs::read(path).await?;
+        self.unpack(data).await
+    }
+
+    async fn unpack(&self, data: Vec<u8>) -> Result<(PathBuf, String), FetchError> {
+        let integrity = hex::encode(Sha256::digest(&data));
+        let destination = self.cache_dir.join(&integrity);
+
+        if destination.join("package.json").is_file() {
+            return Ok((destination, integrity));
+        }
+
+        fs::create_dir_all(&self.cache_dir).await?;
+        let staging = tempfile::tempdir_in(&self.cache_dir)?;
+        let staging_path = staging.path().to_path_buf();
+
+        tokio::task::spawn_blocking(move || unpack_stripped(&data, &staging_path))
+            .await
+            .map_err(io::Error::other)??;
+
+        if !destination.exists() {
+            fs::rename(staging.into_path(), &destination).await?;
+        }
+
+        Ok((destination, integrity))
+    }
+}
+
+/// Fails unless `data` is what the lockfile pinned as `integrity`.
+pub fn check_integrity(url: &str, data: &[u8], integrity: &str) -> Result<(), FetchError> {
+    let actual = hex::encode(Sha256::digest(data));
+    if actual != integrity {
+        return Err(FetchError::IntegrityMismatch {
+            url: url.to_string(),
+            expected: integrity.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Whether `integrity` is a SHA-256 as `TarballFetcher` records it, and so
+/// safe to use as a cache directory name.
+pub fn is_integrity(integrity: &str) -> bool {
+    integrity.len() == 64 && integrity.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Unpacks `data` into `destination`, dropping the top-level directory
+/// (`package/` in npm tarballs) and refusing entries that escape it. Links
+/// are skipped like npm does: a symlink followed by an entry below it
+/// would otherwise write outside `destination`.
+fn unpack_stripped(data: &[u8], destination: &Path) -> io::Result<()> {
+    let mut archive = Archive::new(GzDecoder::new(data));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        let kind = entry.header().entry_type();
+        if kind.is_symlink() || kind.is_hard_link() {
+            log::debug!("Skipping link {} in tarball", path.display());
+            continue;
+        }
+
+        let mut components = path.components();
+        components.next();
+        let relative: PathBuf = components.collect();
+
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Refusing to unpack {}", path.display()),
+            ));
+        }
+
+        let target = destination.join(&relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&target)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tar::{Builder, EntryType, Header};
+    use tempfile::tempdir;
+
+    fn file_header(size: usize) -> Header {
+        let mut header = Header::new_gnu();
+        header.set_size(size as u64);
+        header.set_mode(0o644);
+        header
+    }
+
+    #[test]
+    fn test_unpack_skips_links() {
+        let temp_dir = tempdir().unwrap();
+        let outside = temp_dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let manifest = br#"{"name": "evil", "version": "1.0.0"}"#;
+        builder.append_data(&mut file_header(manifest.len()), "package/package.json", &manifest[..]).unwrap();
+        let mut link = file_header(0);
+        link.set_entry_type(EntryType::Symlink);
+        builder.append_link(&mut link, "package/escape", &outside).unwrap();
+        builder.append_data(&mut file_header(5), "package/escape/pwned", &b"pwned"[..]).unwrap();
+        let mut hard_link = file_header(0);
+        hard_link.set_entry_type(EntryType::Link);
+        builder.append_link(&mut hard_link, "package/hard", "/etc/passwd").unwrap();
+        let data = builder.into_inner().unwrap().finish().unwrap();
+
+        let destination = temp_dir.path().join("unpacked");
+        unpack_stripped(&data, &destination).unwrap();
+
+        assert!(destination.join("package.json").is_file());
+        assert!(!outside.join("pwned").exists());
+        // The entry below the skipped link lands in a plain directory.
+        assert!(std::fs::symlink_metadata(destination.join("escape")).unwrap().is_dir());
+        assert!(!destination.join("hard").exists());
+    }
+}
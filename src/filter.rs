@@ -0,0 +1,267 @@
+use crate::error::WorkspaceError;
+use crate::workspace::{Workspace, WorkspaceMember};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::process::Command;
+
+/// What a `--filter` selector matches before dependency expansion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterTarget {
+    /// Member names matching a glob, e.g. `@acme/*`.
+    Name(String),
+    /// Members with files changed since a git ref, written `[origin/main]`.
+    ChangedSince(String),
+}
+
+/// A workspace selector in pnpm syntax. `foo...` also selects everything
+/// `foo` depends on, `...foo` everything that depends on `foo`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceFilter {
+    pub target: FilterTarget,
+    pub with_dependencies: bool,
+    pub with_dependents: bool,
+}
+
+impl FromStr for WorkspaceFilter {
+    type Err = WorkspaceError;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        let invalid = |details: &str| WorkspaceError::InvalidFilter {
+            filter: filter.to_string(),
+            details: details.to_string(),
+        };
+
+        let (with_dependents, rest) = match filter.strip_prefix("...") {
+            Some(rest) => (true, rest),
+            None => (false, filter),
+        };
+        let (with_dependencies, rest) = match rest.strip_suffix("...") {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+
+        let target = if let Some(reference) = rest.strip_prefix('[') {
+            let reference = reference
+                .strip_suffix(']')
+                .ok_or_else(|| invalid("missing closing bracket"))?;
+            if reference.is_empty() {
+                return Err(invalid("empty git reference"));
+            }
+            FilterTarget::ChangedSince(reference.to_string())
+        } else {
+            if rest.is_empty() {
+                return Err(invalid("empty selector"));
+            }
+            glob::Pattern::new(rest).map_err(|e| invalid(&e.to_string()))?;
+            FilterTarget::Name(rest.to_string())
+        };
+
+        Ok(Self {
+            target,
+            with_dependencies,
+            with_dependents,
+        })
+    }
+}
+
+impl WorkspaceFilter {
+    async fn matches<'a>(&self, workspace: &'a Workspace) -> Result<Vec<&'a WorkspaceMember>, WorkspaceError> {
+        match &self.target {
+            FilterTarget::Name(pattern) => {
+                let pattern = glob::Pattern::new(pattern).map_err(|e| WorkspaceError::InvalidFilter {
+                    filter: pattern.clone(),
+                    details: e.to_string(),
+                })?;
+                Ok(workspace
+                    .members
+                    .iter()
+                    .filter(|m| pattern.matches(&m.name))
+                    .collect())
+            }
+            FilterTarget::ChangedSince(reference) => {
+                let changed = changed_files(workspace, reference).await?;
+                Ok(workspace
+                    .members
+                    .iter()
+                    .filter(|m| changed.iter().any(|path| path.starts_with(&m.relative_path)))
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Selects the members matched by any of `filters`, in workspace order.
+pub async fn apply_filters<'a>(
+    workspace: &'a Workspace,
+    filters: &[WorkspaceFilter],
+) -> Result<Vec<&'a WorkspaceMember>, WorkspaceError> {
+    let graph = workspace.dependency_graph();
+    let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (name, deps) in &graph {
+        for dep in deps {
+            dependents.entry(dep.as_str()).or_default().push(name.as_str());
+        }
+    }
+
+    let mut selected: HashSet<String> = HashSet::new();
+    for filter in filters {
+        for member in filter.matches(workspace).await? {
+            selected.insert(member.name.clone());
+            if filter.with_dependencies {
+                walk(&member.name, |name| graph.get(name).map(|d| d.iter().map(String::as_str).collect()), &mut selected);
+            }
+            if filter.with_dependents {
+                walk(&member.name, |name| dependents.get(name).cloned(), &mut selected);
+            }
+        }
+    }
+
+    Ok(workspace
+        .members
+        .iter()
+        .filter(|m| selected.contains(&m.name))
+        .collect())
+}
+
+fn walk<'g>(start: &str, edges: impl Fn(&str) -> Option<Vec<&'g str>>, selected: &mut HashSet<String>) {
+    let mut stack = vec![start.to_string()];
+    let mut visited = HashSet::new();
+
+    while let Some(name) = stack.pop() {
+        if !visited.insert(name.clone()) {
+            continue;
+        }
+        for next in edges(&name).unwrap_or_default() {
+            selected.insert(next.to_string());
+            stack.push(next.to_string());
+        }
+    }
+}
+
+/// Paths relative to the workspace root that differ from `reference`,
+/// including uncommitted changes and untracked files that aren't ignored.
+async fn changed_files(workspace: &Workspace, reference: &str) -> Result<Vec<PathBuf>, WorkspaceError> {
+    let mut changed = git_lines(workspace, reference, &["diff", "--name-only", "--relative", reference, "--"]).await?;
+    changed.extend(git_lines(workspace, reference, &["ls-files", "--others", "--exclude-standard"]).await?);
+    Ok(changed)
+}
+
+async fn git_lines(workspace: &Workspace, reference: &str, args: &[&str]) -> Result<Vec<PathBuf>, WorkspaceError> {
+    let git_error = |details: String| WorkspaceError::GitError {
+        reference: reference.to_string(),
+        details,
+    };
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(&workspace.root)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| git_error(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(git_error(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(PathBuf::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_manifest;
+    use tempfile::tempdir;
+    use tokio::fs;
+
+    async fn create_monorepo(root: &std::path::Path) -> Workspace {
+        write_manifest(root, r#"{"name": "root", "version": "0.0.0", "workspaces": ["packages/*"]}"#).await;
+        write_manifest(&root.join("packages/utils"), r#"{"name": "@acme/utils", "version": "1.0.0"}"#).await;
+        write_manifest(
+            &root.join("packages/core"),
+            r#"{"name": "@acme/core", "version": "1.0.0", "dependencies": {"@acme/utils": "workspace:*"}}"#,
+        )
+        .await;
+        write_manifest(
+            &root.join("packages/web"),
+            r#"{"name": "web", "version": "1.0.0", "dependencies": {"@acme/core": "^1.0.0"}}"#,
+        )
+        .await;
+        Workspace::discover(root).await.unwrap().unwrap()
+    }
+
+    fn names(members: &[&WorkspaceMember]) -> Vec<String> {
+        members.iter().map(|m| m.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        let filter: WorkspaceFilter = "...@acme/*".parse().unwrap();
+        assert_eq!(filter.target, FilterTarget::Name("@acme/*".to_string()));
+        assert!(filter.with_dependents && !filter.with_dependencies);
+
+        let filter: WorkspaceFilter = "[origin/main]...".parse().unwrap();
+        assert_eq!(filter.target, FilterTarget::ChangedSince("origin/main".to_string()));
+        assert!(filter.with_dependencies && !filter.with_dependents);
+
+        assert!("[origin/main".parse::<WorkspaceFilter>().is_err());
+        assert!("...".parse::<WorkspaceFilter>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_name_filters() {
+        let temp_dir = tempdir().unwrap();
+        let workspace = create_monorepo(temp_dir.path()).await;
+
+        let by_glob = apply_filters(&workspace, &["@acme/*".parse().unwrap()]).await.unwrap();
+        assert_eq!(names(&by_glob), vec!["@acme/core", "@acme/utils"]);
+
+        let with_deps = apply_filters(&workspace, &["web...".parse().unwrap()]).await.unwrap();
+        assert_eq!(names(&with_deps), vec!["@acme/core", "@acme/utils", "web"]);
+
+        let with_dependents = apply_filters(&workspace, &["...@acme/core".parse().unwrap()]).await.unwrap();
+        assert_eq!(names(&with_dependents), vec!["@acme/core", "web"]);
+    }
+
+    async fn git(root: &std::path::Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["-c", "user.name=rpm", "-c", "user.email=rpm@example.com"])
+            .args(args)
+            .output()
+            .await
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[tokio::test]
+    async fn test_apply_changed_since_filter() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let workspace = create_monorepo(root).await;
+        git(root, &["init", "-q"]).await;
+        git(root, &["add", "-A"]).await;
+        git(root, &["commit", "-q", "-m", "initial"]).await;
+
+        let unchanged = apply_filters(&workspace, &["[HEAD]".parse().unwrap()]).await.unwrap();
+        assert!(unchanged.is_empty());
+
+        // A modified tracked file and a new untracked one both count.
+        fs::write(root.join("packages/utils/index.js"), "").await.unwrap();
+        git(root, &["add", "packages/utils/index.js"]).await;
+        fs::write(root.join("packages/web/index.js"), "").await.unwrap();
+        let changed = apply_filters(&workspace, &["[HEAD]".parse().unwrap()]).await.unwrap();
+        assert_eq!(names(&changed), vec!["@acme/utils", "web"]);
+
+        let with_dependents = apply_filters(&workspace, &["...[HEAD]".parse().unwrap()]).await.unwrap();
+        assert_eq!(names(&with_dependents), vec!["@acme/core", "@acme/utils", "web"]);
+
+        assert!(apply_filters(&workspace, &["[no-such-ref]".parse().unwrap()]).await.is_err());
+    }
+}
@@ -1,17 +1,28 @@
 use crate::{
+    audit::AdvisorySource,
+    dependency::{placements, Dependency, DependencyResolver, Layout},
     error::{RpmError, RpmResult},
-    package::Package,
+    fetcher::{copy_dir, FetchedPackage, Fetcher},
+    lockfile::{LockFile, LOCKFILE_NAME},
+    package::{Package, PackageJson},
+    provenance::{check_provenance, in_scopes, Provenance, TrustRoot},
     registry::RegistryClient,
-    verification::{ChecksumIntegrityChecker, Verification},
+    sandbox::Sandbox,
+    verification::{verify_integrity, ChecksumIntegrityChecker, RegistryKeys, SignatureStatus, Verification, VerificationError},
     cache::PackageCache,
     profiling::MemoryProfile,
+    tree::{location, Importer, InstalledTree, Omit},
+    workspace::{symlink_dir, Workspace},
 };
 use anyhow::Result;
 use flate2::read::GzDecoder;
 use futures::future::try_join_all;
+use futures::StreamExt;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use reqwest::Client;
-use std::path::PathBuf;
+use semver::VersionReq;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tar::Archive;
 use tokio::fs;
@@ -27,14 +38,22 @@ pub struct PackageInstaller {
     concurrent_limit: Arc<Semaphore>,
     cache: PackageCache,
     memory_profile: MemoryProfile,
+    omit: Omit,
+    advisories: Option<Arc<AdvisorySource>>,
+    require_provenance: Vec<String>,
+    verify_signatures: bool,
+    /// Install from the lockfile when it still satisfies the manifests.
+    use_lockfile: bool,
 }
 
 impl PackageInstaller {
-    pub fn new(global: bool, cache: PackageCache, memory_profile: MemoryProfile) -> Self {
+    /// Local installs go into `root/node_modules`; `root` is ignored for
+    /// global ones.
+    pub fn new(global: bool, root: &Path, cache: PackageCache, memory_profile: MemoryProfile) -> Self {
         let install_path = if global {
             PathBuf::from("/usr/local/lib/node_modules")
         } else {
-            PathBuf::from("node_modules")
+            root.join("node_modules")
         };
 
         Self {
@@ -45,65 +64,454 @@ impl PackageInstaller {
             concurrent_limit: Arc::new(Semaphore::new(8)),
             cache,
             memory_profile,
+            omit: Omit::default(),
+            advisories: None,
+            require_provenance: Vec::new(),
+            verify_signatures: true,
+            use_lockfile: true,
         }
     }
 
-    pub async fn install_packages(&self, packages: &[String]) -> Result<()> {
+    /// Uses `registry` instead of the public npm registry.
+    pub fn with_registry(mut self, registry: Arc<RegistryClient>) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Leaves dependency types out of project installs. They are still
+    /// resolved and locked, so installing them later is done from the
+    /// lockfile.
+    pub fn with_omit(mut self, omit: Omit) -> Self {
+        self.omit = omit;
+        self
+    }
+
+    /// Prefers versions no advisory from `source` affects when resolving a
+    /// project, and warns about packages where every version in range is
+    /// affected.
+    pub fn with_advisories(mut self, source: AdvisorySource) -> Self {
+        self.advisories = Some(Arc::new(source));
+        self
+    }
+
+    /// Fails installs of packages from `scopes`, e.g. `@myorg`, that have
+    /// no provenance attestation that verifies.
+    pub fn with_required_provenance(mut self, scopes: Vec<String>) -> Self {
+        self.require_provenance = scopes;
+        self
+    }
+
+    /// Whether registry signatures are checked before installing. Turning
+    /// it off is the way to install from a registry whose keys can't be
+    /// reached, or packages it publishes unsigned.
+    pub fn with_signature_verification(mut self, verify: bool) -> Self {
+        self.verify_signatures = verify;
+        self
+    }
+
+    /// Resolves every dependency of a project again rather than installing
+    /// what the lockfile recorded, e.g. once ranges have been rewritten.
+    pub fn with_fresh_resolution(mut self) -> Self {
+        self.use_lockfile = false;
+        self
+    }
+
+    /// Installs the `latest` version of each package at the top level. With
+    /// advisories, an affected `latest` gives way to the newest release
+    /// below it that none of them affect.
+    pub async fn install_packages(&self, packages: &[String]) -> Result<Vec<Package>> {
+        let latest = try_join_all(
+            packages
+                .iter()
+                .map(|package| self.registry.fetch_package_info(package, None)),
+        )
+        .await?;
+        let resolved = match &self.advisories {
+            Some(source) => self.prefer_safe(latest, source).await?,
+            None => latest,
+        };
+
+        self.verify_provenance(resolved.iter()).await?;
+        self.install_resolved(resolved.clone()).await?;
+        Ok(resolved)
+    }
+
+    async fn prefer_safe(&self, latest: Vec<Package>, source: &Arc<AdvisorySource>) -> Result<Vec<Package>> {
+        let resolver = DependencyResolver::new(Arc::clone(&self.registry)).with_advisories(Arc::clone(source));
+        let resolved = try_join_all(latest.iter().map(|package| async {
+            let version_req = VersionReq::parse(&format!("<={}", package.version))?;
+            anyhow::Ok(resolver.resolve_version(&package.name, &version_req).await?)
+        }))
+        .await?;
+
+        let mut packages = Vec::new();
+        for (package, advisories) in resolved {
+            if !advisories.is_empty() {
+                let ids: Vec<&str> = advisories.iter().map(|advisory| advisory.id.as_str()).collect();
+                log::warn!(
+                    "No safe version of {} is published, installing {} affected by {}",
+                    package.name,
+                    package.version,
+                    ids.join(", ")
+                );
+            }
+            packages.push(package);
+        }
+        Ok(packages)
+    }
+
+    /// Resolves and installs every dependency declared by `manifests`, each
+    /// paired with its directory and the project root first. Workspace
+    /// members are linked and the lockfile is written at `root`. A lockfile
+    /// that still satisfies the manifests is installed as it is, unless
+    /// advisories may prefer other versions.
+    pub async fn install_project(
+        &self,
+        root: &Path,
+        manifests: &[(&Path, &PackageJson)],
+        workspace: Option<Arc<Workspace>>,
+    ) -> Result<()> {
+        let (_, root_manifest) = manifests[0];
+        let mut resolver = DependencyResolver::new(Arc::clone(&self.registry)).with_overrides(&root_manifest.overrides())?;
+        if let Some(workspace) = &workspace {
+            resolver = resolver.with_workspace(Arc::clone(workspace));
+        }
+        if let Some(source) = &self.advisories {
+            resolver = resolver.with_advisories(Arc::clone(source));
+        }
+
+        let lockfile_path = root.join(LOCKFILE_NAME);
+        let previous = if lockfile_path.exists() { Some(LockFile::load(&lockfile_path).await?) } else { None };
+        let fetched = self.fetch_external(&resolver, root, manifests, previous.as_ref()).await?;
+
+        // Registry dependencies of workspace members and fetched packages are
+        // resolved together with the project's own.
+        let mut importers: Vec<(&str, &PackageJson)> =
+            manifests[1..].iter().map(|(_, manifest)| (manifest.name.as_str(), *manifest)).collect();
+        importers.extend(fetched.iter().map(|package| (package.name.as_str(), &package.manifest)));
+        let locked = previous.filter(|previous| {
+            let importers = importers.iter().map(|(name, manifest)| Importer {
+                name: name.to_string(),
+                ..Importer::from_manifest(manifest)
+            });
+            let importers = std::iter::once(Importer::from_manifest(root_manifest)).chain(importers).collect();
+            self.use_lockfile
+                && self.advisories.is_none()
+                && InstalledTree::new(previous, importers).is_current(workspace.as_deref(), &root_manifest.overrides())
+        });
+
+        let (layout, provenance) = match &locked {
+            Some(previous) => {
+                log::debug!("{} is up to date, installing it without resolving", LOCKFILE_NAME);
+                let layout = previous.layout()?;
+                let provenance = self.locked_provenance(previous, &layout)?;
+                (layout, provenance)
+            }
+            None => {
+                let graph = resolver.resolve_manifests(root_manifest, &importers).await?;
+                let mut vulnerable: Vec<_> = graph.vulnerable.iter().collect();
+                vulnerable.sort_by(|a, b| a.0.cmp(b.0));
+                for (package, advisories) in vulnerable {
+                    let ids: Vec<&str> = advisories.iter().map(|advisory| advisory.id.as_str()).collect();
+                    log::warn!(
+                        "No safe version of {} is in range, installing it affected by {}",
+                        package,
+                        ids.join(", ")
+                    );
+                }
+                let layout = graph.layout();
+                let packages = placements(&layout).into_iter().map(|(_, package)| package);
+                let provenance = self.verify_provenance(packages).await?;
+                (layout, provenance)
+            }
+        };
+
+        // The lockfile always records the full graph; omitted dependency
+        // types are only left out of `node_modules`.
+        let mut lockfile = LockFile::from_resolved(root_manifest, &layout, &fetched, workspace.as_deref());
+        lockfile.record_provenance(&provenance);
+        let importers: Vec<Importer> = manifests.iter().map(|(_, manifest)| Importer::from_manifest(manifest)).collect();
+        let wanted: HashSet<PathBuf> = InstalledTree::new(&lockfile, importers)
+            .reachable(|kind| self.omit.includes(kind))
+            .iter()
+            .map(|path| PathBuf::from(location(path)))
+            .collect();
+        let is_wanted = |path: &Path| wanted.contains(&Path::new("node_modules").join(path));
+
+        let placed: Vec<(PathBuf, Package)> = placements(&layout)
+            .into_iter()
+            .filter(|(path, _)| is_wanted(path))
+            .map(|(path, package)| (path, package.clone()))
+            .collect();
+        let fetched_wanted: Vec<FetchedPackage> = fetched
+            .iter()
+            .filter(|package| is_wanted(Path::new(&package.name)))
+            .cloned()
+            .collect();
+        let installed = placed.len() + fetched_wanted.len();
+
+        // Importers go in first, since copies may be nested inside them.
+        self.install_fetched(&fetched_wanted).await?;
+        if let Some(workspace) = &workspace {
+            workspace.link_members().await?;
+        }
+        if locked.is_some() {
+            self.install_verified(placed).await?;
+        } else {
+            self.install_placed(placed).await?;
+        }
+
+        lockfile.save(&lockfile_path).await?;
+
+        log::info!("Installed {} packages", installed);
+        Ok(())
+    }
+
+    async fn fetch_external(
+        &self,
+        resolver: &DependencyResolver,
+        root: &Path,
+        manifests: &[(&Path, &PackageJson)],
+        lockfile: Option<&LockFile>,
+    ) -> Result<Vec<FetchedPackage>> {
+        let mut fetcher = Fetcher::new(Arc::clone(&self.registry), self.cache.cache_dir(), root);
+        if let Some(lockfile) = lockfile {
+            fetcher = fetcher.with_lockfile(lockfile);
+        }
+        let mut pending: Vec<Dependency> = Vec::new();
+        for (dir, manifest) in manifests {
+            pending.extend(resolver.collect_external_dependencies(dir, manifest)?);
+        }
+
+        // Fetched packages may declare git or local dependencies of their own.
+        let mut fetched: Vec<FetchedPackage> = Vec::new();
+        while !pending.is_empty() {
+            let batch = try_join_all(pending.iter().map(|dependency| fetcher.fetch(dependency))).await?;
+            pending.clear();
+
+            for package in &batch {
+                for dependency in resolver.collect_external_dependencies(&package.location, &package.manifest)? {
+                    let known = fetched.iter().chain(&batch).any(|p| p.name == dependency.name)
+                        || pending.iter().any(|d| d.name == dependency.name);
+                    if !known {
+                        pending.push(dependency);
+                    }
+                }
+            }
+            fetched.extend(batch);
+        }
+
+        Ok(fetched)
+    }
+
+    /// Copies fetched packages into `node_modules`, or symlinks them for
+    /// `link:` dependencies.
+    pub async fn install_fetched(&self, packages: &[FetchedPackage]) -> Result<()> {
+        for package in packages {
+            let destination = self.install_path.join(&package.name);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+
+            match fs::symlink_metadata(&destination).await {
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&destination).await?,
+                Ok(_) => fs::remove_file(&destination).await?,
+                Err(_) => {}
+            }
+
+            if package.link {
+                symlink_dir(&package.location, &destination).await?;
+            } else {
+                let (from, to) = (package.location.clone(), destination.clone());
+                tokio::task::spawn_blocking(move || copy_dir(&from, &to)).await??;
+            }
+
+            log::debug!("Installed {} from {}", package.name, package.resolved);
+        }
+
+        Ok(())
+    }
+
+    /// Installs packages whose versions have already been resolved, each at
+    /// the top level of `node_modules`.
+    pub async fn install_resolved(&self, packages: Vec<Package>) -> Result<()> {
+        let placed = packages
+            .into_iter()
+            .map(|package| (PathBuf::from(&package.name), package))
+            .collect();
+        self.install_placed(placed).await
+    }
+
+    /// Installs each package into its directory relative to `node_modules`,
+    /// as laid out by `ResolvedGraph::layout`.
+    pub async fn install_placed(&self, packages: Vec<(PathBuf, Package)>) -> Result<()> {
+        self.verify_signatures(packages.iter().map(|(_, package)| package)).await?;
+        self.install_verified(packages).await
+    }
+
+    /// Like `install_placed`, for packages whose registry signatures were
+    /// already checked, such as those installed from the lockfile: the locked
+    /// shasum pins the tarball that was checked.
+    async fn install_verified(&self, packages: Vec<(PathBuf, Package)>) -> Result<()> {
         fs::create_dir_all(&self.install_path).await?;
 
         let m = MultiProgress::new();
         let total_progress = m.add(ProgressBar::new(packages.len() as u64));
         total_progress.set_style(ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})")
+            .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})")?
             .progress_chars("#>-"));
 
-        let tasks: Vec<_> = packages.iter().map(|package| {
+        let package_style = ProgressStyle::default_bar()
+            .template("{spinner:.green} {msg} [{wide_bar:.cyan/blue}] {pos}/{len}")?
+            .progress_chars("#>-");
+        let tasks: Vec<_> = packages.into_iter().map(|(path, package)| {
             let installer = self.clone();
-            let package = package.clone();
             let pb = m.add(ProgressBar::new(4)); // Download, Verify, Extract, Scripts
-            pb.set_style(ProgressStyle::default_bar()
-                .template("{spinner:.green} {msg} [{wide_bar:.cyan/blue}] {pos}/{len}")
-                .progress_chars("#>-"));
-            pb.set_message(format!("Installing {}", package));
+            pb.set_style(package_style.clone());
+            pb.set_message(format!("Installing {}", package.name));
 
             tokio::spawn(async move {
                 let _permit = installer.concurrent_limit.acquire().await?;
-                let result = installer.install_package(&package, pb.clone()).await;
+                let result = installer.install_package(&package, &path, pb.clone()).await;
                 pb.finish_and_clear();
                 result
             })
         }).collect();
 
-        let handle = tokio::spawn(async move {
-            m.join().unwrap();
-        });
-
         let results = try_join_all(tasks).await?;
         for result in results {
             result?;
             total_progress.inc(1);
         }
 
-        handle.await?;
         total_progress.finish_with_message("All packages installed successfully!");
         Ok(())
     }
 
-    async fn install_package(&self, package_name: &str, progress: ProgressBar) -> Result<()> {
+    /// The provenance of each distinct package, keyed by `name@version`.
+    /// Fails when a package from a `require_provenance` scope has no
+    /// verified provenance; elsewhere, attestations that don't verify or
+    /// can't be fetched are only warned about.
+    async fn verify_provenance<'a>(
+        &self,
+        packages: impl Iterator<Item = &'a Package>,
+    ) -> Result<HashMap<String, Provenance>> {
+        let packages: BTreeMap<String, &Package> = packages
+            .map(|package| (format!("{}@{}", package.name, package.version), package))
+            .collect();
+        if packages.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let trust_root = TrustRoot::bundled()?;
+        let checked = futures::future::join_all(packages.values().map(|package| async {
+            check_provenance(&self.registry, &trust_root, package)
+                .await
+                .unwrap_or_else(|e| Provenance::Unavailable { reason: format!("couldn't fetch attestations: {}", e) })
+        }))
+        .await;
+
+        let mut missing = Vec::new();
+        for ((key, package), provenance) in packages.iter().zip(&checked) {
+            let reason = match provenance {
+                Provenance::Verified { .. } => continue,
+                Provenance::Unattested => "no provenance attestation".to_string(),
+                Provenance::Invalid { reason } | Provenance::Unavailable { reason } => reason.clone(),
+            };
+            if in_scopes(&package.name, &self.require_provenance) {
+                missing.push(format!("{} ({})", key, reason));
+            } else if let Provenance::Invalid { reason } = provenance {
+                log::warn!("The provenance of {} doesn't verify: {}", key, reason);
+            } else if let Provenance::Unavailable { reason } = provenance {
+                log::warn!("The provenance of {} couldn't be checked: {}", key, reason);
+            }
+        }
+        if !missing.is_empty() {
+            anyhow::bail!("Packages without verified provenance: {}", missing.join(", "));
+        }
+
+        Ok(packages.into_keys().zip(checked).collect())
+    }
+
+    /// The provenance `lockfile` recorded for the packages in `layout`.
+    /// Fails when a package from a required scope wasn't verified.
+    fn locked_provenance(&self, lockfile: &LockFile, layout: &Layout) -> Result<HashMap<String, Provenance>> {
+        let recorded = lockfile.provenance();
+        let mut missing: Vec<String> = placements(layout)
+            .into_iter()
+            .filter(|(_, package)| in_scopes(&package.name, &self.require_provenance))
+            .map(|(_, package)| format!("{}@{}", package.name, package.version))
+            .filter(|key| !matches!(recorded.get(key), Some(Provenance::Verified { .. })))
+            .collect();
+        if !missing.is_empty() {
+            missing.sort();
+            missing.dedup();
+            anyhow::bail!("Packages without verified provenance: {}", missing.join(", "));
+        }
+        Ok(recorded)
+    }
+
+    /// Fails before anything is downloaded when a package has no registry
+    /// signature that verifies against the registry's keys, or when the keys
+    /// can't be fetched and none are cached. Only registries that publish no
+    /// keys at all go unchecked, unless verification was turned off.
+    async fn verify_signatures(&self, packages: impl Iterator<Item = &Package>) -> Result<()> {
+        if !self.verify_signatures {
+            log::warn!("Skipping registry signature verification");
+            return Ok(());
+        }
+        let keys = match RegistryKeys::load(&self.registry, self.cache.cache_dir()).await {
+            Ok(Some(keys)) => keys,
+            Ok(None) => {
+                log::debug!("The registry publishes no signing keys, skipping signature verification");
+                return Ok(());
+            }
+            Err(e) => {
+                return Err(e.context(
+                    "Couldn't fetch the registry's signing keys; \
+                     pass --skip-signature-verification to install without them",
+                ))
+            }
+        };
+
+        for package in packages {
+            match keys.verify_published(&self.registry, package).await? {
+                SignatureStatus::Verified { .. } => {}
+                SignatureStatus::Unsigned => {
+                    let package = format!("{}@{}", package.name, package.version);
+                    return Err(VerificationError::MissingSignature(package).into());
+                }
+                SignatureStatus::Invalid(reason) => {
+                    return Err(VerificationError::InvalidSignature {
+                        package: format!("{}@{}", package.name, package.version),
+                        reason,
+                    }
+                    .into())
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn install_package(&self, package_info: &Package, path: &Path, progress: ProgressBar) -> Result<()> {
+        let package_name = package_info.name.as_str();
+
         // Download phase
         progress.set_message(format!("Downloading {}", package_name));
-        let package_info = self.registry.fetch_package_info(package_name, None).await?;
-        let package_data = self.download_package(&package_info).await?;
+        let package_data = self.download_package(package_info).await?;
         progress.inc(1);
 
         // Verify phase
         progress.set_message(format!("Verifying {}", package_name));
         ChecksumIntegrityChecker::verify_package(&package_data, &package_info.dist.shasum)?;
+        // What the registry signature covers.
+        if let Some(integrity) = &package_info.dist.integrity {
+            verify_integrity(&package_data, integrity)?;
+        }
         progress.inc(1);
 
         // Extract phase
         progress.set_message(format!("Extracting {}", package_name));
-        let package_path = self.extract_package(&package_data, package_name).await?;
+        let package_path = self.extract_package(&package_data, package_name, path).await?;
         progress.inc(1);
 
         // Run scripts in sandbox
@@ -119,57 +527,46 @@ impl PackageInstaller {
 
     async fn download_package(&self, package: &Package) -> RpmResult<Vec<u8>> {
         // Check cache first
-        if let Some(cached_path) = self.cache.get(&package.name, &package.version).await? {
+        let version = package.version.to_string();
+        if let Some(cached_path) = self.cache.get(&package.name, &version).await? {
             log::debug!("Using cached version of {} {}", package.name, package.version);
             return Ok(fs::read(&cached_path).await?);
         }
 
+        let url = url::Url::parse(&package.dist.tarball).map_err(|e| RpmError::NetworkError(e.to_string()))?;
+        let download_error = |source| RpmError::DownloadError {
+            package: package.name.clone(),
+            url: url.clone(),
+            source,
+        };
         let response = self.http_client
-            .get(&package.dist.tarball)
+            .get(url.clone())
             .send()
             .await
-            .map_err(|e| RpmError::DownloadError {
-                package: package.name.clone(),
-                url: url::Url::parse(&package.dist.tarball)?,
-                source: e,
-            })?;
-
-        if !response.status().is_success() {
-            return Err(RpmError::DownloadError {
-                package: package.name.clone(),
-                url: url::Url::parse(&package.dist.tarball)?,
-                source: reqwest::Error::from(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("HTTP {}", response.status())
-                )),
-            });
-        }
+            .and_then(|response| response.error_for_status())
+            .map_err(download_error)?;
 
         // Stream the download and track memory usage
         let mut bytes = Vec::new();
         let mut stream = response.bytes_stream();
         
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| RpmError::DownloadError {
-                package: package.name.clone(),
-                url: url::Url::parse(&package.dist.tarball)?,
-                source: e,
-            })?;
+            let chunk = chunk.map_err(download_error)?;
             
             self.memory_profile.allocate(chunk.len());
             bytes.extend_from_slice(&chunk);
         }
 
         // Cache the downloaded package
-        self.cache.put(&package.name, &package.version, &bytes).await?;
+        self.cache.put(&package.name, &version, &bytes).await?;
 
         Ok(bytes)
     }
 
-    async fn extract_package(&self, package_data: &[u8], package_name: &str) -> RpmResult<PathBuf> {
-        let package_path = self.install_path.join(package_name);
+    async fn extract_package(&self, package_data: &[u8], package_name: &str, path: &Path) -> RpmResult<PathBuf> {
+        let package_path = self.install_path.join(path);
         let temp_dir = tempfile::tempdir()?;
-        let temp_path = temp_dir.path().join(format!("{}.tgz", package_name));
+        let temp_path = temp_dir.path().join("package.tgz");
 
         // Track memory for temporary files
         self.memory_profile.allocate(package_data.len());
@@ -182,19 +579,24 @@ impl PackageInstaller {
 
         // Extract in a blocking task
         let package_path_clone = package_path.clone();
+        let name = package_name.to_string();
         tokio::task::spawn_blocking(move || -> RpmResult<()> {
             let tar_gz = std::fs::File::open(temp_path)?;
             let tar = GzDecoder::new(tar_gz);
             let mut archive = Archive::new(tar);
 
             archive.unpack(&package_path_clone).map_err(|e| RpmError::ExtractionError {
-                package: package_name.to_string(),
+                package: name,
                 path: package_path_clone.clone(),
                 source: e,
             })?;
 
             Ok(())
-        }).await??;
+        }).await.map_err(|e| RpmError::ExtractionError {
+            package: package_name.to_string(),
+            path: package_path.clone(),
+            source: e.into(),
+        })??;
 
         // Cleanup temporary memory allocation
         self.memory_profile.deallocate(package_data.len());
@@ -213,6 +615,153 @@ impl Clone for PackageInstaller {
             concurrent_limit: Arc::clone(&self.concurrent_limit),
             cache: self.cache.clone(),
             memory_profile: self.memory_profile.clone(),
+            omit: self.omit.clone(),
+            advisories: self.advisories.clone(),
+            require_provenance: self.require_provenance.clone(),
+            verify_signatures: self.verify_signatures,
+            use_lockfile: self.use_lockfile,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheConfig;
+    use crate::registry::mock::{packument, MockRegistry};
+    use crate::testing::write_manifest;
+    use crate::tree::EdgeKind;
+    use crate::verification::KEYS_PATH;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use sha2::{Digest, Sha256};
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    /// A one-version packument for `name` whose tarball is `data`.
+    fn packument_for(name: &str, data: &[u8]) -> String {
+        let mut document: serde_json::Value = serde_json::from_str(&packument(name, &[("1.0.0", &[])])).unwrap();
+        document["versions"]["1.0.0"]["dist"]["shasum"] = hex::encode(Sha256::digest(data)).into();
+        document.to_string()
+    }
+
+    fn tarball(name: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let manifest = format!(r#"{{"name": "{}", "version": "1.0.0"}}"#, name);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "package/package.json", manifest.as_bytes()).unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_omit_switch_installs_from_lockfile() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().join("app");
+        write_manifest(
+            &root,
+            r#"{"name": "app", "version": "1.0.0",
+                "dependencies": {"a": "^1.0.0"}, "devDependencies": {"b": "^1.0.0"}}"#,
+        )
+        .await;
+        let manifest = PackageJson::load(&root).await.unwrap();
+
+        let (a, b) = (tarball("a"), tarball("b"));
+        let documents = HashMap::from([
+            ("/a".to_string(), packument_for("a", &a)),
+            ("/b".to_string(), packument_for("b", &b)),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let cache = PackageCache::new(CacheConfig { cache_dir: temp_dir.path().join("cache"), ..Default::default() })
+            .await
+            .unwrap();
+        cache.put("a", "1.0.0", &a).await.unwrap();
+        cache.put("b", "1.0.0", &b).await.unwrap();
+        let install = |omit: Omit| {
+            PackageInstaller::new(false, &root, cache.clone(), MemoryProfile::new(usize::MAX))
+                .with_registry(Arc::new(RegistryClient::with_url(registry.url.clone())))
+                .with_omit(omit)
+        };
+
+        install(Omit::new(&[EdgeKind::Dev], &[], None))
+            .install_project(&root, &[(root.as_path(), &manifest)], None)
+            .await
+            .unwrap();
+        assert!(root.join("node_modules/a").is_dir());
+        assert!(!root.join("node_modules/b").exists());
+        assert_eq!((registry.hits("/a"), registry.hits("/b")), (1, 1));
+        let lockfile = LockFile::load(&root.join(LOCKFILE_NAME)).await.unwrap();
+        assert_eq!(lockfile.get_dependency("b").unwrap().provenance(), Some(&Provenance::Unattested));
+
+        // Switching modes installs what the lockfile recorded.
+        install(Omit::default())
+            .install_project(&root, &[(root.as_path(), &manifest)], None)
+            .await
+            .unwrap();
+        assert!(root.join("node_modules/b").is_dir());
+        assert_eq!((registry.hits("/a"), registry.hits("/b")), (1, 1));
+        let lockfile = LockFile::load(&root.join(LOCKFILE_NAME)).await.unwrap();
+        assert_eq!(lockfile.get_dependency("a").unwrap().provenance(), Some(&Provenance::Unattested));
+    }
+
+    #[tokio::test]
+    async fn test_signature_checks_fail_closed() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().join("app");
+        write_manifest(&root, r#"{"name": "app", "version": "1.0.0", "dependencies": {"a": "^1.0.0"}}"#).await;
+        let manifest = PackageJson::load(&root).await.unwrap();
+
+        let a = tarball("a");
+        let keys = serde_json::json!({"keys": [{
+            "keyid": "SHA256:test",
+            "keytype": "ecdsa-sha2-nistp256",
+            "scheme": "ecdsa-sha2-nistp256",
+            "key": "",
+            "expires": null
+        }]});
+        let signing = MockRegistry::start(
+            HashMap::from([("/a".to_string(), packument_for("a", &a)), (KEYS_PATH.to_string(), keys.to_string())]),
+            Duration::ZERO,
+        )
+        .await;
+        let unreachable_keys = MockRegistry::start(
+            HashMap::from([("/a".to_string(), packument_for("a", &a)), (KEYS_PATH.to_string(), "<html>".to_string())]),
+            Duration::ZERO,
+        )
+        .await;
+        let install = |registry: &MockRegistry, cache: &str| {
+            let registry = Arc::new(RegistryClient::with_url(registry.url.clone()));
+            let (root, cache_dir, a) = (root.clone(), temp_dir.path().join(cache), a.clone());
+            async move {
+                let cache = PackageCache::new(CacheConfig { cache_dir, ..Default::default() }).await.unwrap();
+                cache.put("a", "1.0.0", &a).await.unwrap();
+                PackageInstaller::new(false, &root, cache, MemoryProfile::new(usize::MAX)).with_registry(registry)
+            }
+        };
+
+        // `a` is unsigned although the registry signs packages.
+        let error = install(&signing, "signing")
+            .await
+            .install_project(&root, &[(root.as_path(), &manifest)], None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("a@1.0.0 has no registry signature"), "{}", error);
+        assert!(!root.join("node_modules/a").exists());
+
+        let error = install(&unreachable_keys, "unreachable")
+            .await
+            .install_project(&root, &[(root.as_path(), &manifest)], None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Couldn't fetch the registry's signing keys"), "{}", error);
+
+        install(&unreachable_keys, "unreachable")
+            .await
+            .with_signature_verification(false)
+            .install_project(&root, &[(root.as_path(), &manifest)], None)
+            .await
+            .unwrap();
+        assert!(root.join("node_modules/a").is_dir());
+    }
+}
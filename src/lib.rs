@@ -1,4 +1,7 @@
 pub mod cli;
+pub mod cache;
+pub mod logging;
+pub mod profiling;
 pub mod error;
 pub mod install;
 pub mod package;
@@ -11,7 +14,36 @@ pub mod dependency;
 pub mod concurrency;
 pub mod security;
 pub mod sandbox;
+pub mod workspace;
+pub mod filter;
+pub mod scripts;
+pub mod fetcher;
+pub mod tree;
+pub mod table;
+pub mod outdated;
+pub mod update;
+pub mod upgrade;
+pub mod dedupe;
+pub mod prune;
+pub mod project;
+pub mod audit;
+pub mod osv;
+pub mod provenance;
+pub mod date;
+#[cfg(test)]
+mod testing;
 
 pub use cli::Cli;
 pub use package::PackageJson;
-pub use security::SecurityChecker; 
\ No newline at end of file
+pub use security::SecurityChecker;
+
+use cache::PackageCache;
+use profiling::MemoryProfile;
+use project::ProjectRoot;
+
+#[derive(Clone)]
+pub struct AppContext {
+    pub project: ProjectRoot,
+    pub memory_profile: MemoryProfile,
+    pub package_cache: PackageCache,
+} 
\ No newline at end of file
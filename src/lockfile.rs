@@ -1,15 +1,41 @@
+use crate::dependency::{InstallNode, Layout};
+use crate::fetcher::FetchedPackage;
+use crate::package::{Package, PackageDistribution, PackageJson};
+use crate::provenance::Provenance;
+use crate::workspace::Workspace;
+use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use tokio::fs;
 use anyhow::Result;
 
+pub const LOCKFILE_NAME: &str = "rpm-lock.json";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LockedDependency {
     version: String,
     resolved: String,
     integrity: String,
     requires: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    optional_requires: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    peer_requires: Option<HashMap<String, String>>,
+    /// The original specifier of git, tarball, local and aliased dependencies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    /// Set for workspace members and `link:` dependencies, which are
+    /// symlinked rather than downloaded.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    link: bool,
+    /// Whether a verified attestation says where registry packages were
+    /// built.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    provenance: Option<Provenance>,
+    /// Copies nested in this package's own `node_modules`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dependencies: Option<HashMap<String, LockedDependency>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,6 +48,75 @@ pub struct LockFile {
 }
 
 impl LockFile {
+    pub fn new(name: String, version: String) -> Self {
+        Self {
+            name,
+            version,
+            lockfile_version: 1,
+            requires: true,
+            dependencies: HashMap::new(),
+        }
+    }
+
+    /// Builds the lockfile for a resolved install, mirroring the
+    /// `node_modules` layout. In a workspace there is a single lockfile at the
+    /// root, with every member recorded as a link.
+    pub fn from_resolved(
+        manifest: &PackageJson,
+        layout: &Layout,
+        fetched: &[FetchedPackage],
+        workspace: Option<&Workspace>,
+    ) -> Self {
+        let mut lockfile = Self::new(manifest.name.clone(), manifest.version.clone());
+        lockfile.dependencies = lock_tree(&layout.tree);
+        let nested = |name: &str| layout.importers.get(name).map(lock_tree);
+
+        for package in fetched {
+            let requires = package.dependencies();
+            lockfile.dependencies.insert(package.name.clone(), LockedDependency {
+                version: package.version.clone(),
+                resolved: package.resolved.clone(),
+                integrity: package.integrity.clone(),
+                requires: (!requires.is_empty()).then_some(requires),
+                optional_requires: package.manifest.optional_dependencies.clone(),
+                peer_requires: package.manifest.peer_dependencies.clone(),
+                from: Some(package.from.clone()),
+                link: package.link,
+                provenance: None,
+                dependencies: nested(&package.name),
+            });
+        }
+
+        if let Some(workspace) = workspace {
+            for member in &workspace.members {
+                lockfile.dependencies.insert(member.name.clone(), LockedDependency {
+                    version: member.version.clone(),
+                    resolved: format!("file:{}", member.relative_path.display()),
+                    integrity: String::new(),
+                    requires: member.manifest.dependencies.clone(),
+                    optional_requires: member.manifest.optional_dependencies.clone(),
+                    peer_requires: member.manifest.peer_dependencies.clone(),
+                    from: None,
+                    link: true,
+                    provenance: None,
+                    dependencies: nested(&member.name),
+                });
+            }
+        }
+
+        lockfile
+    }
+
+    /// Reads what is actually installed in `root/node_modules` into the same
+    /// shape, so it can be compared with what the manifests require.
+    /// Symlinked packages are recorded as links and not descended into.
+    pub async fn from_node_modules(manifest: &PackageJson, root: &Path) -> Result<Self> {
+        let mut lockfile = Self::new(manifest.name.clone(), manifest.version.clone());
+        let node_modules = root.join("node_modules");
+        lockfile.dependencies = tokio::task::spawn_blocking(move || scan_node_modules(&node_modules)).await??;
+        Ok(lockfile)
+    }
+
     pub async fn load(path: &Path) -> Result<Self> {
         let content = fs::read_to_string(path).await?;
         Ok(serde_json::from_str(&content)?)
@@ -46,10 +141,294 @@ impl LockFile {
             resolved,
             integrity,
             requires,
+            optional_requires: None,
+            peer_requires: None,
+            from: None,
+            link: false,
+            provenance: None,
+            dependencies: None,
         });
     }
 
     pub fn get_dependency(&self, name: &str) -> Option<&LockedDependency> {
         self.dependencies.get(name)
     }
-} 
\ No newline at end of file
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The top level of `node_modules`.
+    pub fn dependencies(&self) -> &HashMap<String, LockedDependency> {
+        &self.dependencies
+    }
+
+    /// The copy at `path`, the chain of names leading to it from the top
+    /// level.
+    pub fn entry(&self, path: &[String]) -> Option<&LockedDependency> {
+        let (first, rest) = path.split_first()?;
+        let mut entry = self.dependencies.get(first)?;
+        for name in rest {
+            entry = entry.dependencies.as_ref()?.get(name)?;
+        }
+        Some(entry)
+    }
+
+    /// Every copy in the tree with its path, in no particular order.
+    pub fn nodes(&self) -> Vec<(Vec<String>, &LockedDependency)> {
+        let mut nodes = Vec::new();
+        let mut stack: Vec<(Vec<String>, &HashMap<String, LockedDependency>)> = vec![(Vec::new(), &self.dependencies)];
+
+        while let Some((base, scope)) = stack.pop() {
+            for (name, entry) in scope {
+                let mut path = base.clone();
+                path.push(name.clone());
+                if let Some(children) = &entry.dependencies {
+                    stack.push((path.clone(), children));
+                }
+                nodes.push((path, entry));
+            }
+        }
+
+        nodes
+    }
+
+    /// Takes out the copy at `path` along with everything nested under it.
+    pub fn remove(&mut self, path: &[String]) -> Option<LockedDependency> {
+        let (name, parents) = path.split_last()?;
+        let Some((first, rest)) = parents.split_first() else {
+            return self.dependencies.remove(name);
+        };
+
+        let mut parent = self.dependencies.get_mut(first)?;
+        for ancestor in rest {
+            parent = parent.dependencies.as_mut()?.get_mut(ancestor)?;
+        }
+        let nested = parent.dependencies.as_mut()?;
+        let removed = nested.remove(name);
+        if nested.is_empty() {
+            parent.dependencies = None;
+        }
+        removed
+    }
+
+    /// Records the provenance of every registry copy found in
+    /// `provenance`, keyed by `name@version`.
+    pub fn record_provenance(&mut self, provenance: &HashMap<String, Provenance>) {
+        let mut stack: Vec<&mut HashMap<String, LockedDependency>> = vec![&mut self.dependencies];
+        while let Some(scope) = stack.pop() {
+            for (name, entry) in scope.iter_mut() {
+                if entry.from.is_none() && !entry.link {
+                    entry.provenance = provenance.get(&format!("{}@{}", name, entry.version)).cloned();
+                }
+                if let Some(children) = entry.dependencies.as_mut() {
+                    stack.push(children);
+                }
+            }
+        }
+    }
+
+    /// The `node_modules` layout the lockfile records, to install it again
+    /// without resolving anything. Copies nested in workspace members and
+    /// fetched packages go under their importer.
+    pub fn layout(&self) -> Result<Layout> {
+        let mut layout = Layout::default();
+        for (name, entry) in &self.dependencies {
+            if entry.from.is_some() || entry.link {
+                if let Some(children) = &entry.dependencies {
+                    layout.importers.insert(name.clone(), install_tree(children)?);
+                }
+            } else {
+                layout.tree.insert(name.clone(), install_node(name, entry)?);
+            }
+        }
+        Ok(layout)
+    }
+
+    /// The recorded provenance of every registry copy, keyed by
+    /// `name@version`.
+    pub fn provenance(&self) -> HashMap<String, Provenance> {
+        self.nodes()
+            .into_iter()
+            .filter_map(|(path, entry)| {
+                let provenance = entry.provenance.clone()?;
+                Some((format!("{}@{}", path.last()?, entry.version), provenance))
+            })
+            .collect()
+    }
+
+    /// Puts `entry` at the top level, replacing any copy already there.
+    pub fn hoist(&mut self, name: String, entry: LockedDependency) {
+        self.dependencies.insert(name, entry);
+    }
+}
+
+impl LockedDependency {
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn resolved(&self) -> &str {
+        &self.resolved
+    }
+
+    pub fn integrity(&self) -> &str {
+        &self.integrity
+    }
+
+    pub fn is_link(&self) -> bool {
+        self.link
+    }
+
+    /// The original specifier, set for git, tarball, local and aliased
+    /// dependencies.
+    pub fn from(&self) -> Option<&str> {
+        self.from.as_deref()
+    }
+
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    /// This package plus every copy nested under it.
+    pub fn package_count(&self) -> usize {
+        1 + self
+            .dependencies
+            .iter()
+            .flatten()
+            .map(|(_, nested)| nested.package_count())
+            .sum::<usize>()
+    }
+
+    pub fn requires(&self) -> Option<&HashMap<String, String>> {
+        self.requires.as_ref()
+    }
+
+    pub fn optional_requires(&self) -> Option<&HashMap<String, String>> {
+        self.optional_requires.as_ref()
+    }
+
+    pub fn peer_requires(&self) -> Option<&HashMap<String, String>> {
+        self.peer_requires.as_ref()
+    }
+
+    /// Copies nested in this package's own `node_modules`.
+    pub fn dependencies(&self) -> Option<&HashMap<String, LockedDependency>> {
+        self.dependencies.as_ref()
+    }
+}
+
+fn lock_tree(tree: &BTreeMap<String, InstallNode>) -> HashMap<String, LockedDependency> {
+    tree.iter()
+        .map(|(name, node)| {
+            let package = &node.package;
+            let locked = LockedDependency {
+                version: package.version.to_string(),
+                resolved: package.dist.tarball.clone(),
+                integrity: package.dist.shasum.clone(),
+                requires: (!package.dependencies.is_empty()).then(|| package.dependencies.clone()),
+                optional_requires: (!package.optional_dependencies.is_empty())
+                    .then(|| package.optional_dependencies.clone()),
+                peer_requires: (!package.peer_dependencies.is_empty()).then(|| package.peer_dependencies.clone()),
+                from: None,
+                link: false,
+                provenance: None,
+                dependencies: (!node.children.is_empty()).then(|| lock_tree(&node.children)),
+            };
+            (name.clone(), locked)
+        })
+        .collect()
+}
+
+fn install_tree(scope: &HashMap<String, LockedDependency>) -> Result<BTreeMap<String, InstallNode>> {
+    scope.iter().map(|(name, entry)| Ok((name.clone(), install_node(name, entry)?))).collect()
+}
+
+/// The registry package a locked copy was installed from. Only what
+/// installing it needs is recorded: its signatures were checked when it
+/// was locked, and the locked shasum still pins the tarball.
+fn install_node(name: &str, entry: &LockedDependency) -> Result<InstallNode> {
+    let package = Package {
+        name: name.to_string(),
+        version: Version::parse(&entry.version)?,
+        dependencies: entry.requires.clone().unwrap_or_default(),
+        optional_dependencies: entry.optional_requires.clone().unwrap_or_default(),
+        peer_dependencies: entry.peer_requires.clone().unwrap_or_default(),
+        repository: None,
+        dist: PackageDistribution {
+            tarball: entry.resolved.clone(),
+            shasum: entry.integrity.clone(),
+            integrity: None,
+            signatures: Vec::new(),
+            attestations: None,
+        },
+    };
+    let children = match &entry.dependencies {
+        Some(children) => install_tree(children)?,
+        None => BTreeMap::new(),
+    };
+    Ok(InstallNode { package, children })
+}
+
+fn scan_node_modules(dir: &Path) -> Result<HashMap<String, LockedDependency>> {
+    let mut installed = HashMap::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(installed),
+        Err(e) => return Err(e.into()),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.starts_with('.') {
+            continue;
+        }
+
+        // Scoped packages live one level further down, in `@scope/name`.
+        let packages = if file_name.starts_with('@') {
+            std::fs::read_dir(entry.path())?
+                .map(|scoped| scoped.map(|s| (format!("{}/{}", file_name, s.file_name().to_string_lossy()), s.path())))
+                .collect::<std::io::Result<Vec<_>>>()?
+        } else {
+            vec![(file_name, entry.path())]
+        };
+
+        for (name, path) in packages {
+            let Ok(content) = std::fs::read_to_string(path.join("package.json")) else {
+                continue;
+            };
+            let manifest: PackageJson = match serde_json::from_str(&content) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    log::warn!("Ignoring {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            let link = std::fs::symlink_metadata(&path)?.file_type().is_symlink();
+            let nested = if link {
+                HashMap::new()
+            } else {
+                scan_node_modules(&path.join("node_modules"))?
+            };
+
+            // A directory named differently from its package is an `npm:` alias.
+            let from = (!link && manifest.name != name).then(|| format!("npm:{}@{}", manifest.name, manifest.version));
+            installed.insert(name, LockedDependency {
+                version: manifest.version,
+                resolved: String::new(),
+                integrity: String::new(),
+                requires: manifest.dependencies,
+                optional_requires: manifest.optional_dependencies,
+                peer_requires: manifest.peer_dependencies,
+                from,
+                link,
+                provenance: None,
+                dependencies: (!nested.is_empty()).then_some(nested),
+            });
+        }
+    }
+
+    Ok(installed)
+}
@@ -1,5 +1,5 @@
-use log::{LevelFilter, Record};
-use env_logger::{Builder, fmt::{Color, Style, StyledValue}};
+use log::LevelFilter;
+use env_logger::Builder;
 use std::io::Write;
 
 pub struct LoggingConfig {
@@ -24,17 +24,19 @@ pub fn setup_logging(config: LoggingConfig) {
     let mut builder = Builder::new();
 
     builder.format(move |buf, record| {
-        let mut style = buf.style();
-
-        let level = colored_level(&mut style, record.level());
-
         let mut output = String::new();
 
         if config.show_timestamps {
-            output.push_str(&format!("[{}] ", chrono::Local::now().format("%Y-%m-%d %H:%M:%S")));
+            output.push_str(&format!("[{}] ", buf.timestamp_seconds()));
         }
 
-        output.push_str(&format!("{}: ", level));
+        let level = record.level();
+        if config.color {
+            let style = buf.default_level_style(level);
+            output.push_str(&format!("{style}{level}{style:#}: "));
+        } else {
+            output.push_str(&format!("{}: ", level));
+        }
 
         if config.show_module_path {
             if let Some(module_path) = record.module_path() {
@@ -49,13 +51,3 @@ pub fn setup_logging(config: LoggingConfig) {
     builder.filter_level(config.level);
     builder.init();
 }
-
-fn colored_level(style: &mut Style, level: log::Level) -> StyledValue<&'static str> {
-    match level {
-        log::Level::Trace => style.set_color(Color::Magenta).value("TRACE"),
-        log::Level::Debug => style.set_color(Color::Blue).value("DEBUG"),
-        log::Level::Info => style.set_color(Color::Green).value("INFO"),
-        log::Level::Warn => style.set_color(Color::Yellow).value("WARN"),
-        log::Level::Error => style.set_color(Color::Red).value("ERROR"),
-    }
-} 
\ No newline at end of file
@@ -1,32 +1,19 @@
 use clap::Parser;
 use log::info;
+use std::process::ExitCode;
 
-mod cli;
-mod error;
-mod install;
-mod package;
-mod registry;
-mod verification;
-mod lockfile;
-mod progress;
-mod version;
-mod dependency;
-mod concurrency;
-mod security;
-mod cache;
-mod logging;
-mod profiling;
-
-use crate::{
+use rpm::{
     cli::Cli,
     cache::{PackageCache, CacheConfig},
     logging::{LoggingConfig, setup_logging},
     profiling::MemoryProfile,
     error::RpmResult,
+    project::ProjectRoot,
+    AppContext,
 };
 
 #[tokio::main]
-async fn main() -> RpmResult<()> {
+async fn main() -> RpmResult<ExitCode> {
     // Parse command line arguments
     let cli = Cli::parse();
 
@@ -51,16 +38,26 @@ async fn main() -> RpmResult<()> {
     let package_cache = PackageCache::new(cache_config).await?;
 
     info!("RPM package manager initialized");
-    info!("Cache directory: {}", cache_config.cache_dir.display());
+    info!("Cache directory: {}", package_cache.cache_dir().display());
+
+    // Commands run against the project containing the working directory
+    let cwd = std::env::current_dir()?;
+    let project = if cli.is_global() {
+        ProjectRoot::at(cwd)
+    } else {
+        ProjectRoot::locate(&cwd, cli.prefix.as_deref()).await
+    };
+    info!("Project root: {}", project.root.display());
 
     // Create application context with shared resources
     let context = AppContext {
+        project,
         memory_profile: memory_profile.clone(),
         package_cache: package_cache.clone(),
     };
 
     // Execute CLI command with context
-    cli.execute(context).await?;
+    let exit_code = cli.execute(context).await?;
 
     // Log final memory usage statistics
     info!(
@@ -68,11 +65,5 @@ async fn main() -> RpmResult<()> {
         memory_profile.peak_usage()
     );
 
-    Ok(())
-}
-
-#[derive(Clone)]
-pub struct AppContext {
-    pub memory_profile: MemoryProfile,
-    pub package_cache: PackageCache,
+    Ok(exit_code)
 }
@@ -0,0 +1,453 @@
+use crate::security::{Advisory, Severity};
+use anyhow::{bail, Context, Result};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The OSV ecosystem name for npm packages.
+const ECOSYSTEM: &str = "npm";
+const INDEX_NAME: &str = "index.json";
+
+/// A local copy of OSV advisories for npm packages, so `rpm audit` can run
+/// without network access. Records are stored one file per package under
+/// `packages/`, each holding only that package's `affected` entries.
+pub struct OsvDatabase {
+    dir: PathBuf,
+}
+
+/// What an import wrote.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub source: PathBuf,
+    /// Seconds since the Unix epoch.
+    pub imported_at: u64,
+    pub records: usize,
+    pub packages: usize,
+}
+
+impl ImportSummary {
+    /// Whole days since the import.
+    pub fn age_days(&self) -> u64 {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        now.saturating_sub(self.imported_at) / 86_400
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OsvRecord {
+    id: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    withdrawn: Option<String>,
+    #[serde(default)]
+    affected: Vec<OsvAffected>,
+    #[serde(default)]
+    references: Vec<OsvReference>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    database_specific: Option<DatabaseSpecific>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OsvAffected {
+    package: OsvPackage,
+    #[serde(default)]
+    ranges: Vec<OsvRange>,
+    #[serde(default)]
+    versions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OsvPackage {
+    ecosystem: String,
+    name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OsvRange {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    events: Vec<OsvEvent>,
+}
+
+/// One of `introduced`, `fixed`, `last_affected` or `limit`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct OsvEvent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    introduced: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fixed: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_affected: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    limit: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OsvReference {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+}
+
+/// GitHub's extra fields, present on the records it exports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DatabaseSpecific {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    severity: Option<String>,
+    #[serde(default)]
+    cwe_ids: Vec<String>,
+}
+
+impl OsvDatabase {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Whether a database has been imported.
+    pub fn exists(&self) -> bool {
+        self.dir.join(INDEX_NAME).is_file()
+    }
+
+    /// The summary of the last import, if any.
+    pub async fn summary(&self) -> Result<Option<ImportSummary>> {
+        if !self.exists() {
+            return Ok(None);
+        }
+        let content = tokio::fs::read_to_string(self.dir.join(INDEX_NAME)).await?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    /// Replaces the database with the npm records found in `source`, a
+    /// directory (searched recursively) or zip archive of OSV JSON files such
+    /// as the `npm/all.zip` export. Records for other ecosystems, withdrawn
+    /// ones and files that aren't OSV records are skipped.
+    pub async fn import(&self, source: &Path) -> Result<ImportSummary> {
+        let dir = self.dir.clone();
+        let source = std::path::absolute(source)?;
+        tokio::task::spawn_blocking(move || import_blocking(&dir, &source)).await?
+    }
+
+    /// Every advisory recorded for the packages in `query`, in the shape the
+    /// registry's bulk endpoint returns. Versions aren't filtered here; the
+    /// caller matches `vulnerable_versions` against what's installed.
+    pub async fn advisories(
+        &self,
+        query: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Result<HashMap<String, Vec<Advisory>>> {
+        let mut advisories = HashMap::new();
+        for name in query.keys() {
+            let path = self.package_path(name);
+            let content = match tokio::fs::read_to_string(&path).await {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let records: Vec<OsvRecord> =
+                serde_json::from_str(&content).with_context(|| format!("Corrupt OSV index {}", path.display()))?;
+
+            let converted: Vec<Advisory> = records
+                .iter()
+                .flat_map(|record| record.affected.iter().filter_map(move |affected| to_advisory(record, affected)))
+                .collect();
+            if !converted.is_empty() {
+                advisories.insert(name.clone(), converted);
+            }
+        }
+        Ok(advisories)
+    }
+
+    fn package_path(&self, name: &str) -> PathBuf {
+        package_path(&self.dir, name)
+    }
+}
+
+/// Scoped names keep their `@` but the slash can't be part of a file name.
+fn package_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join("packages").join(format!("{}.json", name.replace('/', "%2f")))
+}
+
+fn import_blocking(dir: &Path, source: &Path) -> Result<ImportSummary> {
+    let mut documents = Vec::new();
+    if source.is_dir() {
+        collect_json_files(source, &mut documents)?;
+    } else if source.is_file() {
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(source)?)
+            .with_context(|| format!("{} is neither a directory nor a zip archive", source.display()))?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            if file.is_file() && file.name().ends_with(".json") {
+                let name = file.name().to_string();
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                documents.push((name, content));
+            }
+        }
+    } else {
+        bail!("{} does not exist", source.display());
+    }
+
+    // Every record goes to the file of each npm package it affects.
+    let mut by_package: BTreeMap<String, Vec<OsvRecord>> = BTreeMap::new();
+    let mut records = 0;
+    for (name, content) in documents {
+        let record: OsvRecord = match serde_json::from_str(&content) {
+            Ok(record) => record,
+            Err(e) => {
+                log::warn!("Skipping {}: {}", name, e);
+                continue;
+            }
+        };
+        if record.withdrawn.is_some() {
+            continue;
+        }
+
+        let mut packages: BTreeMap<&str, Vec<OsvAffected>> = BTreeMap::new();
+        for affected in record.affected.iter().filter(|affected| affected.package.ecosystem == ECOSYSTEM) {
+            packages.entry(&affected.package.name).or_default().push(affected.clone());
+        }
+        if packages.is_empty() {
+            continue;
+        }
+        records += 1;
+        for (package, affected) in packages {
+            by_package
+                .entry(package.to_string())
+                .or_default()
+                .push(OsvRecord { affected, ..record.clone() });
+        }
+    }
+
+    // Built next to the old database and swapped in once complete, so a
+    // failed import leaves the previous one usable.
+    let staging = dir.with_extension("importing");
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    std::fs::create_dir_all(staging.join("packages"))?;
+    for (package, records) in &by_package {
+        std::fs::write(package_path(&staging, package), serde_json::to_string(records)?)?;
+    }
+
+    let summary = ImportSummary {
+        source: source.to_path_buf(),
+        imported_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+        records,
+        packages: by_package.len(),
+    };
+    std::fs::write(staging.join(INDEX_NAME), serde_json::to_string_pretty(&summary)?)?;
+
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    std::fs::rename(&staging, dir)?;
+    Ok(summary)
+}
+
+fn collect_json_files(dir: &Path, documents: &mut Vec<(String, String)>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_json_files(&path, documents)?;
+        } else if path.extension().is_some_and(|extension| extension == "json") {
+            documents.push((path.display().to_string(), std::fs::read_to_string(&path)?));
+        }
+    }
+    Ok(())
+}
+
+fn to_advisory(record: &OsvRecord, affected: &OsvAffected) -> Option<Advisory> {
+    let vulnerable_versions = vulnerable_range(affected)?;
+    let url = record
+        .references
+        .iter()
+        .find(|reference| reference.kind == "ADVISORY")
+        .or_else(|| record.references.first())
+        .map(|reference| reference.url.clone())
+        .unwrap_or_else(|| format!("https://osv.dev/vulnerability/{}", record.id));
+    let database_specific = record.database_specific.as_ref();
+
+    Some(Advisory {
+        id: record.id.clone(),
+        url,
+        title: record.summary.clone().unwrap_or_else(|| record.id.clone()),
+        severity: database_specific
+            .and_then(|specific| specific.severity.as_deref())
+            .map_or(Severity::Unknown, Severity::parse),
+        vulnerable_versions,
+        cwe: database_specific.map(|specific| specific.cwe_ids.clone()).unwrap_or_default(),
+        cvss: None,
+    })
+}
+
+/// The versions an `affected` entry covers, as an npm range. Each range's
+/// events are replayed in version order: `introduced` opens an interval,
+/// the next `fixed` or `last_affected` closes it. Explicitly listed versions
+/// are used only when there are no semver ranges.
+fn vulnerable_range(affected: &OsvAffected) -> Option<String> {
+    let mut alternatives = Vec::new();
+
+    for range in affected.ranges.iter().filter(|range| range.kind == "SEMVER" || range.kind == "ECOSYSTEM") {
+        let mut events: Vec<(Version, &OsvEvent)> = range
+            .events
+            .iter()
+            .filter_map(|event| {
+                let version = [&event.introduced, &event.fixed, &event.last_affected, &event.limit]
+                    .into_iter()
+                    .find_map(Option::as_deref)?;
+                if version == "0" {
+                    return Some((Version::new(0, 0, 0), event));
+                }
+                match Version::parse(version) {
+                    Ok(parsed) => Some((parsed, event)),
+                    Err(_) => {
+                        log::warn!("Ignoring OSV event with version {:?}", version);
+                        None
+                    }
+                }
+            })
+            .collect();
+        events.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut open: Option<String> = None;
+        for (version, event) in events {
+            if event.introduced.is_some() {
+                if open.is_none() {
+                    open = Some(if version == Version::new(0, 0, 0) { String::new() } else { format!(">={}", version) });
+                }
+            } else if event.fixed.is_some() {
+                if let Some(lower) = open.take() {
+                    alternatives.push(format!("{} <{}", lower, version).trim().to_string());
+                }
+            } else if event.last_affected.is_some() {
+                if let Some(lower) = open.take() {
+                    alternatives.push(format!("{} <={}", lower, version).trim().to_string());
+                }
+            }
+        }
+        if let Some(lower) = open {
+            alternatives.push(if lower.is_empty() { "*".to_string() } else { lower });
+        }
+    }
+
+    if alternatives.is_empty() {
+        alternatives = affected.versions.iter().map(|version| format!("={}", version)).collect();
+    }
+    (!alternatives.is_empty()).then(|| alternatives.join(" || "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn record(id: &str, ecosystem: &str, package: &str, events: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "schema_version": "1.6.0",
+            "id": id,
+            "aliases": ["CVE-2021-23337"],
+            "summary": format!("Vulnerability in {}", package),
+            "affected": [{
+                "package": {"ecosystem": ecosystem, "name": package},
+                "ranges": [{"type": "SEMVER", "events": events}]
+            }],
+            "references": [
+                {"type": "WEB", "url": "https://example.com"},
+                {"type": "ADVISORY", "url": format!("https://github.com/advisories/{}", id)}
+            ],
+            "database_specific": {"severity": "HIGH", "cwe_ids": ["CWE-94"]}
+        })
+    }
+
+    fn range(events: serde_json::Value) -> Option<String> {
+        let affected: OsvAffected = serde_json::from_value(serde_json::json!({
+            "package": {"ecosystem": "npm", "name": "pkg"},
+            "ranges": [{"type": "SEMVER", "events": events}]
+        }))
+        .unwrap();
+        vulnerable_range(&affected)
+    }
+
+    #[test]
+    fn test_vulnerable_range() {
+        assert_eq!(range(serde_json::json!([{"introduced": "0"}, {"fixed": "4.17.21"}])).as_deref(), Some("<4.17.21"));
+        assert_eq!(range(serde_json::json!([{"introduced": "1.0.0"}])).as_deref(), Some(">=1.0.0"));
+        assert_eq!(range(serde_json::json!([{"introduced": "0"}])).as_deref(), Some("*"));
+        assert_eq!(
+            range(serde_json::json!([{"introduced": "0"}, {"last_affected": "2.3.0"}])).as_deref(),
+            Some("<=2.3.0")
+        );
+        // Events may come in any order.
+        assert_eq!(
+            range(serde_json::json!([
+                {"fixed": "1.2.3"},
+                {"introduced": "2.0.0"},
+                {"introduced": "1.0.0"},
+                {"fixed": "2.0.3"}
+            ]))
+            .as_deref(),
+            Some(">=1.0.0 <1.2.3 || >=2.0.0 <2.0.3")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_and_query() {
+        let source = tempdir().unwrap();
+        let nested = source.path().join("advisories/2021");
+        std::fs::create_dir_all(&nested).unwrap();
+        let lodash = record("GHSA-35jh-r3h4-6jhm", "npm", "lodash", serde_json::json!([{"introduced": "0"}, {"fixed": "4.17.21"}]));
+        let django = record("GHSA-xxxx-0000-0000", "PyPI", "django", serde_json::json!([{"introduced": "0"}]));
+        std::fs::write(nested.join("GHSA-35jh-r3h4-6jhm.json"), lodash.to_string()).unwrap();
+        std::fs::write(nested.join("GHSA-xxxx-0000-0000.json"), django.to_string()).unwrap();
+        std::fs::write(source.path().join("README.md"), "not a record").unwrap();
+
+        let cache = tempdir().unwrap();
+        let database = OsvDatabase::new(cache.path().join("osv"));
+        assert!(!database.exists());
+
+        let summary = database.import(source.path()).await.unwrap();
+        assert_eq!((summary.records, summary.packages), (1, 1));
+        assert!(database.exists());
+
+        let query = BTreeMap::from([
+            ("lodash".to_string(), BTreeSet::from(["4.17.20".to_string()])),
+            ("react".to_string(), BTreeSet::from(["18.2.0".to_string()])),
+        ]);
+        let advisories = database.advisories(&query).await.unwrap();
+        assert_eq!(advisories.len(), 1);
+        let advisory = &advisories["lodash"][0];
+        assert_eq!(advisory.id, "GHSA-35jh-r3h4-6jhm");
+        assert_eq!(advisory.severity, Severity::High);
+        assert_eq!(advisory.url, "https://github.com/advisories/GHSA-35jh-r3h4-6jhm");
+        assert!(advisory.affects(&Version::new(4, 17, 20)));
+        assert!(!advisory.affects(&Version::new(4, 17, 21)));
+
+        // A zip export replaces the previous import.
+        let archive_path = source.path().join("all.zip");
+        let mut archive = zip::ZipWriter::new(std::fs::File::create(&archive_path).unwrap());
+        let scoped = record("GHSA-aaaa-bbbb-cccc", "npm", "@babel/traverse", serde_json::json!([{"introduced": "0"}, {"fixed": "7.23.2"}]));
+        archive.start_file("GHSA-aaaa-bbbb-cccc.json", zip::write::SimpleFileOptions::default()).unwrap();
+        archive.write_all(scoped.to_string().as_bytes()).unwrap();
+        archive.finish().unwrap();
+
+        database.import(&archive_path).await.unwrap();
+        let query = BTreeMap::from([
+            ("lodash".to_string(), BTreeSet::from(["4.17.20".to_string()])),
+            ("@babel/traverse".to_string(), BTreeSet::from(["7.23.0".to_string()])),
+        ]);
+        let advisories = database.advisories(&query).await.unwrap();
+        assert!(!advisories.contains_key("lodash"));
+        assert_eq!(advisories["@babel/traverse"][0].vulnerable_versions, "<7.23.2");
+    }
+}
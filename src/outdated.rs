@@ -0,0 +1,153 @@
+use crate::error::RegistryError;
+use crate::lockfile::LockFile;
+use crate::registry::RegistryClient;
+use crate::tree::Importer;
+use crate::workspace::Workspace;
+use futures::future::try_join_all;
+use semver::Version;
+use serde::Serialize;
+
+/// A dependency whose installed version is behind what its range or the
+/// `latest` dist-tag allows.
+#[derive(Debug, Clone, Serialize)]
+pub struct OutdatedDependency {
+    pub name: String,
+    /// The registry package, which differs from `name` for `npm:` aliases.
+    #[serde(skip)]
+    pub package: String,
+    /// The installed version, if any.
+    pub current: Option<Version>,
+    /// The highest version satisfying the declared range.
+    pub wanted: Option<Version>,
+    /// The version tagged `latest`.
+    pub latest: Option<Version>,
+    /// The manifest field declaring the dependency, e.g. `devDependencies`.
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// The project or workspace member declaring it.
+    pub workspace: String,
+    /// The range as written in `package.json`.
+    pub range: String,
+}
+
+impl OutdatedDependency {
+    pub fn row(&self) -> Vec<String> {
+        let show = |version: &Option<Version>| version.as_ref().map_or("-".to_string(), Version::to_string);
+        vec![
+            self.name.clone(),
+            self.current.as_ref().map_or("missing".to_string(), Version::to_string),
+            show(&self.wanted),
+            show(&self.latest),
+            self.kind.to_string(),
+            self.workspace.clone(),
+        ]
+    }
+}
+
+pub const OUTDATED_HEADERS: [&str; 6] = ["Package", "Current", "Wanted", "Latest", "Type", "Workspace"];
+
+/// Compares the registry dependencies declared by each importer with what is
+/// installed. Git, path and workspace dependencies are not checked.
+pub async fn find_outdated(
+    registry: &RegistryClient,
+    importers: &[Importer],
+    installed: &LockFile,
+    workspace: Option<&Workspace>,
+) -> Result<Vec<OutdatedDependency>, RegistryError> {
+    let candidates: Vec<_> = importers
+        .iter()
+        .flat_map(|importer| {
+            importer
+                .registry_edges(workspace)
+                .into_iter()
+                .map(move |(edge, package, version_req)| (importer, edge, package, version_req))
+        })
+        .collect();
+
+    let packuments = try_join_all(
+        candidates
+            .iter()
+            .map(|(_, _, package, _)| registry.fetch_packument(package)),
+    )
+    .await?;
+
+    let mut outdated: Vec<OutdatedDependency> = candidates
+        .into_iter()
+        .zip(packuments)
+        .filter_map(|((importer, edge, package, version_req), packument)| {
+            let current = installed
+                .get_dependency(&edge.name)
+                .and_then(|entry| Version::parse(entry.version()).ok());
+            let wanted = packument.max_satisfying(&version_req).map(|p| p.version.clone());
+            let latest = packument.latest().map(|p| p.version.clone());
+
+            let behind = |target: &Option<Version>| match (&current, target) {
+                (Some(current), Some(target)) => current < target,
+                _ => false,
+            };
+            if current.is_some() && !behind(&wanted) && !behind(&latest) {
+                return None;
+            }
+
+            Some(OutdatedDependency {
+                name: edge.name.clone(),
+                package,
+                current,
+                wanted,
+                latest,
+                kind: edge.kind.manifest_field(),
+                workspace: importer.name.clone(),
+                range: edge.range.clone(),
+            })
+        })
+        .collect();
+
+    outdated.sort_by(|a, b| (&a.workspace, &a.name).cmp(&(&b.workspace, &b.name)));
+    Ok(outdated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::PackageJson;
+    use crate::registry::mock::{packument, MockRegistry};
+    use crate::testing::{locked, lockfile};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_find_outdated() {
+        let documents = HashMap::from([
+            ("/lodash".to_string(), packument("lodash", &[("4.17.15", &[]), ("4.17.21", &[])])),
+            ("/react".to_string(), packument("react", &[("17.0.2", &[]), ("18.2.0", &[])])),
+            ("/jest".to_string(), packument("jest", &[("29.7.0", &[])])),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let client = RegistryClient::with_url(registry.url.clone());
+
+        let manifest: PackageJson = serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": {"lodash": "^4.17.0", "react": "^17.0.0", "local": "file:../local"},
+            "devDependencies": {"jest": "^29.0.0"}
+        }))
+        .unwrap();
+        let installed = lockfile(serde_json::json!({
+            "lodash": locked("4.17.15"),
+            "react": locked("17.0.2"),
+            "jest": locked("29.7.0")
+        }));
+
+        let importers = vec![Importer::from_manifest(&manifest)];
+        let outdated = find_outdated(&client, &importers, &installed, None).await.unwrap();
+
+        let rows: Vec<Vec<String>> = outdated.iter().map(OutdatedDependency::row).collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["lodash", "4.17.15", "4.17.21", "4.17.21", "dependencies", "app"],
+                vec!["react", "17.0.2", "17.0.2", "18.2.0", "dependencies", "app"],
+            ]
+        );
+    }
+}
@@ -1,26 +1,184 @@
 use anyhow::Result;
-use semver::Version;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
 use tokio::fs;
 use std::path::Path;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Package {
     pub name: String,
     pub version: Version,
+    #[serde(default)]
     pub dependencies: HashMap<String, String>,
+    #[serde(default)]
+    pub optional_dependencies: HashMap<String, String>,
+    #[serde(default)]
+    pub peer_dependencies: HashMap<String, String>,
+    #[serde(default, deserialize_with = "deserialize_repository", skip_serializing_if = "Option::is_none")]
+    pub repository: Option<Repository>,
     pub dist: PackageDistribution,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Package {
+    /// Dependencies installed alongside the package: regular and optional
+    /// ones. Peers are expected to be provided by the dependent.
+    pub fn installed_dependencies(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.dependencies.iter().chain(&self.optional_dependencies)
+    }
+}
+
+/// The `repository` field: a URL, a `github:user/repo` style shorthand, or
+/// `{ "type": "git", "url": ... }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Repository {
+    Shorthand(String),
+    Detailed {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        directory: Option<String>,
+    },
+}
+
+impl Repository {
+    pub fn url(&self) -> &str {
+        match self {
+            Repository::Shorthand(url) => url,
+            Repository::Detailed { url, .. } => url,
+        }
+    }
+
+    /// The repository's web page, e.g. `https://github.com/lodash/lodash` for
+    /// `git+https://github.com/lodash/lodash.git`.
+    pub fn web_url(&self) -> Option<String> {
+        let url = self.url().trim();
+
+        let shorthands = [
+            ("github:", "https://github.com/"),
+            ("gitlab:", "https://gitlab.com/"),
+            ("bitbucket:", "https://bitbucket.org/"),
+        ];
+        for (prefix, base) in shorthands {
+            if let Some(path) = url.strip_prefix(prefix) {
+                return Some(format!("{}{}", base, path.trim_end_matches(".git")));
+            }
+        }
+        if !url.contains(':') && url.split('/').count() == 2 {
+            return Some(format!("https://github.com/{}", url.trim_end_matches(".git")));
+        }
+
+        let url = url.strip_prefix("git+").unwrap_or(url);
+        // scp-like `git@github.com:user/repo.git`
+        let url = match url.strip_prefix("git@") {
+            Some(rest) => format!("https://{}", rest.replacen(':', "/", 1)),
+            None => url.to_string(),
+        };
+        let parsed = url::Url::parse(&url).ok()?;
+        let path = parsed.path().trim_end_matches('/').trim_end_matches(".git");
+        Some(format!("https://{}{}", parsed.host_str()?, path))
+    }
+}
+
+/// Some old packages have malformed `repository` fields; those are dropped
+/// rather than making the whole version unreadable.
+fn deserialize_repository<'de, D>(deserializer: D) -> Result<Option<Repository>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(raw.and_then(|value| serde_json::from_value(value).ok()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageDistribution {
     pub tarball: String,
     pub shasum: String,
+    /// A Subresource Integrity string, e.g. `sha512-<base64>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    /// The registry's signatures over `name@version:integrity`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<RegistrySignature>,
+    /// Where the registry serves the version's Sigstore attestations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestations: Option<DistAttestations>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DistAttestations {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegistrySignature {
+    pub keyid: String,
+    /// A base64 DER ECDSA signature.
+    pub sig: String,
+}
+
+/// Registry metadata for every published version of a package.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Packument {
+    pub name: String,
+    #[serde(rename = "dist-tags", default)]
+    pub dist_tags: HashMap<String, String>,
+    #[serde(default, deserialize_with = "deserialize_versions")]
+    pub versions: HashMap<Version, Package>,
+}
+
+impl Packument {
+    /// The version tagged `latest`, if it was published.
+    pub fn latest(&self) -> Option<&Package> {
+        let latest = Version::parse(self.dist_tags.get("latest")?).ok()?;
+        self.versions.get(&latest)
+    }
+
+    /// The version npm would pick for `version_req`: `latest` when it
+    /// satisfies the range, otherwise the highest matching version.
+    pub fn max_satisfying(&self, version_req: &VersionReq) -> Option<&Package> {
+        if let Some(latest) = self.latest() {
+            if version_req.matches(&latest.version) {
+                return Some(latest);
+            }
+        }
+
+        self.versions
+            .iter()
+            .filter(|(version, _)| version_req.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, package)| package)
+    }
+
+    /// Every published version, oldest first.
+    pub fn sorted_versions(&self) -> Vec<Version> {
+        let mut versions: Vec<Version> = self.versions.keys().cloned().collect();
+        versions.sort();
+        versions
+    }
+}
+
+/// Old packages occasionally contain versions that aren't valid semver;
+/// those entries are skipped rather than failing the whole document.
+fn deserialize_versions<'de, D>(deserializer: D) -> Result<HashMap<Version, Package>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw: HashMap<String, serde_json::Value> = HashMap::deserialize(deserializer)?;
+
+    Ok(raw
+        .into_values()
+        .filter_map(|value| serde_json::from_value::<Package>(value).ok())
+        .map(|package| (package.version.clone(), package))
+        .collect())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A project's `package.json`. The fields rpm works with are typed; the
+/// document they were read from is kept alongside, so saving writes back
+/// every other field with the original key order and formatting.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PackageJson {
     pub name: String,
     pub version: String,
@@ -28,20 +186,203 @@ pub struct PackageJson {
     pub dependencies: Option<HashMap<String, String>>,
     #[serde(rename = "devDependencies", skip_serializing_if = "Option::is_none")]
     pub dev_dependencies: Option<HashMap<String, String>>,
+    #[serde(rename = "optionalDependencies", default, skip_serializing_if = "Option::is_none")]
+    pub optional_dependencies: Option<HashMap<String, String>>,
+    #[serde(rename = "peerDependencies", default, skip_serializing_if = "Option::is_none")]
+    pub peer_dependencies: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scripts: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspaces: Option<Workspaces>,
+    #[serde(skip)]
+    source: Option<Source>,
+}
+
+/// The file a manifest was read from.
+#[derive(Debug, Clone)]
+struct Source {
+    document: Map<String, Value>,
+    indent: String,
+    line_ending: &'static str,
+    trailing_newline: bool,
+}
+
+impl Source {
+    fn parse(content: &str) -> Result<Self> {
+        // The first indented line tells how the file is indented.
+        let indent = content
+            .lines()
+            .skip(1)
+            .map(|line| &line[..line.len() - line.trim_start().len()])
+            .find(|indent| !indent.is_empty())
+            .unwrap_or("  ")
+            .to_string();
+
+        Ok(Self {
+            document: serde_json::from_str(content)?,
+            indent,
+            line_ending: if content.contains("\r\n") { "\r\n" } else { "\n" },
+            trailing_newline: content.ends_with('\n'),
+        })
+    }
+}
+
+impl Default for Source {
+    /// What `npm init` writes.
+    fn default() -> Self {
+        Self {
+            document: Map::new(),
+            indent: "  ".to_string(),
+            line_ending: "\n",
+            trailing_newline: true,
+        }
+    }
+}
+
+/// The `workspaces` field, either a plain list of globs or the
+/// `{ "packages": [...] }` object form used by yarn.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Workspaces {
+    Globs(Vec<String>),
+    Config { packages: Vec<String> },
+}
+
+impl Workspaces {
+    pub fn patterns(&self) -> &[String] {
+        match self {
+            Workspaces::Globs(globs) => globs,
+            Workspaces::Config { packages } => packages,
+        }
+    }
 }
 
 impl PackageJson {
-    pub async fn load() -> Result<Self> {
-        let content = fs::read_to_string("package.json").await?;
-        Ok(serde_json::from_str(&content)?)
+    /// Reads `package.json` in the project directory `root`.
+    pub async fn load(root: impl AsRef<Path>) -> Result<Self> {
+        Self::load_from(root.as_ref().join("package.json")).await
+    }
+
+    /// Writes `package.json` in the project directory `root`.
+    pub async fn save(&self, root: impl AsRef<Path>) -> Result<()> {
+        self.save_to(root.as_ref().join("package.json")).await
     }
 
-    pub async fn save(&self) -> Result<()> {
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write("package.json", content).await?;
+    pub async fn save_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_json_string()?).await?;
         Ok(())
     }
 
+    /// Parses a manifest, keeping the document for [`Self::to_json_string`].
+    pub fn from_json_str(content: &str) -> Result<Self> {
+        let source = Source::parse(content)?;
+        let mut manifest: Self = serde_json::from_value(Value::Object(source.document.clone()))?;
+        manifest.source = Some(source);
+        Ok(manifest)
+    }
+
+    /// Serializes the manifest into the document it was read from. Only keys
+    /// whose typed value changed are touched: existing keys keep their place,
+    /// new dependencies go in alphabetically if the group was sorted and at
+    /// the end otherwise.
+    pub fn to_json_string(&self) -> Result<String> {
+        let source = self.source.clone().unwrap_or_default();
+        let mut document = source.document;
+
+        set_field(&mut document, "name", Some(Value::String(self.name.clone())));
+        set_field(&mut document, "version", Some(Value::String(self.version.clone())));
+        merge_group(&mut document, "dependencies", self.dependencies.as_ref());
+        merge_group(&mut document, "devDependencies", self.dev_dependencies.as_ref());
+        merge_group(&mut document, "optionalDependencies", self.optional_dependencies.as_ref());
+        merge_group(&mut document, "peerDependencies", self.peer_dependencies.as_ref());
+        merge_group(&mut document, "scripts", self.scripts.as_ref());
+        set_field(
+            &mut document,
+            "workspaces",
+            self.workspaces.as_ref().map(serde_json::to_value).transpose()?,
+        );
+
+        let mut content = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(source.indent.as_bytes());
+        let mut serializer = serde_json::Serializer::with_formatter(&mut content, formatter);
+        Value::Object(document).serialize(&mut serializer)?;
+
+        let mut content = String::from_utf8(content)?;
+        if source.trailing_newline {
+            content.push('\n');
+        }
+        if source.line_ending != "\n" {
+            content = content.replace('\n', source.line_ending);
+        }
+        Ok(content)
+    }
+
+    /// A field rpm doesn't model, such as `main` or `engines`, as it was read.
+    pub fn field(&self, key: &str) -> Option<&Value> {
+        self.source.as_ref()?.document.get(key)
+    }
+
+    /// The flat entries of `overrides`, which force every dependency on a
+    /// package to the given specifier. npm's nested form, scoping an
+    /// override to one dependent, isn't supported and is left out.
+    pub fn overrides(&self) -> HashMap<String, String> {
+        match self.field("overrides") {
+            Some(Value::Object(overrides)) => overrides
+                .iter()
+                .filter_map(|(name, spec)| Some((name.clone(), spec.as_str()?.to_string())))
+                .collect(),
+            _ => HashMap::new(),
+        }
+    }
+
+    /// Sets the flat override for `package`, adding the `overrides` field if
+    /// needed. Returns whether the manifest changed.
+    pub fn set_override(&mut self, package: &str, spec: &str) -> bool {
+        let document = &mut self.source.get_or_insert_with(Source::default).document;
+        let overrides = document
+            .entry("overrides")
+            .or_insert_with(|| Value::Object(Map::new()));
+        let Value::Object(overrides) = overrides else {
+            return false;
+        };
+
+        let spec = Value::String(spec.to_string());
+        if overrides.get(package) == Some(&spec) {
+            return false;
+        }
+        overrides.insert(package.to_string(), spec);
+        true
+    }
+
+    pub fn add_dependency(&mut self, package: &str, range: &str, dev: bool) {
+        let deps = if dev {
+            self.dev_dependencies.get_or_insert_with(HashMap::new)
+        } else {
+            self.dependencies.get_or_insert_with(HashMap::new)
+        };
+        deps.insert(package.to_string(), range.to_string());
+    }
+
+    /// Replaces the range of `package` in `field`, e.g. `devDependencies`.
+    /// Returns whether the manifest changed.
+    pub fn set_range(&mut self, field: &str, package: &str, range: &str) -> bool {
+        let group = match field {
+            "dependencies" => &mut self.dependencies,
+            "devDependencies" => &mut self.dev_dependencies,
+            "optionalDependencies" => &mut self.optional_dependencies,
+            "peerDependencies" => &mut self.peer_dependencies,
+            _ => return false,
+        };
+
+        match group.as_mut().and_then(|deps| deps.get_mut(package)) {
+            Some(current) if current != range => {
+                *current = range.to_string();
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn remove_dependency(&mut self, package: &str) {
         if let Some(deps) = &mut self.dependencies {
             deps.remove(package);
@@ -49,11 +390,63 @@ impl PackageJson {
         if let Some(dev_deps) = &mut self.dev_dependencies {
             dev_deps.remove(package);
         }
+        if let Some(optional_deps) = &mut self.optional_dependencies {
+            optional_deps.remove(package);
+        }
     }
 
     pub async fn load_from(path: impl AsRef<Path>) -> Result<Self> {
         let content = fs::read_to_string(path).await?;
-        Ok(serde_json::from_str(&content)?)
+        Self::from_json_str(&content)
+    }
+}
+
+/// Sets a top-level field, leaving it untouched when the value is the same.
+fn set_field(document: &mut Map<String, Value>, key: &str, value: Option<Value>) {
+    match value {
+        Some(value) if document.get(key) != Some(&value) => {
+            document.insert(key.to_string(), value);
+        }
+        Some(_) => {}
+        None => {
+            document.shift_remove(key);
+        }
+    }
+}
+
+/// Brings a name-to-string group such as `dependencies` in line with
+/// `entries`, editing only the entries that differ.
+fn merge_group(document: &mut Map<String, Value>, key: &str, entries: Option<&HashMap<String, String>>) {
+    let Some(entries) = entries else {
+        document.shift_remove(key);
+        return;
+    };
+    let sorted: BTreeMap<&String, &String> = entries.iter().collect();
+
+    let Some(Value::Object(group)) = document.get_mut(key) else {
+        let group = sorted
+            .into_iter()
+            .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+            .collect();
+        document.insert(key.to_string(), Value::Object(group));
+        return;
+    };
+
+    group.retain(|name, _| entries.contains_key(name));
+    let was_sorted = group.keys().zip(group.keys().skip(1)).all(|(a, b)| a <= b);
+    for (name, value) in sorted {
+        let value = Value::String(value.clone());
+        if group.contains_key(name.as_str()) || !was_sorted {
+            if group.get(name.as_str()) != Some(&value) {
+                group.insert(name.clone(), value);
+            }
+            continue;
+        }
+
+        let mut rebuilt: Vec<(String, Value)> = std::mem::take(group).into_iter().collect();
+        let position = rebuilt.partition_point(|(existing, _)| existing < name);
+        rebuilt.insert(position, (name.clone(), value));
+        *group = rebuilt.into_iter().collect();
     }
 }
 
@@ -75,6 +468,9 @@ mod tests {
                 ("typescript".to_string(), "^4.5.4".to_string()),
                 ("jest".to_string(), "^27.4.7".to_string()),
             ])),
+            optional_dependencies: None,
+            peer_dependencies: None,
+            ..Default::default()
         };
 
         let content = serde_json::to_string_pretty(&package_json)?;
@@ -85,10 +481,9 @@ mod tests {
     #[tokio::test]
     async fn test_load_package_json() -> Result<()> {
         let temp_dir = tempdir()?;
-        std::env::set_current_dir(&temp_dir)?;
         create_test_package_json(temp_dir.path()).await?;
 
-        let package_json = PackageJson::load().await?;
+        let package_json = PackageJson::load(temp_dir.path()).await?;
 
         assert_eq!(package_json.name, "test-package");
         assert_eq!(package_json.version, "1.0.0");
@@ -107,7 +502,6 @@ mod tests {
     #[tokio::test]
     async fn test_save_package_json() -> Result<()> {
         let temp_dir = tempdir()?;
-        std::env::set_current_dir(&temp_dir)?;
 
         let package_json = PackageJson {
             name: "save-test".to_string(),
@@ -117,11 +511,14 @@ mod tests {
                 "^4.17.21".to_string(),
             )])),
             dev_dependencies: None,
+            optional_dependencies: None,
+            peer_dependencies: None,
+            ..Default::default()
         };
 
-        package_json.save().await?;
+        package_json.save(temp_dir.path()).await?;
 
-        let content = fs::read_to_string("package.json").await?;
+        let content = fs::read_to_string(temp_dir.path().join("package.json")).await?;
         let loaded: PackageJson = serde_json::from_str(&content)?;
 
         assert_eq!(loaded.name, "save-test");
@@ -137,10 +534,9 @@ mod tests {
     #[tokio::test]
     async fn test_remove_dependency() -> Result<()> {
         let temp_dir = tempdir()?;
-        std::env::set_current_dir(&temp_dir)?;
         create_test_package_json(temp_dir.path()).await?;
 
-        let mut package_json = PackageJson::load().await?;
+        let mut package_json = PackageJson::load(temp_dir.path()).await?;
 
         package_json.remove_dependency("express");
         package_json.remove_dependency("typescript");
@@ -159,10 +555,9 @@ mod tests {
     #[tokio::test]
     async fn test_remove_nonexistent_dependency() -> Result<()> {
         let temp_dir = tempdir()?;
-        std::env::set_current_dir(&temp_dir)?;
         create_test_package_json(temp_dir.path()).await?;
 
-        let mut package_json = PackageJson::load().await?;
+        let mut package_json = PackageJson::load(temp_dir.path()).await?;
 
         package_json.remove_dependency("nonexistent-package");
 
@@ -173,4 +568,82 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_round_trip_preserves_document() -> Result<()> {
+        let content = r#"{
+    "name": "app",
+    "version": "1.0.0",
+    "main": "index.js",
+    "scripts": {
+        "test": "jest",
+        "build": "tsc"
+    },
+    "dependencies": {
+        "react": "^17.0.2",
+        "express": "^4.17.1"
+    },
+    "devDependencies": {
+        "jest": "^29.0.0",
+        "typescript": "^5.0.0"
+    },
+    "engines": {
+        "node": ">=18"
+    }
+}
+"#;
+
+        let mut package_json = PackageJson::from_json_str(content)?;
+        assert_eq!(package_json.to_json_string()?, content);
+        assert_eq!(package_json.field("main"), Some(&Value::from("index.js")));
+
+        package_json.remove_dependency("express");
+        package_json.set_range("dependencies", "react", "^18.2.0");
+        package_json.add_dependency("eslint", "^8.0.0", true);
+
+        // `dependencies` wasn't sorted, `devDependencies` was.
+        let expected = content
+            .replace("        \"express\": \"^4.17.1\"\n", "")
+            .replace(r#""react": "^17.0.2","#, r#""react": "^18.2.0""#)
+            .replace(
+                "        \"jest\"",
+                "        \"eslint\": \"^8.0.0\",\n        \"jest\"",
+            );
+        assert_eq!(package_json.to_json_string()?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip_keeps_formatting() -> Result<()> {
+        let content = "{\r\n\t\"name\": \"app\",\r\n\t\"version\": \"1.0.0\"\r\n}";
+        let mut package_json = PackageJson::from_json_str(content)?;
+        assert_eq!(package_json.to_json_string()?, content);
+
+        package_json.add_dependency("lodash", "^4.17.21", false);
+        assert_eq!(
+            package_json.to_json_string()?,
+            "{\r\n\t\"name\": \"app\",\r\n\t\"version\": \"1.0.0\",\r\n\t\"dependencies\": {\r\n\t\t\"lodash\": \"^4.17.21\"\r\n\t}\r\n}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_repository_web_url() {
+        let web_url = |value: serde_json::Value| {
+            serde_json::from_value::<Repository>(value).unwrap().web_url()
+        };
+
+        let lodash = Some("https://github.com/lodash/lodash".to_string());
+        assert_eq!(web_url(serde_json::json!("github:lodash/lodash")), lodash);
+        assert_eq!(web_url(serde_json::json!("lodash/lodash")), lodash);
+        assert_eq!(web_url(serde_json::json!({"type": "git", "url": "git+https://github.com/lodash/lodash.git"})), lodash);
+        assert_eq!(web_url(serde_json::json!("git@github.com:lodash/lodash.git")), lodash);
+        assert_eq!(web_url(serde_json::json!("git+ssh://git@github.com/lodash/lodash.git")), lodash);
+        assert_eq!(
+            web_url(serde_json::json!("https://gitlab.com/group/project")),
+            Some("https://gitlab.com/group/project".to_string())
+        );
+    }
 }
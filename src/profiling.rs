@@ -3,6 +3,7 @@ use std::sync::Arc;
 use tokio::time::Duration;
 use log::{info, warn};
 
+#[derive(Clone)]
 pub struct MemoryProfile {
     allocated: Arc<AtomicUsize>,
     peak: Arc<AtomicUsize>,
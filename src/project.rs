@@ -0,0 +1,141 @@
+use crate::workspace::Workspace;
+use std::path::{Path, PathBuf};
+
+/// The directory a command operates on, holding the `package.json`,
+/// `node_modules` and lockfile it reads and writes.
+#[derive(Debug, Clone)]
+pub struct ProjectRoot {
+    pub root: PathBuf,
+    /// The workspace member the command was started in, when the root is a
+    /// workspace root found above it.
+    pub member: Option<String>,
+}
+
+impl ProjectRoot {
+    /// Uses `prefix` as is when given. Otherwise walks up from `cwd` to the
+    /// nearest `package.json`, then on to the workspace root if that package
+    /// is one of its members. Falls back to `cwd` when there's no
+    /// `package.json` at all, so commands that don't need one still run.
+    ///
+    /// Only the nearest workspace root above the package is considered, and
+    /// a `package.json` above it that can't be read doesn't make one.
+    pub async fn locate(cwd: &Path, prefix: Option<&Path>) -> Self {
+        if let Some(prefix) = prefix {
+            return Self::at(cwd.join(prefix));
+        }
+
+        let Some(package) = cwd.ancestors().find(|dir| dir.join("package.json").is_file()) else {
+            return Self::at(cwd.to_path_buf());
+        };
+
+        for dir in package.ancestors().skip(1) {
+            let workspace = match Workspace::discover(dir).await {
+                Ok(Some(workspace)) => workspace,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::debug!("Not treating {} as a workspace root: {}", dir.display(), e);
+                    continue;
+                }
+            };
+            if let Some(member) = workspace.members.iter().find(|member| same_dir(&member.path, package)) {
+                return Self { root: dir.to_path_buf(), member: Some(member.name.clone()) };
+            }
+            break;
+        }
+
+        Self::at(package.to_path_buf())
+    }
+
+    /// `root` itself, for commands that don't work on a project, like
+    /// global installs.
+    pub fn at(root: PathBuf) -> Self {
+        Self { root, member: None }
+    }
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write(path: &Path, manifest: serde_json::Value) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, manifest.to_string()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_locate() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(
+            &root.join("package.json"),
+            serde_json::json!({"name": "monorepo", "version": "1.0.0", "workspaces": ["packages/*"]}),
+        );
+        write(
+            &root.join("packages/app/package.json"),
+            serde_json::json!({"name": "app", "version": "1.0.0"}),
+        );
+        write(
+            &root.join("tools/script/package.json"),
+            serde_json::json!({"name": "script", "version": "1.0.0"}),
+        );
+        std::fs::create_dir_all(root.join("packages/app/src/components")).unwrap();
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+
+        let located = ProjectRoot::locate(&root.join("packages/app/src/components"), None).await;
+        assert_eq!(located.root, root);
+        assert_eq!(located.member.as_deref(), Some("app"));
+
+        let located = ProjectRoot::locate(&root.join("docs"), None).await;
+        assert_eq!(located.root, root);
+        assert_eq!(located.member, None);
+
+        // Not matched by the `workspaces` globs, so it's a project of its own.
+        let located = ProjectRoot::locate(&root.join("tools/script"), None).await;
+        assert_eq!(located.root, root.join("tools/script"));
+        assert_eq!(located.member, None);
+
+        let located = ProjectRoot::locate(&root.join("docs"), Some(Path::new("../tools/script")))
+            .await;
+        assert_eq!(located.root, root.join("docs/../tools/script"));
+    }
+
+    #[tokio::test]
+    async fn test_locate_stops_at_nearest_workspace() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("package.json"), "{ not json").unwrap();
+        write(
+            &root.join("lib/package.json"),
+            serde_json::json!({"name": "lib", "version": "1.0.0"}),
+        );
+        write(
+            &root.join("outer/package.json"),
+            serde_json::json!({"name": "outer", "version": "1.0.0", "workspaces": ["repo/packages/*"]}),
+        );
+        write(
+            &root.join("outer/repo/package.json"),
+            serde_json::json!({"name": "monorepo", "version": "1.0.0", "workspaces": ["libs/*"]}),
+        );
+        write(
+            &root.join("outer/repo/packages/app/package.json"),
+            serde_json::json!({"name": "app", "version": "1.0.0"}),
+        );
+
+        // An unreadable manifest above isn't an error.
+        let located = ProjectRoot::locate(&root.join("lib"), None).await;
+        assert_eq!(located.root, root.join("lib"));
+
+        // `repo` doesn't list it, and `outer` is never looked at.
+        let located = ProjectRoot::locate(&root.join("outer/repo/packages/app"), None).await;
+        assert_eq!(located.root, root.join("outer/repo/packages/app"));
+        assert_eq!(located.member, None);
+    }
+}
@@ -0,0 +1,902 @@
+use crate::package::Package;
+use crate::registry::RegistryClient;
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use p256::pkcs8::DecodePublicKey;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256, Sha384};
+use x509_cert::der::asn1::{ObjectIdentifier, Utf8StringRef};
+use x509_cert::der::{Decode, Encode};
+use x509_cert::Certificate;
+
+/// The trust root shipped with rpm, in the `trusted_root.json` format
+/// sigstore/root-signing publishes Sigstore's public-good instance in.
+/// Attestations are checked against it without contacting Fulcio or Rekor.
+const TRUSTED_ROOT: &str = include_str!("trusted_root.json");
+
+const SLSA_PREDICATE_PREFIX: &str = "https://slsa.dev/provenance/";
+const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+const ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+/// Fulcio's Build Signer URI, e.g.
+/// `https://github.com/o/r/.github/workflows/release.yml@refs/tags/v1.0.0`.
+pub const BUILD_SIGNER_URI: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.9");
+/// Fulcio's Source Repository URI, e.g. `https://github.com/o/r`.
+pub const SOURCE_REPOSITORY_URI: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57264.1.12");
+
+/// What is known about where an installed version was built, as recorded
+/// in the lockfile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum Provenance {
+    /// A verified attestation says `workflow` in `repository` built it.
+    Verified { repository: String, workflow: String },
+    /// The registry has no attestations for it.
+    Unattested,
+    /// Attestations were published but don't verify.
+    Invalid { reason: String },
+    /// The attestations or metadata needed to check them couldn't be
+    /// fetched, so nothing is known either way.
+    Unavailable { reason: String },
+}
+
+impl Provenance {
+    pub fn is_verified(&self) -> bool {
+        matches!(self, Provenance::Verified { .. })
+    }
+}
+
+/// The certificate authorities and transparency logs attestations are
+/// checked against. Only the parts used offline are read.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustRoot {
+    #[serde(default)]
+    pub certificate_authorities: Vec<CertificateAuthority>,
+    #[serde(default)]
+    pub tlogs: Vec<TransparencyLog>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateAuthority {
+    #[serde(default)]
+    pub uri: String,
+    /// Issuer first, root last.
+    pub cert_chain: CertificateChain,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateChain {
+    pub certificates: Vec<RawBytes>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawBytes {
+    /// Base64 DER.
+    pub raw_bytes: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransparencyLog {
+    #[serde(default)]
+    pub base_url: String,
+    pub public_key: RawBytes,
+    pub log_id: LogId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogId {
+    /// Base64 SHA-256 of the log's public key.
+    pub key_id: String,
+}
+
+/// One entry of the registry's `/-/npm/v1/attestations/<name>@<version>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attestation {
+    pub predicate_type: String,
+    pub bundle: Bundle,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationList {
+    pub attestations: Vec<Attestation>,
+}
+
+/// A Sigstore bundle holding a DSSE envelope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    #[serde(default)]
+    pub media_type: String,
+    pub verification_material: VerificationMaterial,
+    pub dsse_envelope: Envelope,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationMaterial {
+    /// Newer bundles carry the leaf certificate alone, older ones a chain
+    /// starting with it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<RawBytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x509_certificate_chain: Option<CertificateChain>,
+    #[serde(default)]
+    pub tlog_entries: Vec<TlogEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlogEntry {
+    #[serde(deserialize_with = "deserialize_int")]
+    pub log_index: u64,
+    pub log_id: LogId,
+    #[serde(deserialize_with = "deserialize_int")]
+    pub integrated_time: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inclusion_promise: Option<InclusionPromise>,
+    /// Base64 of the entry Rekor stored.
+    pub canonicalized_body: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionPromise {
+    /// Base64 DER signature of the log over the entry.
+    pub signed_entry_timestamp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    /// Base64 of the in-toto statement.
+    pub payload: String,
+    pub payload_type: String,
+    pub signatures: Vec<EnvelopeSignature>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvelopeSignature {
+    pub sig: String,
+    #[serde(default)]
+    pub keyid: String,
+}
+
+/// Protobuf JSON writes 64-bit integers as strings.
+fn deserialize_int<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Int {
+        Number(u64),
+        Text(String),
+    }
+
+    match Int::deserialize(deserializer)? {
+        Int::Number(value) => Ok(value),
+        Int::Text(value) => value.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+impl TrustRoot {
+    /// The trust root shipped with rpm.
+    pub fn bundled() -> Result<Self> {
+        Self::from_json(TRUSTED_ROOT)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self> {
+        Ok(serde_json::from_str(content)?)
+    }
+
+    /// Whether a bundled certificate authority issued `leaf`, with every
+    /// certificate on the way valid at `time` (seconds since the epoch).
+    fn verify_chain(&self, leaf: &Certificate, time: u64) -> Result<(), String> {
+        check_validity(leaf, time)?;
+        for authority in &self.certificate_authorities {
+            let chain: Option<Vec<Certificate>> = authority
+                .cert_chain
+                .certificates
+                .iter()
+                .map(|raw| STANDARD.decode(&raw.raw_bytes).ok().and_then(|der| Certificate::from_der(&der).ok()))
+                .collect();
+            let Some(chain) = chain else {
+                log::warn!("Skipping the unreadable certificate authority {}", authority.uri);
+                continue;
+            };
+
+            let mut child = leaf;
+            let issued = chain.iter().all(|issuer| {
+                let valid = is_signed_by(child, issuer) && check_validity(issuer, time).is_ok();
+                child = issuer;
+                valid
+            });
+            if issued && !chain.is_empty() {
+                return Ok(());
+            }
+        }
+        Err("the signing certificate wasn't issued by a trusted certificate authority".to_string())
+    }
+
+    /// Checks the log's promise to include `entry`, returning the time it
+    /// was logged at. The entry has to be about `payload`, and record `leaf`
+    /// and one of the signatures in `envelope`.
+    fn verify_tlog_entry(
+        &self,
+        entry: &TlogEntry,
+        envelope: &Envelope,
+        payload: &[u8],
+        leaf: &Certificate,
+    ) -> Result<u64, String> {
+        let log = self
+            .tlogs
+            .iter()
+            .find(|log| log.log_id.key_id == entry.log_id.key_id)
+            .ok_or_else(|| format!("logged in an unknown transparency log {}", entry.log_id.key_id))?;
+        let promise = entry
+            .inclusion_promise
+            .as_ref()
+            .ok_or_else(|| "the transparency log entry has no inclusion promise".to_string())?;
+
+        // What the log signed: canonical JSON, keys sorted, no whitespace.
+        let log_id = STANDARD.decode(&entry.log_id.key_id).map_err(|_| "malformed log ID".to_string())?;
+        let signed = serde_json::json!({
+            "body": entry.canonicalized_body,
+            "integratedTime": entry.integrated_time,
+            "logID": hex::encode(log_id),
+            "logIndex": entry.log_index,
+        })
+        .to_string();
+        let key = STANDARD.decode(&log.public_key.raw_bytes).map_err(|_| "malformed log key".to_string())?;
+        let signature = STANDARD
+            .decode(&promise.signed_entry_timestamp)
+            .map_err(|_| "malformed inclusion promise".to_string())?;
+        if !verify_ecdsa(&key, &Sha256::digest(signed.as_bytes()), &signature) {
+            return Err("the transparency log's inclusion promise doesn't verify".to_string());
+        }
+
+        let body: serde_json::Value = STANDARD
+            .decode(&entry.canonicalized_body)
+            .ok()
+            .and_then(|body| serde_json::from_slice(&body).ok())
+            .ok_or_else(|| "unreadable transparency log entry".to_string())?;
+        // `intoto` entries nest the hash under `content`, `dsse` ones don't.
+        let logged_hash = body
+            .pointer("/spec/content/payloadHash/value")
+            .or_else(|| body.pointer("/spec/payloadHash/value"))
+            .and_then(|value| value.as_str());
+        if logged_hash != Some(hex::encode(Sha256::digest(payload)).as_str()) {
+            return Err("the transparency log entry is about another attestation".to_string());
+        }
+        check_logged_signature(&body, envelope, leaf)?;
+        Ok(entry.integrated_time)
+    }
+}
+
+/// Whether the registry publishes attestations for `package` and, if so,
+/// whether its SLSA provenance verifies against `trust_root`.
+pub async fn check_provenance(registry: &RegistryClient, trust_root: &TrustRoot, package: &Package) -> Result<Provenance> {
+    let Some(attestations) = &package.dist.attestations else {
+        return Ok(Provenance::Unattested);
+    };
+    let attestations = registry.fetch_attestations(&attestations.url).await?;
+    if package.repository.is_some() {
+        return Ok(verify_provenance(trust_root, package, &attestations));
+    }
+
+    // Abbreviated metadata leaves `repository` out; the version document
+    // has it.
+    let document = registry
+        .fetch_package_info(&package.name, Some(&package.version.to_string()))
+        .await?;
+    let package = Package { repository: document.repository, ..package.clone() };
+    Ok(verify_provenance(trust_root, &package, &attestations))
+}
+
+/// Verifies the SLSA provenance among `attestations` offline: the signing
+/// certificate chains up to `trust_root`, the transparency log promised to
+/// include the attestation while the certificate was valid, the envelope
+/// is signed by the certificate's key, its subject is this exact tarball,
+/// and the repository and workflow it names, both in the statement and in
+/// the certificate, match the package's `repository` field.
+pub fn verify_provenance(trust_root: &TrustRoot, package: &Package, attestations: &[Attestation]) -> Provenance {
+    let Some(attestation) = attestations
+        .iter()
+        .find(|attestation| attestation.predicate_type.starts_with(SLSA_PREDICATE_PREFIX))
+    else {
+        return Provenance::Invalid { reason: "no SLSA provenance attestation".to_string() };
+    };
+    match verify_bundle(trust_root, package, &attestation.bundle) {
+        Ok((repository, workflow)) => Provenance::Verified { repository, workflow },
+        Err(reason) => Provenance::Invalid { reason },
+    }
+}
+
+fn verify_bundle(trust_root: &TrustRoot, package: &Package, bundle: &Bundle) -> Result<(String, String), String> {
+    let material = &bundle.verification_material;
+    let leaf = material
+        .certificate
+        .as_ref()
+        .or_else(|| material.x509_certificate_chain.as_ref().and_then(|chain| chain.certificates.first()))
+        .ok_or_else(|| "the attestation has no signing certificate".to_string())?;
+    let leaf = STANDARD
+        .decode(&leaf.raw_bytes)
+        .ok()
+        .and_then(|der| Certificate::from_der(&der).ok())
+        .ok_or_else(|| "unreadable signing certificate".to_string())?;
+
+    let envelope = &bundle.dsse_envelope;
+    let payload = STANDARD.decode(&envelope.payload).map_err(|_| "malformed attestation payload".to_string())?;
+    let entry = material
+        .tlog_entries
+        .first()
+        .ok_or_else(|| "the attestation isn't in a transparency log".to_string())?;
+    let logged_at = trust_root.verify_tlog_entry(entry, envelope, &payload, &leaf)?;
+    trust_root.verify_chain(&leaf, logged_at)?;
+
+    // DSSE signs the pre-authentication encoding, not the bare payload.
+    let mut pae = format!("DSSEv1 {} {} {} ", envelope.payload_type.len(), envelope.payload_type, payload.len()).into_bytes();
+    pae.extend_from_slice(&payload);
+    let leaf_key = leaf
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|_| "unreadable signing key".to_string())?;
+    let signed = envelope.signatures.iter().any(|signature| {
+        STANDARD
+            .decode(&signature.sig)
+            .is_ok_and(|sig| verify_ecdsa(&leaf_key, &Sha256::digest(&pae), &sig))
+    });
+    if !signed {
+        return Err("the attestation's signature doesn't match its certificate".to_string());
+    }
+
+    if envelope.payload_type != IN_TOTO_PAYLOAD_TYPE {
+        return Err(format!("unexpected payload type {}", envelope.payload_type));
+    }
+    let statement: serde_json::Value =
+        serde_json::from_slice(&payload).map_err(|_| "unreadable in-toto statement".to_string())?;
+    check_subject(package, &statement)?;
+    check_source(package, &statement, &leaf)
+}
+
+/// The log entry `body` has to record the bundle's signing certificate and
+/// one of its envelope signatures, or the log vouches for another signing.
+/// `dsse` entries list each signature with the certificate that verifies
+/// it; `intoto` ones keep the envelope, with signatures encoded twice.
+fn check_logged_signature(body: &serde_json::Value, envelope: &Envelope, leaf: &Certificate) -> Result<(), String> {
+    let leaf = leaf.to_der().map_err(|_| "unreadable signing certificate".to_string())?;
+    let signed: Vec<Vec<u8>> =
+        envelope.signatures.iter().filter_map(|signature| STANDARD.decode(&signature.sig).ok()).collect();
+    let decode = |value: &serde_json::Value| value.as_str().and_then(|value| STANDARD.decode(value).ok());
+
+    let (logged, signature_field, key_field, encoded_twice) = match body.pointer("/spec/signatures") {
+        Some(logged) => (logged, "signature", "verifier", false),
+        None => {
+            let logged = body.pointer("/spec/content/envelope/signatures").unwrap_or(&serde_json::Value::Null);
+            (logged, "sig", "publicKey", true)
+        }
+    };
+    let matches = logged.as_array().into_iter().flatten().any(|logged| {
+        let signature = match decode(&logged[signature_field]) {
+            Some(signature) if encoded_twice => STANDARD.decode(signature).ok(),
+            signature => signature,
+        };
+        let certificate = decode(&logged[key_field]).and_then(|pem| pem_to_der(&String::from_utf8_lossy(&pem)));
+        let certified = certificate.as_deref() == Some(leaf.as_slice());
+        certified && signature.is_some_and(|signature| signed.contains(&signature))
+    });
+    if !matches {
+        return Err("the transparency log entry records another signature or certificate".to_string());
+    }
+    Ok(())
+}
+
+/// The DER inside a PEM block.
+fn pem_to_der(pem: &str) -> Option<Vec<u8>> {
+    let encoded: String = pem.lines().filter(|line| !line.starts_with("-----")).map(str::trim).collect();
+    STANDARD.decode(encoded).ok()
+}
+
+/// The statement has to be about this version's tarball.
+fn check_subject(package: &Package, statement: &serde_json::Value) -> Result<(), String> {
+    let purl = format!("pkg:npm/{}@{}", package.name.replacen('@', "%40", 1), package.version);
+    let digest = package
+        .dist
+        .integrity
+        .as_deref()
+        .and_then(|integrity| integrity.split_whitespace().find_map(|hash| hash.strip_prefix("sha512-")))
+        .and_then(|digest| STANDARD.decode(digest).ok())
+        .map(hex::encode)
+        .ok_or_else(|| "the package has no SHA-512 integrity to compare with".to_string())?;
+
+    let subjects = statement["subject"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let matches = subjects.iter().any(|subject| {
+        subject["name"].as_str() == Some(purl.as_str()) && subject["digest"]["sha512"].as_str() == Some(digest.as_str())
+    });
+    if !matches {
+        return Err(format!("the attestation is about another artifact than {}", purl));
+    }
+    Ok(())
+}
+
+/// The repository and workflow path, once the statement and the
+/// certificate agree with each other and with the package's `repository`.
+fn check_source(package: &Package, statement: &serde_json::Value, leaf: &Certificate) -> Result<(String, String), String> {
+    let workflow = &statement["predicate"]["buildDefinition"]["externalParameters"]["workflow"];
+    let (Some(repository), Some(path), Some(reference)) =
+        (workflow["repository"].as_str(), workflow["path"].as_str(), workflow["ref"].as_str())
+    else {
+        return Err("the provenance names no source workflow".to_string());
+    };
+    let repository = normalize_repository(repository);
+
+    let declared = package
+        .repository
+        .as_ref()
+        .and_then(|declared| declared.web_url())
+        .ok_or_else(|| "the package has no repository field to compare with".to_string())?;
+    if normalize_repository(&declared) != repository {
+        return Err(format!("built from {}, but the package's repository is {}", repository, declared));
+    }
+
+    let certified = extension(leaf, SOURCE_REPOSITORY_URI).map(|uri| normalize_repository(&uri));
+    if certified.as_deref() != Some(repository.as_str()) {
+        return Err("the certificate is for another repository".to_string());
+    }
+    let signer = extension(leaf, BUILD_SIGNER_URI).unwrap_or_default();
+    let expected = format!("{}/{}@{}", repository, path, reference);
+    if !signer.eq_ignore_ascii_case(&expected) {
+        return Err(format!("signed by {}, not by the attested workflow {}", signer, expected));
+    }
+
+    Ok((repository, path.to_string()))
+}
+
+/// `https://github.com/o/r` for `https://github.com/O/r.git/`.
+fn normalize_repository(url: &str) -> String {
+    url.trim_end_matches('/').trim_end_matches(".git").to_ascii_lowercase()
+}
+
+/// A Fulcio extension holding a UTF-8 string.
+fn extension(certificate: &Certificate, oid: ObjectIdentifier) -> Option<String> {
+    let extension = certificate
+        .tbs_certificate
+        .extensions
+        .as_ref()?
+        .iter()
+        .find(|extension| extension.extn_id == oid)?;
+    let value = Utf8StringRef::from_der(extension.extn_value.as_bytes()).ok()?;
+    Some(value.as_str().to_string())
+}
+
+fn check_validity(certificate: &Certificate, time: u64) -> Result<(), String> {
+    let validity = &certificate.tbs_certificate.validity;
+    let not_before = validity.not_before.to_unix_duration().as_secs();
+    let not_after = validity.not_after.to_unix_duration().as_secs();
+    if time < not_before || time > not_after {
+        return Err("a certificate wasn't valid when the attestation was logged".to_string());
+    }
+    Ok(())
+}
+
+fn is_signed_by(certificate: &Certificate, issuer: &Certificate) -> bool {
+    let Ok(tbs) = certificate.tbs_certificate.to_der() else {
+        return false;
+    };
+    let digest = match certificate.signature_algorithm.oid {
+        ECDSA_WITH_SHA256 => Sha256::digest(&tbs).to_vec(),
+        ECDSA_WITH_SHA384 => Sha384::digest(&tbs).to_vec(),
+        _ => return false,
+    };
+    let Ok(issuer_key) = issuer.tbs_certificate.subject_public_key_info.to_der() else {
+        return false;
+    };
+    verify_ecdsa(&issuer_key, &digest, certificate.signature.raw_bytes())
+}
+
+/// Verifies a DER ECDSA signature over `digest` with a P-256 or P-384
+/// public key given as DER SubjectPublicKeyInfo.
+fn verify_ecdsa(public_key: &[u8], digest: &[u8], signature: &[u8]) -> bool {
+    use p256::ecdsa::signature::hazmat::PrehashVerifier;
+
+    if let Ok(key) = p256::ecdsa::VerifyingKey::from_public_key_der(public_key) {
+        return p256::ecdsa::Signature::from_der(signature).is_ok_and(|sig| key.verify_prehash(digest, &sig).is_ok());
+    }
+    if let Ok(key) = p384::ecdsa::VerifyingKey::from_public_key_der(public_key) {
+        return p384::ecdsa::Signature::from_der(signature).is_ok_and(|sig| key.verify_prehash(digest, &sig).is_ok());
+    }
+    false
+}
+
+/// Whether `package` belongs to one of `scopes`, e.g. `@myorg`.
+pub fn in_scopes(package: &str, scopes: &[String]) -> bool {
+    scopes
+        .iter()
+        .any(|scope| package.strip_prefix(scope.as_str()).is_some_and(|rest| rest.starts_with('/')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::signature::Signer;
+    use sha2::Sha512;
+    use std::str::FromStr;
+    use std::time::Duration;
+    use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+    use x509_cert::der::asn1::UtcTime;
+    use x509_cert::der::oid::AssociatedOid;
+    use x509_cert::der::{Length, Writer};
+    use x509_cert::ext::AsExtension;
+    use x509_cert::name::Name;
+    use x509_cert::serial_number::SerialNumber;
+    use x509_cert::spki::SubjectPublicKeyInfoOwned;
+    use x509_cert::time::{Time, Validity};
+
+    const REPOSITORY: &str = "https://github.com/myorg/widget";
+    const WORKFLOW: &str = ".github/workflows/release.yml";
+    const ISSUED_AT: u64 = 1_700_000_000;
+
+    macro_rules! fulcio_extension {
+        ($name:ident, $oid:expr) => {
+            struct $name(String);
+
+            impl AssociatedOid for $name {
+                const OID: ObjectIdentifier = $oid;
+            }
+
+            impl Encode for $name {
+                fn encoded_len(&self) -> x509_cert::der::Result<Length> {
+                    Utf8StringRef::new(&self.0)?.encoded_len()
+                }
+
+                fn encode(&self, writer: &mut impl Writer) -> x509_cert::der::Result<()> {
+                    Utf8StringRef::new(&self.0)?.encode(writer)
+                }
+            }
+
+            impl AsExtension for $name {
+                fn critical(&self, _: &Name, _: &[x509_cert::ext::Extension]) -> bool {
+                    false
+                }
+            }
+        };
+    }
+
+    fulcio_extension!(SourceRepository, SOURCE_REPOSITORY_URI);
+    fulcio_extension!(BuildSigner, BUILD_SIGNER_URI);
+
+    fn validity(from: u64, seconds: u64) -> Validity {
+        let time = |secs| Time::UtcTime(UtcTime::from_unix_duration(Duration::from_secs(secs)).unwrap());
+        Validity { not_before: time(from), not_after: time(from + seconds) }
+    }
+
+    fn encode(certificate: &Certificate) -> String {
+        STANDARD.encode(certificate.to_der().unwrap())
+    }
+
+    fn pem(certificate: &Certificate) -> String {
+        let encoded = encode(certificate);
+        let lines: Vec<&str> = encoded.as_bytes().chunks(64).map(|line| std::str::from_utf8(line).unwrap()).collect();
+        format!("-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n", lines.join("\n"))
+    }
+
+    /// A Fulcio-like CA, a Rekor-like log, and a build workflow signing
+    /// with a short-lived certificate the CA issued.
+    struct Sigstore {
+        root: Certificate,
+        leaf: Certificate,
+        leaf_key: p256::ecdsa::SigningKey,
+        log_key: p256::ecdsa::SigningKey,
+    }
+
+    impl Sigstore {
+        fn new(seed: u8) -> Self {
+            let root_key = p384::ecdsa::SigningKey::from_slice(&[seed; 48]).unwrap();
+            let root_name = Name::from_str("CN=sigstore,O=sigstore.dev").unwrap();
+            let root = CertificateBuilder::new(
+                Profile::Root,
+                SerialNumber::from(1u32),
+                validity(ISSUED_AT - 1_000_000, 100_000_000),
+                root_name.clone(),
+                SubjectPublicKeyInfoOwned::from_key(*root_key.verifying_key()).unwrap(),
+                &root_key,
+            )
+            .unwrap()
+            .build::<p384::ecdsa::DerSignature>()
+            .unwrap();
+
+            let leaf_key = p256::ecdsa::SigningKey::from_slice(&[seed + 1; 32]).unwrap();
+            let mut builder = CertificateBuilder::new(
+                Profile::Leaf { issuer: root_name, enable_key_agreement: false, enable_key_encipherment: false },
+                SerialNumber::from(2u32),
+                // Fulcio certificates last ten minutes.
+                validity(ISSUED_AT, 600),
+                Name::default(),
+                SubjectPublicKeyInfoOwned::from_key(*leaf_key.verifying_key()).unwrap(),
+                &root_key,
+            )
+            .unwrap();
+            builder.add_extension(&SourceRepository(REPOSITORY.to_string())).unwrap();
+            builder
+                .add_extension(&BuildSigner(format!("{}/{}@refs/tags/v1.0.0", REPOSITORY, WORKFLOW)))
+                .unwrap();
+            let leaf = builder.build::<p384::ecdsa::DerSignature>().unwrap();
+
+            let log_key = p256::ecdsa::SigningKey::from_slice(&[seed + 2; 32]).unwrap();
+            Self { root, leaf, leaf_key, log_key }
+        }
+
+        fn log_key_der(&self) -> Vec<u8> {
+            use p256::pkcs8::EncodePublicKey;
+            self.log_key.verifying_key().to_public_key_der().unwrap().as_bytes().to_vec()
+        }
+
+        fn trust_root(&self) -> TrustRoot {
+            let root = serde_json::json!({
+                "mediaType": "application/vnd.dev.sigstore.trustedroot+json;version=0.1",
+                "tlogs": [{
+                    "baseUrl": "https://rekor.example",
+                    "publicKey": {"rawBytes": STANDARD.encode(self.log_key_der())},
+                    "logId": {"keyId": STANDARD.encode(Sha256::digest(self.log_key_der()))}
+                }],
+                "certificateAuthorities": [{
+                    "uri": "https://fulcio.example",
+                    "certChain": {"certificates": [{"rawBytes": encode(&self.root)}]}
+                }]
+            });
+            TrustRoot::from_json(&root.to_string()).unwrap()
+        }
+
+        /// The registry's attestations for `package`, logged at
+        /// `integrated_time`.
+        fn attest(&self, package: &Package, integrated_time: u64) -> Vec<Attestation> {
+            self.attest_logged(package, integrated_time, |_| {})
+        }
+
+        /// Like `attest`, with `edit` applied to the log entry's body
+        /// before the log signs it.
+        fn attest_logged(
+            &self,
+            package: &Package,
+            integrated_time: u64,
+            edit: impl Fn(&mut serde_json::Value),
+        ) -> Vec<Attestation> {
+            let purl = format!("pkg:npm/{}@{}", package.name.replacen('@', "%40", 1), package.version);
+            let statement = serde_json::json!({
+                "_type": "https://in-toto.io/Statement/v1",
+                "subject": [{"name": purl, "digest": {"sha512": hex::encode(Sha512::digest(TARBALL))}}],
+                "predicateType": "https://slsa.dev/provenance/v1",
+                "predicate": {
+                    "buildDefinition": {
+                        "externalParameters": {
+                            "workflow": {"ref": "refs/tags/v1.0.0", "repository": REPOSITORY, "path": WORKFLOW}
+                        }
+                    }
+                }
+            })
+            .to_string();
+
+            let mut pae = format!("DSSEv1 {} {} {} ", IN_TOTO_PAYLOAD_TYPE.len(), IN_TOTO_PAYLOAD_TYPE, statement.len())
+                .into_bytes();
+            pae.extend_from_slice(statement.as_bytes());
+            let signature: p256::ecdsa::Signature = self.leaf_key.sign(&pae);
+
+            let signature = STANDARD.encode(signature.to_der().as_bytes());
+            let mut body = serde_json::json!({
+                "apiVersion": "0.0.1",
+                "kind": "dsse",
+                "spec": {
+                    "payloadHash": {"algorithm": "sha256", "value": hex::encode(Sha256::digest(&statement))},
+                    "signatures": [{"signature": signature, "verifier": STANDARD.encode(pem(&self.leaf))}]
+                }
+            });
+            edit(&mut body);
+            let body = STANDARD.encode(body.to_string());
+            let log_id = Sha256::digest(self.log_key_der());
+            let signed = serde_json::json!({
+                "body": body,
+                "integratedTime": integrated_time,
+                "logID": hex::encode(log_id),
+                "logIndex": 42,
+            })
+            .to_string();
+            let promise: p256::ecdsa::Signature = self.log_key.sign(signed.as_bytes());
+
+            let attestation = serde_json::json!({
+                "predicateType": "https://slsa.dev/provenance/v1",
+                "bundle": {
+                    "mediaType": "application/vnd.dev.sigstore.bundle.v0.3+json",
+                    "verificationMaterial": {
+                        "certificate": {"rawBytes": encode(&self.leaf)},
+                        "tlogEntries": [{
+                            "logIndex": "42",
+                            "logId": {"keyId": STANDARD.encode(log_id)},
+                            "integratedTime": integrated_time.to_string(),
+                            "inclusionPromise": {"signedEntryTimestamp": STANDARD.encode(promise.to_der().as_bytes())},
+                            "canonicalizedBody": body
+                        }]
+                    },
+                    "dsseEnvelope": {
+                        "payload": STANDARD.encode(&statement),
+                        "payloadType": IN_TOTO_PAYLOAD_TYPE,
+                        "signatures": [{"sig": signature, "keyid": ""}]
+                    }
+                }
+            });
+            let list: AttestationList =
+                serde_json::from_value(serde_json::json!({"attestations": [attestation]})).unwrap();
+            list.attestations
+        }
+    }
+
+    const TARBALL: &[u8] = b"widget-1.0.0.tgz";
+
+    fn package(repository: &str) -> Package {
+        serde_json::from_value(serde_json::json!({
+            "name": "@myorg/widget",
+            "version": "1.0.0",
+            "repository": {"type": "git", "url": repository},
+            "dist": {
+                "tarball": "https://registry.example/@myorg/widget/-/widget-1.0.0.tgz",
+                "shasum": "0000",
+                "integrity": format!("sha512-{}", STANDARD.encode(Sha512::digest(TARBALL)))
+            }
+        }))
+        .unwrap()
+    }
+
+    fn reason(provenance: Provenance) -> String {
+        match provenance {
+            Provenance::Invalid { reason } => reason,
+            other => panic!("expected invalid provenance, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_provenance() {
+        let sigstore = Sigstore::new(1);
+        let trust_root = sigstore.trust_root();
+        let widget = package("git+https://github.com/myorg/widget.git");
+        let attestations = sigstore.attest(&widget, ISSUED_AT + 60);
+
+        assert_eq!(
+            verify_provenance(&trust_root, &widget, &attestations),
+            Provenance::Verified { repository: REPOSITORY.to_string(), workflow: WORKFLOW.to_string() }
+        );
+
+        // Published from a fork: the package claims another repository.
+        let claimed = package("git+https://github.com/other/widget.git");
+        let attestations = sigstore.attest(&claimed, ISSUED_AT + 60);
+        assert!(reason(verify_provenance(&trust_root, &claimed, &attestations)).starts_with("built from"));
+
+        // Another tarball than the one attested.
+        let mut tampered = widget.clone();
+        tampered.dist.integrity = Some(format!("sha512-{}", STANDARD.encode(Sha512::digest(b"evil"))));
+        let attestations = sigstore.attest(&widget, ISSUED_AT + 60);
+        assert!(reason(verify_provenance(&trust_root, &tampered, &attestations)).contains("another artifact"));
+
+        // Logged after the certificate expired.
+        let attestations = sigstore.attest(&widget, ISSUED_AT + 3600);
+        assert_eq!(
+            reason(verify_provenance(&trust_root, &widget, &attestations)),
+            "a certificate wasn't valid when the attestation was logged"
+        );
+
+        // Signed by a CA and a log rpm doesn't trust.
+        let untrusted = Sigstore::new(7);
+        let attestations = untrusted.attest(&widget, ISSUED_AT + 60);
+        assert!(reason(verify_provenance(&trust_root, &widget, &attestations)).starts_with("logged in an unknown"));
+        let mut trust_root = trust_root;
+        trust_root.tlogs = untrusted.trust_root().tlogs;
+        assert_eq!(
+            reason(verify_provenance(&trust_root, &widget, &attestations)),
+            "the signing certificate wasn't issued by a trusted certificate authority"
+        );
+
+        assert!(reason(verify_provenance(&trust_root, &widget, &[])).contains("no SLSA provenance"));
+    }
+
+    #[test]
+    fn test_tlog_entry_must_record_the_signing() {
+        let sigstore = Sigstore::new(1);
+        let trust_root = sigstore.trust_root();
+        let widget = package("git+https://github.com/myorg/widget.git");
+        let mismatch = "the transparency log entry records another signature or certificate";
+
+        // The log vouches for a signature by another certificate over the
+        // same payload.
+        let other = Sigstore::new(7);
+        let attestations = sigstore.attest_logged(&widget, ISSUED_AT + 60, |body| {
+            body["spec"]["signatures"][0]["verifier"] = STANDARD.encode(pem(&other.leaf)).into();
+        });
+        assert_eq!(reason(verify_provenance(&trust_root, &widget, &attestations)), mismatch);
+
+        let attestations = sigstore.attest_logged(&widget, ISSUED_AT + 60, |body| {
+            let forged: p256::ecdsa::Signature = other.leaf_key.sign(b"something else");
+            body["spec"]["signatures"][0]["signature"] = STANDARD.encode(forged.to_der().as_bytes()).into();
+        });
+        assert_eq!(reason(verify_provenance(&trust_root, &widget, &attestations)), mismatch);
+
+        // `intoto` entries keep the envelope, signature encoded twice.
+        let attestations = sigstore.attest_logged(&widget, ISSUED_AT + 60, |body| {
+            let logged = body["spec"]["signatures"][0].clone();
+            let signature = STANDARD.encode(logged["signature"].as_str().unwrap());
+            body["kind"] = "intoto".into();
+            body["spec"] = serde_json::json!({
+                "content": {
+                    "payloadHash": body["spec"]["payloadHash"].clone(),
+                    "envelope": {"signatures": [{"sig": signature, "publicKey": logged["verifier"].clone()}]}
+                }
+            });
+        });
+        assert!(verify_provenance(&trust_root, &widget, &attestations).is_verified());
+    }
+
+    #[tokio::test]
+    async fn test_check_provenance_fetches_repository() {
+        use crate::registry::mock::MockRegistry;
+        use std::collections::HashMap;
+
+        let sigstore = Sigstore::new(1);
+        let widget = package("git+https://github.com/myorg/widget.git");
+        let attestations = serde_json::json!({"attestations": sigstore.attest(&widget, ISSUED_AT + 60)});
+        let path = "/-/npm/v1/attestations/@myorg%2fwidget@1.0.0";
+        let documents = HashMap::from([
+            (path.to_string(), attestations.to_string()),
+            ("/@myorg%2fwidget/1.0.0".to_string(), serde_json::to_string(&widget).unwrap()),
+        ]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let client = RegistryClient::with_url(registry.url.clone());
+
+        // As abbreviated metadata has it: no `repository`.
+        let mut abbreviated = widget.clone();
+        abbreviated.repository = None;
+        abbreviated.dist.attestations = Some(crate::package::DistAttestations { url: path.to_string() });
+
+        let provenance = check_provenance(&client, &sigstore.trust_root(), &abbreviated).await.unwrap();
+        assert!(provenance.is_verified());
+        assert_eq!(registry.hits("/@myorg%2fwidget/1.0.0"), 1);
+    }
+
+    #[test]
+    fn test_in_scopes() {
+        let scopes = vec!["@myorg".to_string()];
+        assert!(in_scopes("@myorg/widget", &scopes));
+        assert!(!in_scopes("@myorganization/widget", &scopes));
+        assert!(!in_scopes("widget", &scopes));
+    }
+
+    #[test]
+    fn test_bundled_trust_root() {
+        let trust_root = TrustRoot::bundled().unwrap();
+        assert!(!trust_root.tlogs.is_empty());
+        assert!(!trust_root.certificate_authorities.is_empty());
+
+        // Each log is identified by the hash of its key.
+        for log in &trust_root.tlogs {
+            let key = STANDARD.decode(&log.public_key.raw_bytes).unwrap();
+            assert_eq!(STANDARD.encode(Sha256::digest(&key)), log.log_id.key_id);
+        }
+        // Each chain ends at a self-signed root that issued the rest.
+        for authority in &trust_root.certificate_authorities {
+            let chain: Vec<Certificate> = authority
+                .cert_chain
+                .certificates
+                .iter()
+                .map(|raw| Certificate::from_der(&STANDARD.decode(&raw.raw_bytes).unwrap()).unwrap())
+                .collect();
+            let root = chain.last().unwrap();
+            assert!(is_signed_by(root, root));
+            assert!(chain.windows(2).all(|pair| is_signed_by(&pair[0], &pair[1])));
+        }
+    }
+}
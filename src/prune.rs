@@ -0,0 +1,205 @@
+use crate::lockfile::{LockFile, LOCKFILE_NAME};
+use crate::package::PackageJson;
+use crate::tree::{importers, location, EdgeKind, InstalledTree, NodePath};
+use crate::workspace::Workspace;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A package deleted from `node_modules`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Pruned {
+    pub location: String,
+    pub version: String,
+}
+
+/// Deletes every package in `root/node_modules` that isn't reachable from
+/// the manifests, leaving out edges of the `omit` kinds, and unlinks the bins
+/// that pointed into them. Extraneous packages are dropped from the lockfile
+/// too; omitted ones stay there so a full install doesn't need to resolve
+/// them again.
+pub async fn prune(
+    root: &Path,
+    manifest: &PackageJson,
+    workspace: Option<&Workspace>,
+    omit: &[EdgeKind],
+) -> Result<Vec<Pruned>> {
+    let installed = LockFile::from_node_modules(manifest, root).await?;
+    let tree = InstalledTree::new(&installed, importers(manifest, workspace));
+    let unreachable = tree.unreachable(|kind| !omit.contains(&kind));
+    let extraneous: HashSet<NodePath> = tree.extraneous().into_iter().collect();
+
+    // Anything nested under a pruned package goes with it.
+    let outermost: Vec<&NodePath> = unreachable
+        .iter()
+        .filter(|path| !unreachable.iter().any(|other| other != *path && path.starts_with(other)))
+        .collect();
+    let pruned: Vec<Pruned> = outermost
+        .iter()
+        .filter_map(|path| {
+            Some(Pruned {
+                location: location(path),
+                version: installed.entry(path)?.version().to_string(),
+            })
+        })
+        .collect();
+
+    let directories: Vec<PathBuf> = pruned.iter().map(|package| root.join(&package.location)).collect();
+    tokio::task::spawn_blocking(move || remove_packages(&directories)).await??;
+
+    let lockfile_path = root.join(LOCKFILE_NAME);
+    if lockfile_path.exists() {
+        let mut lockfile = LockFile::load(&lockfile_path).await?;
+        let mut changed = false;
+        for path in outermost.iter().filter(|path| extraneous.contains(**path)) {
+            changed |= lockfile.remove(path).is_some();
+        }
+        if changed {
+            lockfile.save(&lockfile_path).await?;
+        }
+    }
+
+    Ok(pruned)
+}
+
+fn remove_packages(directories: &[PathBuf]) -> std::io::Result<()> {
+    for dir in directories {
+        // Read before the package goes, so only its own bin links are touched.
+        let bin_links: Vec<PathBuf> = match bin_dir(dir) {
+            Some(bin_dir) => declared_bins(dir).iter().map(|name| bin_dir.join(name)).collect(),
+            None => Vec::new(),
+        };
+
+        // `link:` dependencies are symlinks; only the link itself goes.
+        if std::fs::symlink_metadata(dir)?.file_type().is_symlink() {
+            std::fs::remove_file(dir)?;
+        } else {
+            std::fs::remove_dir_all(dir)?;
+        }
+
+        let scope = dir
+            .parent()
+            .filter(|parent| parent.file_name().is_some_and(|name| name.to_string_lossy().starts_with('@')));
+        if let Some(parent) = scope {
+            if std::fs::read_dir(parent)?.next().is_none() {
+                std::fs::remove_dir(parent)?;
+            }
+        }
+
+        // A link another package has since taken over still resolves.
+        for link in bin_links {
+            let Ok(metadata) = std::fs::symlink_metadata(&link) else {
+                continue;
+            };
+            if metadata.file_type().is_symlink() && std::fs::metadata(&link).is_err() {
+                std::fs::remove_file(&link)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The `.bin` directory a package at `dir` links its bins into:
+/// `node_modules/@scope/name` and `node_modules/name` both use
+/// `node_modules/.bin`.
+fn bin_dir(dir: &Path) -> Option<PathBuf> {
+    let parent = dir.parent()?;
+    let node_modules = if parent.file_name()?.to_string_lossy().starts_with('@') {
+        parent.parent()?
+    } else {
+        parent
+    };
+    Some(node_modules.join(".bin"))
+}
+
+/// The bin names the manifest in `dir` declares. A plain string `bin` is
+/// named after the package, without its scope.
+fn declared_bins(dir: &Path) -> Vec<String> {
+    let Ok(content) = std::fs::read_to_string(dir.join("package.json")) else {
+        return Vec::new();
+    };
+    let Ok(manifest) = serde_json::from_str::<serde_json::Value>(&content) else {
+        return Vec::new();
+    };
+    match &manifest["bin"] {
+        serde_json::Value::String(_) => manifest["name"]
+            .as_str()
+            .map(|name| vec![name.rsplit('/').next().unwrap_or(name).to_string()])
+            .unwrap_or_default(),
+        serde_json::Value::Object(bins) => bins.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn install(dir: &Path, name: &str, version: &str, dependencies: serde_json::Value) {
+        std::fs::create_dir_all(dir).unwrap();
+        let manifest = serde_json::json!({"name": name, "version": version, "dependencies": dependencies});
+        std::fs::write(dir.join("package.json"), manifest.to_string()).unwrap();
+    }
+
+    fn declare_bin(dir: &Path, bin: serde_json::Value) {
+        let path = dir.join("package.json");
+        let mut manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        manifest["bin"] = bin;
+        std::fs::write(path, manifest.to_string()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_prune() {
+        let root = tempdir().unwrap();
+        let node_modules = root.path().join("node_modules");
+        install(&node_modules.join("express"), "express", "4.18.2", serde_json::json!({"debug": "2.6.9"}));
+        install(&node_modules.join("debug"), "debug", "2.6.9", serde_json::json!({}));
+        install(&node_modules.join("jest"), "jest", "29.7.0", serde_json::json!({}));
+        // Left behind by a removed dependency, with a nested copy of its own.
+        install(&node_modules.join("left-pad"), "left-pad", "1.3.0", serde_json::json!({"debug": "^4.0.0"}));
+        install(
+            &node_modules.join("left-pad/node_modules/debug"),
+            "debug",
+            "4.3.4",
+            serde_json::json!({}),
+        );
+
+        declare_bin(&node_modules.join("left-pad"), serde_json::json!("cli.js"));
+        declare_bin(&node_modules.join("jest"), serde_json::json!({"jest": "cli.js"}));
+
+        std::fs::create_dir_all(node_modules.join(".bin")).unwrap();
+        std::fs::write(node_modules.join("left-pad/cli.js"), "").unwrap();
+        std::fs::write(node_modules.join("jest/cli.js"), "").unwrap();
+        std::os::unix::fs::symlink("../left-pad/cli.js", node_modules.join(".bin/left-pad")).unwrap();
+        std::os::unix::fs::symlink("../jest/cli.js", node_modules.join(".bin/jest")).unwrap();
+        // Dangling, but not declared by anything pruned here.
+        std::os::unix::fs::symlink("../gone/cli.js", node_modules.join(".bin/gone")).unwrap();
+
+        let manifest: PackageJson = serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": {"express": "^4.18.0"},
+            "devDependencies": {"jest": "^29.0.0"}
+        }))
+        .unwrap();
+
+        let pruned = prune(root.path(), &manifest, None, &[]).await.unwrap();
+        let locations: Vec<&str> = pruned.iter().map(|package| package.location.as_str()).collect();
+        assert_eq!(locations, vec!["node_modules/left-pad"]);
+        assert!(!node_modules.join("left-pad").exists());
+        assert!(std::fs::symlink_metadata(node_modules.join(".bin/left-pad")).is_err());
+        assert!(node_modules.join(".bin/jest").exists());
+
+        let pruned = prune(root.path(), &manifest, None, &[EdgeKind::Dev]).await.unwrap();
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].location, "node_modules/jest");
+        assert!(node_modules.join("express").exists());
+        assert!(node_modules.join("debug").exists());
+        assert!(std::fs::symlink_metadata(node_modules.join(".bin/jest")).is_err());
+        assert!(std::fs::symlink_metadata(node_modules.join(".bin/gone")).is_ok());
+    }
+}
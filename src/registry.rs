@@ -1,18 +1,53 @@
 use crate::error::RegistryError;
-use crate::package::Package;
+use crate::package::{Package, Packument};
+use crate::provenance::{Attestation, AttestationList};
+use crate::verification::{RegistryKeys, KEYS_PATH};
+use futures::future::{BoxFuture, FutureExt, Shared};
 use reqwest::Client;
+use std::collections::HashMap;
 use std::time::Duration;
+use tokio::sync::Semaphore;
 use url::Url;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+
+/// The `time` field of a full packument: publish times by version.
+#[derive(serde::Deserialize)]
+struct PublishTimes {
+    #[serde(default)]
+    time: HashMap<String, String>,
+}
+
+/// Upper bound on metadata requests in flight at once.
+const DEFAULT_METADATA_CONCURRENCY: usize = 16;
+
+/// Abbreviated metadata: only the fields needed to resolve and install.
+const ABBREVIATED_METADATA: &str = "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8";
+
+type PackumentFuture = Shared<BoxFuture<'static, Result<Arc<Packument>, Arc<RegistryError>>>>;
 
 pub struct RegistryClient {
     client: Arc<Client>,
     registry_url: Url,
     timeout: Duration,
+    metadata_limit: Arc<Semaphore>,
+    packuments: Mutex<HashMap<String, PackumentFuture>>,
+}
+
+impl Default for RegistryClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RegistryClient {
     pub fn new() -> Self {
+        let registry_url = Url::parse("https://registry.npmjs.org")
+            .expect("Invalid registry URL");
+
+        Self::with_url(registry_url)
+    }
+
+    pub fn with_url(registry_url: Url) -> Self {
         let client = Client::builder()
             .timeout(Duration::from_secs(30))
             .pool_idle_timeout(Duration::from_secs(90))
@@ -20,24 +55,33 @@ impl RegistryClient {
             .build()
             .expect("Failed to create HTTP client");
 
-        let registry_url = Url::parse("https://registry.npmjs.org")
-            .expect("Invalid registry URL");
-
         Self {
             client: Arc::new(client),
             registry_url,
             timeout: Duration::from_secs(30),
+            metadata_limit: Arc::new(Semaphore::new(DEFAULT_METADATA_CONCURRENCY)),
+            packuments: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Limits how many metadata requests may be in flight at once.
+    pub fn with_max_concurrent_requests(mut self, limit: usize) -> Self {
+        self.metadata_limit = Arc::new(Semaphore::new(limit.max(1)));
+        self
+    }
+
+    pub fn registry_url(&self) -> &Url {
+        &self.registry_url
+    }
+
     pub async fn fetch_package_info(
         &self,
         name: &str,
         version: Option<&str>,
     ) -> Result<Package, RegistryError> {
         let url = match version {
-            Some(v) => self.registry_url.join(&format!("/{}/{}/-/{}-{}.tgz", name, v, name, v))?,
-            None => self.registry_url.join(&format!("/{}/latest", name))?,
+            Some(v) => self.registry_url.join(&format!("/{}/{}", encode_name(name), v))?,
+            None => self.registry_url.join(&format!("/{}/latest", encode_name(name)))?,
         };
 
         let response = self.client
@@ -45,7 +89,7 @@ impl RegistryClient {
             .timeout(self.timeout)
             .send()
             .await
-            .map_err(|e| RegistryError::NetworkError(e))?;
+            .map_err(RegistryError::NetworkError)?;
 
         if !response.status().is_success() {
             return Err(RegistryError::PackageNotFound(name.to_string()));
@@ -56,4 +100,306 @@ impl RegistryClient {
         
         Ok(package_data)
     }
+
+    /// The keys the registry signs packages with, or `None` when it doesn't
+    /// publish any.
+    pub async fn fetch_keys(&self) -> Result<Option<RegistryKeys>, RegistryError> {
+        let url = self.registry_url.join(KEYS_PATH)?;
+        let response = self.client
+            .get(url)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(RegistryError::NetworkError)?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let keys = response
+            .error_for_status()
+            .map_err(RegistryError::NetworkError)?
+            .json()
+            .await
+            .map_err(|e| RegistryError::DeserializationError(e.to_string()))?;
+        Ok(Some(keys))
+    }
+
+    /// The attestations served at `url`, a version's `dist.attestations.url`.
+    pub async fn fetch_attestations(&self, url: &str) -> Result<Vec<Attestation>, RegistryError> {
+        let url = self.registry_url.join(url)?;
+        let _permit = self.metadata_limit.acquire().await.expect("metadata semaphore is never closed");
+        let list: AttestationList = self.client
+            .get(url)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(RegistryError::NetworkError)?
+            .json()
+            .await
+            .map_err(|e| RegistryError::DeserializationError(e.to_string()))?;
+        Ok(list.attestations)
+    }
+
+    /// When `version` of `name` was published, e.g. `2021-03-01T12:00:00.000Z`.
+    /// Only the full packument has the `time` field, so it's fetched here
+    /// without going through the abbreviated metadata cache.
+    pub async fn fetch_publish_time(
+        &self,
+        name: &str,
+        version: &semver::Version,
+    ) -> Result<Option<String>, RegistryError> {
+        let url = self.registry_url.join(&format!("/{}", encode_name(name)))?;
+        let _permit = self.metadata_limit.acquire().await.expect("metadata semaphore is never closed");
+        let mut times: PublishTimes = self.client
+            .get(url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .timeout(self.timeout)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(RegistryError::NetworkError)?
+            .json()
+            .await
+            .map_err(|e| RegistryError::DeserializationError(e.to_string()))?;
+        Ok(times.time.remove(&version.to_string()))
+    }
+
+    /// Fetches the metadata of every version of `name`. Documents are kept for
+    /// the lifetime of the client, and concurrent calls for the same name
+    /// share a single request. A failed request is forgotten, so the next
+    /// call asks again.
+    pub async fn fetch_packument(&self, name: &str) -> Result<Arc<Packument>, RegistryError> {
+        let request = {
+            let mut packuments = self.packuments.lock().expect("packument cache poisoned");
+            packuments
+                .entry(name.to_string())
+                .or_insert_with(|| self.request_packument(name))
+                .clone()
+        };
+
+        let result = request.clone().await;
+        if result.is_err() {
+            let mut packuments = self.packuments.lock().expect("packument cache poisoned");
+            // Unless a later call already replaced it with a new request.
+            if packuments.get(name).is_some_and(|cached| cached.ptr_eq(&request)) {
+                packuments.remove(name);
+            }
+        }
+        Ok(result?)
+    }
+
+    fn request_packument(&self, name: &str) -> PackumentFuture {
+        let client = Arc::clone(&self.client);
+        let limit = Arc::clone(&self.metadata_limit);
+        let url = self.registry_url.join(&format!("/{}", encode_name(name)));
+        let timeout = self.timeout;
+        let name = name.to_string();
+
+        async move {
+            let url = url.map_err(|e| Arc::new(RegistryError::UrlError(e)))?;
+            let _permit = limit.acquire_owned().await.expect("metadata semaphore is never closed");
+            log::debug!("GET {}", url);
+
+            let response = client
+                .get(url)
+                .header(reqwest::header::ACCEPT, ABBREVIATED_METADATA)
+                .timeout(timeout)
+                .send()
+                .await
+                .map_err(|e| Arc::new(RegistryError::NetworkError(e)))?;
+
+            if !response.status().is_success() {
+                return Err(Arc::new(RegistryError::PackageNotFound(name)));
+            }
+
+            let packument: Packument = response
+                .json()
+                .await
+                .map_err(|e| Arc::new(RegistryError::DeserializationError(e.to_string())))?;
+
+            Ok(Arc::new(packument))
+        }
+        .boxed()
+        .shared()
+    }
+}
+
+/// Scoped names keep their `@` but the slash must be escaped in the path.
+fn encode_name(name: &str) -> String {
+    name.replace('/', "%2f")
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use url::Url;
+
+    /// A minimal HTTP registry serving fixed JSON documents by path,
+    /// counting how often each path is requested and keeping the last
+    /// request body sent to it.
+    pub(crate) struct MockRegistry {
+        pub url: Url,
+        hits: Arc<Mutex<HashMap<String, usize>>>,
+        bodies: Arc<Mutex<HashMap<String, String>>>,
+    }
+
+    impl MockRegistry {
+        pub async fn start(documents: HashMap<String, String>, delay: Duration) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+            let hits = Arc::new(Mutex::new(HashMap::new()));
+            let bodies = Arc::new(Mutex::new(HashMap::new()));
+            let documents = Arc::new(documents);
+
+            let counter = Arc::clone(&hits);
+            let received = Arc::clone(&bodies);
+            tokio::spawn(async move {
+                loop {
+                    let Ok((mut socket, _)) = listener.accept().await else {
+                        break;
+                    };
+                    let documents = Arc::clone(&documents);
+                    let counter = Arc::clone(&counter);
+                    let received = Arc::clone(&received);
+
+                    tokio::spawn(async move {
+                        let mut request = Vec::new();
+                        let mut buffer = [0u8; 1024];
+                        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                            match socket.read(&mut buffer).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(n) => request.extend_from_slice(&buffer[..n]),
+                            }
+                        }
+
+                        let header_end = request.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+                        let headers = String::from_utf8_lossy(&request[..header_end]).to_string();
+                        let content_length = headers
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse().ok())?
+                            })
+                            .unwrap_or(0);
+                        while request.len() < header_end + content_length {
+                            match socket.read(&mut buffer).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(n) => request.extend_from_slice(&buffer[..n]),
+                            }
+                        }
+
+                        let path = headers.split_whitespace().nth(1).unwrap_or("/").to_string();
+                        *counter.lock().unwrap().entry(path.clone()).or_default() += 1;
+                        if content_length > 0 {
+                            let body = String::from_utf8_lossy(&request[header_end..]).to_string();
+                            received.lock().unwrap().insert(path.clone(), body);
+                        }
+                        tokio::time::sleep(delay).await;
+
+                        let response = match documents.get(&path) {
+                            Some(body) => format!(
+                                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                body.len(),
+                                body
+                            ),
+                            None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                        };
+                        let _ = socket.write_all(response.as_bytes()).await;
+                    });
+                }
+            });
+
+            Self { url, hits, bodies }
+        }
+
+        pub fn hits(&self, path: &str) -> usize {
+            self.hits.lock().unwrap().get(path).copied().unwrap_or(0)
+        }
+
+        pub fn body(&self, path: &str) -> Option<String> {
+            self.bodies.lock().unwrap().get(path).cloned()
+        }
+    }
+
+    /// Builds a packument document with the given versions and dependencies.
+    pub fn packument(name: &str, versions: &[(&str, &[(&str, &str)])]) -> String {
+        let versions: serde_json::Map<String, serde_json::Value> = versions
+            .iter()
+            .map(|(version, deps)| {
+                let deps: serde_json::Map<String, serde_json::Value> = deps
+                    .iter()
+                    .map(|(dep, range)| (dep.to_string(), serde_json::json!(range)))
+                    .collect();
+                let metadata = serde_json::json!({
+                    "name": name,
+                    "version": version,
+                    "dependencies": deps,
+                    "dist": {
+                        "tarball": format!("https://registry.example/{}/-/{}.tgz", name, version),
+                        "shasum": "0000",
+                    },
+                });
+                (version.to_string(), metadata)
+            })
+            .collect();
+        let latest = versions
+            .keys()
+            .max_by_key(|version| semver::Version::parse(version).ok())
+            .cloned()
+            .unwrap_or_default();
+
+        serde_json::json!({
+            "name": name,
+            "dist-tags": { "latest": latest },
+            "versions": versions,
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{packument, MockRegistry};
+    use super::*;
+    use futures::future::join_all;
+
+    #[tokio::test]
+    async fn test_concurrent_packument_requests_are_coalesced() {
+        let documents = HashMap::from([(
+            "/left-pad".to_string(),
+            packument("left-pad", &[("1.0.0", &[]), ("1.3.0", &[])]),
+        )]);
+        let registry = MockRegistry::start(documents, Duration::from_millis(50)).await;
+        let client = RegistryClient::with_url(registry.url.clone());
+
+        let results = join_all((0..10).map(|_| client.fetch_packument("left-pad"))).await;
+
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(registry.hits("/left-pad"), 1);
+        assert_eq!(results[0].as_ref().unwrap().sorted_versions().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_names_and_missing_packages() {
+        let documents = HashMap::from([(
+            "/@scope%2fpkg".to_string(),
+            packument("@scope/pkg", &[("2.0.0", &[])]),
+        )]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let client = RegistryClient::with_url(registry.url.clone());
+
+        let packument = client.fetch_packument("@scope/pkg").await.unwrap();
+        assert_eq!(packument.latest().unwrap().version.to_string(), "2.0.0");
+
+        let missing = client.fetch_packument("missing").await;
+        assert!(missing.is_err());
+        // Failures aren't cached: the registry is asked again.
+        assert!(client.fetch_packument("missing").await.is_err());
+        assert_eq!(registry.hits("/missing"), 2);
+    }
 }
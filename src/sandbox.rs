@@ -1,5 +1,5 @@
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use anyhow::Result;
 
 pub struct Sandbox {
@@ -33,4 +33,29 @@ impl Sandbox {
 
         Ok(())
     }
-} 
\ No newline at end of file
+
+    /// Starts a `package.json` script with piped output so the caller can
+    /// prefix each line. `bin_dirs` (usually `node_modules/.bin` of the package
+    /// and of the workspace root) are searched before the restricted `PATH`.
+    pub fn spawn_script(&self, script: &str, bin_dirs: &[PathBuf]) -> Result<tokio::process::Child> {
+        let mut path = bin_dirs
+            .iter()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        path.push("/usr/local/bin:/usr/bin:/bin".to_string());
+
+        let child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .current_dir(&self.working_dir)
+            .env("PATH", path.join(":"))
+            .env_remove("USER")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        Ok(child)
+    }
+}
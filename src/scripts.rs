@@ -0,0 +1,262 @@
+use crate::error::WorkspaceError;
+use crate::package::PackageJson;
+use crate::sandbox::Sandbox;
+use crate::workspace::{Workspace, WorkspaceMember};
+use anyhow::Result;
+use log::{debug, info};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::task::JoinSet;
+
+pub struct ScriptRunner {
+    concurrency: usize,
+}
+
+impl ScriptRunner {
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    /// Runs `script` from a single manifest, streaming its output unchanged.
+    pub async fn run(&self, dir: &Path, manifest: &PackageJson, script: &str) -> Result<()> {
+        let command = manifest
+            .scripts
+            .as_ref()
+            .and_then(|scripts| scripts.get(script))
+            .ok_or_else(|| anyhow::anyhow!("Missing script \"{}\" in {}", script, manifest.name))?;
+
+        info!("{}: {}", manifest.name, command);
+        run_script(None, dir, command, vec![dir.join("node_modules/.bin")]).await
+    }
+
+    /// Runs `script` in every selected member that defines it. A member starts
+    /// once all of its selected workspace dependencies have finished, and at
+    /// most `concurrency` scripts run at a time. After the first failure no
+    /// new scripts are started.
+    pub async fn run_recursive(
+        &self,
+        workspace: &Workspace,
+        selected: &[&WorkspaceMember],
+        script: &str,
+    ) -> Result<()> {
+        let graph = workspace.dependency_graph();
+        let members: HashMap<&str, &WorkspaceMember> =
+            selected.iter().map(|m| (m.name.as_str(), *m)).collect();
+
+        // Only edges inside the selection constrain the order.
+        let mut pending: HashMap<&str, usize> = HashMap::new();
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for member in selected {
+            let deps: Vec<&str> = graph
+                .get(&member.name)
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .filter(|dep| members.contains_key(dep))
+                .collect();
+            pending.insert(member.name.as_str(), deps.len());
+            for dep in deps {
+                dependents.entry(dep).or_default().push(member.name.as_str());
+            }
+        }
+
+        let mut ready: VecDeque<&str> = selected
+            .iter()
+            .map(|m| m.name.as_str())
+            .filter(|name| pending[name] == 0)
+            .collect();
+        let mut running = JoinSet::new();
+        let mut finished = 0;
+        let mut failure = None;
+
+        loop {
+            while failure.is_none() && running.len() < self.concurrency {
+                let Some(name) = ready.pop_front() else {
+                    break;
+                };
+                let member = members[name];
+
+                match member.manifest.scripts.as_ref().and_then(|s| s.get(script)) {
+                    Some(command) => {
+                        info!("{}: {}", name, command);
+                        let prefix = name.to_string();
+                        let dir = member.path.clone();
+                        let command = command.clone();
+                        let bin_dirs = vec![
+                            member.path.join("node_modules/.bin"),
+                            workspace.root.join("node_modules/.bin"),
+                        ];
+
+                        running.spawn(async move {
+                            let result = run_script(Some(&prefix), &dir, &command, bin_dirs).await;
+                            (prefix, result)
+                        });
+                    }
+                    None => {
+                        debug!("{} has no \"{}\" script, skipping", name, script);
+                        finished += 1;
+                        release(name, &mut pending, &dependents, &mut ready);
+                    }
+                }
+            }
+
+            let Some(joined) = running.join_next().await else {
+                break;
+            };
+            let (name, result) = joined?;
+
+            match result {
+                Ok(()) => {
+                    finished += 1;
+                    if let Some((name, _)) = members.get_key_value(name.as_str()) {
+                        release(name, &mut pending, &dependents, &mut ready);
+                    }
+                }
+                Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+
+        if let Some(e) = failure {
+            return Err(e);
+        }
+
+        if finished < selected.len() {
+            let mut stuck: Vec<&str> = pending
+                .iter()
+                .filter(|(_, count)| **count > 0)
+                .map(|(name, _)| *name)
+                .collect();
+            stuck.sort();
+            return Err(WorkspaceError::DependencyCycle(stuck.join(", ")).into());
+        }
+
+        Ok(())
+    }
+}
+
+fn release<'a>(
+    name: &str,
+    pending: &mut HashMap<&'a str, usize>,
+    dependents: &HashMap<&'a str, Vec<&'a str>>,
+    ready: &mut VecDeque<&'a str>,
+) {
+    for dependent in dependents.get(name).into_iter().flatten() {
+        if let Some(count) = pending.get_mut(dependent) {
+            *count -= 1;
+            if *count == 0 {
+                ready.push_back(dependent);
+            }
+        }
+    }
+}
+
+async fn run_script(prefix: Option<&str>, dir: &Path, command: &str, bin_dirs: Vec<PathBuf>) -> Result<()> {
+    let mut child = Sandbox::new(dir).spawn_script(command, &bin_dirs)?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let (status, _, _) = tokio::join!(
+        child.wait(),
+        forward_lines(stdout, prefix, false),
+        forward_lines(stderr, prefix, true),
+    );
+
+    let status = status?;
+    if !status.success() {
+        anyhow::bail!(
+            "Script \"{}\" failed in {} ({})",
+            command,
+            prefix.unwrap_or("project"),
+            status
+        );
+    }
+
+    Ok(())
+}
+
+async fn forward_lines(reader: impl AsyncRead + Unpin, prefix: Option<&str>, stderr: bool) {
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let line = match prefix {
+            Some(prefix) => format!("{}: {}", prefix, line),
+            None => line,
+        };
+        if stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_manifest;
+    use tempfile::tempdir;
+    use tokio::fs;
+
+    #[tokio::test]
+    async fn test_run_recursive_in_dependency_order() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let log = root.join("order.log");
+
+        write_manifest(root, r#"{"name": "root", "version": "0.0.0", "workspaces": ["packages/*"]}"#).await;
+        for (dir, name, deps) in [
+            ("a", "a", r#"{"b": "workspace:*", "c": "workspace:*"}"#),
+            ("b", "b", r#"{"c": "workspace:*"}"#),
+            ("c", "c", "{}"),
+        ] {
+            let manifest = format!(
+                r#"{{"name": "{}", "version": "1.0.0", "dependencies": {}, "scripts": {{"build": "echo {} >> {}"}}}}"#,
+                name,
+                deps,
+                name,
+                log.display()
+            );
+            write_manifest(&root.join("packages").join(dir), &manifest).await;
+        }
+
+        let workspace = Workspace::discover(root).await.unwrap().unwrap();
+        let selected: Vec<_> = workspace.members.iter().collect();
+        ScriptRunner::new(4)
+            .run_recursive(&workspace, &selected, "build")
+            .await
+            .unwrap();
+
+        let order = fs::read_to_string(&log).await.unwrap();
+        assert_eq!(order.lines().collect::<Vec<_>>(), vec!["c", "b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_recursive_stops_on_failure() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+
+        write_manifest(root, r#"{"name": "root", "version": "0.0.0", "workspaces": ["packages/*"]}"#).await;
+        write_manifest(
+            &root.join("packages/lib"),
+            r#"{"name": "lib", "version": "1.0.0", "scripts": {"test": "exit 1"}}"#,
+        )
+        .await;
+        write_manifest(
+            &root.join("packages/app"),
+            r#"{"name": "app", "version": "1.0.0", "dependencies": {"lib": "workspace:*"}, "scripts": {"test": "touch ran"}}"#,
+        )
+        .await;
+
+        let workspace = Workspace::discover(root).await.unwrap().unwrap();
+        let selected: Vec<_> = workspace.members.iter().collect();
+        let result = ScriptRunner::new(2).run_recursive(&workspace, &selected, "test").await;
+
+        assert!(result.is_err());
+        assert!(!root.join("packages/app/ran").exists());
+    }
+}
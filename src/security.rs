@@ -1,75 +1,365 @@
 use crate::error::SecurityError;
+use crate::version::VersionRange;
 use reqwest::Client;
-use semver::Version;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use anyhow::Result;
+use url::Url;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Vulnerability {
+/// How bad an advisory is, ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Missing or not one of the levels below. Ranks lowest for sorting,
+    /// but an audit fails on it whatever the threshold.
+    Unknown,
+    Info,
+    Low,
+    Moderate,
+    High,
+    Critical,
+}
+
+impl Severity {
+    /// Most to least severe, the order summaries list them in.
+    pub const DESCENDING: [Severity; 6] = [
+        Severity::Critical,
+        Severity::High,
+        Severity::Moderate,
+        Severity::Low,
+        Severity::Info,
+        Severity::Unknown,
+    ];
+
+    /// Case-insensitive, as OSV records spell GitHub's levels in upper case.
+    pub fn parse(text: &str) -> Self {
+        match text.to_ascii_lowercase().as_str() {
+            "info" => Severity::Info,
+            "low" => Severity::Low,
+            "moderate" | "medium" => Severity::Moderate,
+            "high" => Severity::High,
+            "critical" => Severity::Critical,
+            _ => Severity::Unknown,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Unknown => "unknown",
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Moderate => "moderate",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Severity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Severity::parse(&String::deserialize(deserializer)?))
+    }
+}
+
+/// An advisory as returned by the registry's bulk advisory endpoint, or
+/// read from an offline OSV database.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Advisory {
+    #[serde(deserialize_with = "deserialize_id")]
     pub id: String,
+    #[serde(default)]
+    pub url: String,
     pub title: String,
-    pub description: String,
-    pub severity: String,
-    pub affected_versions: String,
-    pub patched_version: Option<String>,
+    pub severity: Severity,
+    /// The affected versions as an npm range, e.g. `>=4.0.0 <4.17.21`.
+    pub vulnerable_versions: String,
+    #[serde(default)]
+    pub cwe: Vec<String>,
+    #[serde(default)]
+    pub cvss: Option<Cvss>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Cvss {
+    pub score: f64,
+    #[serde(rename = "vectorString", default)]
+    pub vector_string: Option<String>,
+}
+
+/// The registry's advisory IDs are numbers, OSV's are strings such as
+/// `GHSA-vh95-rmgr-6w4m`.
+fn deserialize_id<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Number(u64),
+        Text(String),
+    }
+
+    Ok(match Id::deserialize(deserializer)? {
+        Id::Number(id) => id.to_string(),
+        Id::Text(id) => id,
+    })
+}
+
+impl Advisory {
+    /// The GitHub advisory ID, which the registry only has in the URL.
+    pub fn ghsa_id(&self) -> Option<&str> {
+        if self.id.starts_with("GHSA-") {
+            return Some(&self.id);
+        }
+        self.url.rsplit('/').next().filter(|segment| segment.starts_with("GHSA-"))
+    }
+
+    /// Whether `version` is in `vulnerable_versions`. A range that doesn't
+    /// parse counts as affecting every version: the registry only returns
+    /// advisories for versions it was asked about, so this errs on the side
+    /// of reporting.
+    pub fn affects(&self, version: &Version) -> bool {
+        match VersionRange::parse(&self.vulnerable_versions) {
+            Ok(range) => range.matches(version),
+            Err(_) => {
+                log::warn!(
+                    "Advisory {} has an unreadable range {:?}",
+                    self.id,
+                    self.vulnerable_versions
+                );
+                true
+            }
+        }
+    }
 }
 
 pub struct SecurityChecker {
     client: Client,
-    cache: HashMap<String, Vec<Vulnerability>>,
+    registry_url: Url,
+}
+
+impl Default for SecurityChecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SecurityChecker {
     pub fn new() -> Self {
+        let registry_url = Url::parse("https://registry.npmjs.org").expect("Invalid registry URL");
+        Self::with_url(registry_url)
+    }
+
+    pub fn with_url(registry_url: Url) -> Self {
         Self {
             client: Client::new(),
-            cache: HashMap::new(),
+            registry_url,
         }
     }
 
-    pub async fn check_package(&mut self, name: &str, version: &Version) -> Result<Vec<Vulnerability>> {
-        // Query the NPM Security Advisory Database
-        let url = format!(
-            "https://registry.npmjs.org/-/npm/v1/security/advisories/search?package={}",
-            name
-        );
+    /// Posts every installed version of every package to the bulk advisory
+    /// endpoint in one request. The result holds, per package, the advisories
+    /// affecting at least one of the versions sent.
+    pub async fn fetch_advisories(
+        &self,
+        installed: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Result<HashMap<String, Vec<Advisory>>> {
+        if installed.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let url = self.registry_url.join("/-/npm/v1/security/advisories/bulk")?;
+        let response = self
+            .client
+            .post(url)
+            .json(installed)
+            .send()
+            .await
+            .map_err(|e| SecurityError::CheckFailed(e.to_string()))?;
 
-        let response = self.client.get(&url).send().await?;
-        let vulnerabilities: Vec<Vulnerability> = response.json().await?;
-        
-        self.cache.insert(name.to_string(), vulnerabilities.clone());
-        
-        Ok(vulnerabilities)
+        if !response.status().is_success() {
+            return Err(SecurityError::CheckFailed(format!("advisory endpoint returned {}", response.status())).into());
+        }
+
+        let advisories = response
+            .json()
+            .await
+            .map_err(|e| SecurityError::CheckFailed(e.to_string()))?;
+        Ok(advisories)
+    }
+
+    /// The advisories affecting one version of a package.
+    pub async fn check_package(&self, name: &str, version: &Version) -> Result<Vec<Advisory>> {
+        let installed = BTreeMap::from([(name.to_string(), BTreeSet::from([version.to_string()]))]);
+        let mut advisories = self.fetch_advisories(&installed).await?;
+
+        Ok(advisories
+            .remove(name)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|advisory| advisory.affects(version))
+            .collect())
     }
 
-    pub async fn find_safe_version(
-        &mut self,
+    /// The version to move `name` to so that no advisory in `advisories`
+    /// affects it: `current_version` when it's already safe, otherwise the
+    /// safe release `range` allows that is nearest to it, otherwise the
+    /// lowest newer release at all. Prereleases are never picked. Within
+    /// the range the lowest newer release wins, and failing that the
+    /// highest older one, so a line with no fix yet can go back to the
+    /// last release before the vulnerability. Staying close to
+    /// `current_version` keeps fixes from crossing major versions when a
+    /// patch release would do.
+    pub fn find_safe_version(
+        &self,
         name: &str,
         current_version: &Version,
+        range: &VersionReq,
+        advisories: &[Advisory],
         available_versions: &[Version],
-    ) -> Result<Version> {
-        let vulnerabilities = self.check_package(name, current_version).await?;
-        
-        if vulnerabilities.is_empty() {
-            return Ok(current_version.clone());
+    ) -> Result<SafeVersion> {
+        let is_safe = |version: &Version| !advisories.iter().any(|advisory| advisory.affects(version));
+        if is_safe(current_version) {
+            return Ok(SafeVersion { version: current_version.clone(), reason: SafeReason::AlreadySafe });
         }
 
-        // Find the nearest safe version
-        let safe_version = available_versions
+        let mut candidates: Vec<&Version> = available_versions
             .iter()
-            .rev()  // Start from newest versions
-            .find(|&version| {
-                !vulnerabilities.iter().any(|vuln| {
-                    if let Some(patched) = &vuln.patched_version {
-                        Version::parse(patched).map_or(true, |p| version >= &p)
-                    } else {
-                        false
-                    }
-                })
-            })
-            .ok_or_else(|| SecurityError::NoSafeVersion(name.to_string()))?;
-
-        Ok(safe_version.clone())
-    }
-} 
\ No newline at end of file
+            .filter(|version| version.pre.is_empty() && is_safe(version))
+            .collect();
+        candidates.sort();
+        let (older, newer) = candidates.split_at(candidates.partition_point(|version| *version < current_version));
+
+        if let Some(version) = newer.iter().find(|version| range.matches(version)) {
+            return Ok(SafeVersion { version: (*version).clone(), reason: SafeReason::InRange });
+        }
+        if let Some(version) = older.iter().rev().find(|version| range.matches(version)) {
+            return Ok(SafeVersion { version: (*version).clone(), reason: SafeReason::BackInRange });
+        }
+        match newer.first() {
+            Some(version) => Ok(SafeVersion { version: (*version).clone(), reason: SafeReason::OutOfRange }),
+            None => Err(SecurityError::NoSafeVersion(name.to_string()).into()),
+        }
+    }
+}
+
+/// A version `find_safe_version` settled on, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafeVersion {
+    pub version: Version,
+    pub reason: SafeReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafeReason {
+    /// No advisory affects the current version.
+    AlreadySafe,
+    /// The lowest safe release within the range above the current version.
+    InRange,
+    /// Nothing above the current version within the range is safe; the
+    /// highest safe release below it is.
+    BackInRange,
+    /// Every release within the range is affected; the lowest safe one
+    /// needs a new range.
+    OutOfRange,
+}
+
+impl fmt::Display for SafeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SafeReason::AlreadySafe => "not affected",
+            SafeReason::InRange => "lowest safe version in range",
+            SafeReason::BackInRange => "highest older safe version in range",
+            SafeReason::OutOfRange => "no safe version in range",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_severity() {
+        assert_eq!(Severity::parse("CRITICAL"), Severity::Critical);
+        assert_eq!(Severity::parse("medium"), Severity::Moderate);
+        assert_eq!(Severity::parse("whatever"), Severity::Unknown);
+        assert!(Severity::Critical > Severity::High);
+        assert!(Severity::Moderate >= Severity::Low);
+        assert!(Severity::Info > Severity::Unknown);
+
+        let advisory: Advisory = serde_json::from_value(serde_json::json!({
+            "id": 1179,
+            "title": "Prototype Pollution in minimist",
+            "severity": "moderate",
+            "vulnerable_versions": "<0.2.1"
+        }))
+        .unwrap();
+        assert_eq!(advisory.id, "1179");
+        assert_eq!(advisory.severity, Severity::Moderate);
+        assert_eq!(serde_json::to_value(advisory.severity).unwrap(), "moderate");
+    }
+
+    fn advisory(id: &str, vulnerable_versions: &str) -> Advisory {
+        Advisory {
+            id: id.to_string(),
+            url: String::new(),
+            title: format!("Advisory {}", id),
+            severity: Severity::High,
+            vulnerable_versions: vulnerable_versions.to_string(),
+            cwe: Vec::new(),
+            cvss: None,
+        }
+    }
+
+    #[test]
+    fn test_find_safe_version() {
+        let checker = SecurityChecker::new();
+        let v = |text: &str| Version::parse(text).unwrap();
+        let range = |text: &str| VersionReq::parse(text).unwrap();
+        let available: Vec<Version> = ["1.0.0", "1.2.0", "1.2.5", "1.2.6", "1.2.8", "1.3.0-beta.1", "1.3.0", "2.0.0"]
+            .iter()
+            .map(|text| v(text))
+            .collect();
+        // One advisory fixed in 1.2.6, another with no fix below 1.3.0 in
+        // the 1.2.x line that doesn't affect 1.3.x.
+        let advisories = [advisory("1", "<1.2.6"), advisory("2", ">=1.2.6 <1.2.8")];
+
+        let safe = checker.find_safe_version("a", &v("1.2.0"), &range("^1.2.0"), &advisories, &available).unwrap();
+        assert_eq!(safe, SafeVersion { version: v("1.2.8"), reason: SafeReason::InRange });
+
+        // The range decides over being closest to the current version.
+        let safe = checker.find_safe_version("a", &v("1.2.0"), &range(">=1.3.0"), &advisories, &available).unwrap();
+        assert_eq!(safe, SafeVersion { version: v("1.3.0"), reason: SafeReason::InRange });
+
+        // Nothing safe in `=1.2.5`; the lowest safe release wins over the
+        // newest, and the 1.3.0 prerelease is skipped.
+        let advisories = [advisory("1", "<1.3.0")];
+        let safe = checker.find_safe_version("a", &v("1.2.5"), &range("=1.2.5"), &advisories, &available).unwrap();
+        assert_eq!(safe, SafeVersion { version: v("1.3.0"), reason: SafeReason::OutOfRange });
+
+        let safe = checker.find_safe_version("a", &v("2.0.0"), &range("^2.0.0"), &advisories, &available).unwrap();
+        assert_eq!(safe, SafeVersion { version: v("2.0.0"), reason: SafeReason::AlreadySafe });
+
+        // No fix in the 1.x line yet: back to the newest release before it.
+        let advisories = [advisory("1", ">=1.2.6 <2.0.0")];
+        let safe = checker.find_safe_version("a", &v("1.2.8"), &range("^1.0.0"), &advisories, &available).unwrap();
+        assert_eq!(safe, SafeVersion { version: v("1.2.5"), reason: SafeReason::BackInRange });
+        // Outside the range only newer releases are considered.
+        let safe = checker.find_safe_version("a", &v("1.2.8"), &range("=1.2.8"), &advisories, &available).unwrap();
+        assert_eq!(safe, SafeVersion { version: v("2.0.0"), reason: SafeReason::OutOfRange });
+
+        // Unpatched: every release is affected.
+        let advisories = [advisory("1", ">=0.0.0")];
+        let error = checker.find_safe_version("a", &v("1.0.0"), &range("^1.0.0"), &advisories, &available).unwrap_err();
+        assert_eq!(error.to_string(), "No safe version found for package a");
+    }
+}
@@ -0,0 +1,52 @@
+/// Renders rows as left-aligned columns under a header line, for command
+/// output such as `rpm outdated`.
+pub fn render(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let header_row: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+    let all_rows: Vec<Vec<String>> = std::iter::once(header_row).chain(rows.iter().cloned()).collect();
+
+    align(&all_rows).into_iter().map(|line| line + "\n").collect()
+}
+
+/// Pads every column to its widest cell, returning one line per row without
+/// trailing whitespace.
+pub fn align(rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (column, cell) in row.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(column) {
+                Some(existing) => *existing = (*existing).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_aligns_columns() {
+        let rows = vec![
+            vec!["lodash".to_string(), "4.17.15".to_string(), "4.17.21".to_string()],
+            vec!["react".to_string(), "missing".to_string(), "18.2.0".to_string()],
+        ];
+
+        assert_eq!(
+            render(&["Package", "Current", "Latest"], &rows),
+            "Package  Current  Latest\nlodash   4.17.15  4.17.21\nreact    missing  18.2.0\n"
+        );
+    }
+}
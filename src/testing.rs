@@ -0,0 +1,73 @@
+use crate::lockfile::LockFile;
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::fs;
+use tokio::process::Command;
+
+/// Writes `content` as `dir/package.json`, creating `dir` first.
+pub async fn write_manifest(dir: &Path, content: &str) {
+    fs::create_dir_all(dir).await.unwrap();
+    fs::write(dir.join("package.json"), content).await.unwrap();
+}
+
+/// A lockfile entry for `version` without dependencies of its own.
+pub fn locked(version: &str) -> serde_json::Value {
+    locked_requiring(version, serde_json::Value::Null)
+}
+
+/// A lockfile entry for `version` declaring `requires`.
+pub fn locked_requiring(version: &str, requires: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({"version": version, "resolved": "", "integrity": "", "requires": requires})
+}
+
+/// The lockfile of a project `app@1.0.0` with `dependencies` at the top
+/// level.
+pub fn lockfile(dependencies: serde_json::Value) -> LockFile {
+    serde_json::from_value(serde_json::json!({
+        "name": "app",
+        "version": "1.0.0",
+        "lockfile_version": 1,
+        "requires": true,
+        "dependencies": dependencies
+    }))
+    .unwrap()
+}
+
+/// Runs `git` in `dir`, returning its trimmed stdout.
+pub async fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=rpm", "-c", "user.email=rpm@example.com"])
+        .args(args)
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// A bare repository with tags v1.0.0, v1.1.0 (annotated) and v2.0.0.
+pub async fn create_bare_repo(root: &Path) -> (String, HashMap<&'static str, String>) {
+    let work = root.join("work");
+    fs::create_dir_all(&work).await.unwrap();
+    git(&work, &["init", "--quiet"]).await;
+
+    let mut commits = HashMap::new();
+    for (tag, annotated) in [("v1.0.0", false), ("v1.1.0", true), ("v2.0.0", false)] {
+        let manifest = format!(r#"{{"name": "lib", "version": "{}"}}"#, &tag[1..]);
+        fs::write(work.join("package.json"), manifest).await.unwrap();
+        git(&work, &["add", "package.json"]).await;
+        git(&work, &["commit", "--quiet", "-m", tag]).await;
+        if annotated {
+            git(&work, &["tag", "-a", tag, "-m", tag]).await;
+        } else {
+            git(&work, &["tag", tag]).await;
+        }
+        commits.insert(tag, git(&work, &["rev-parse", "HEAD"]).await);
+    }
+
+    let bare = root.join("lib.git");
+    git(root, &["clone", "--quiet", "--bare", &work.to_string_lossy(), &bare.to_string_lossy()]).await;
+    (format!("file://{}", bare.display()), commits)
+}
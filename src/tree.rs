@@ -0,0 +1,1019 @@
+use crate::dependency::DependencySource;
+use crate::lockfile::{LockFile, LockedDependency};
+use crate::package::PackageJson;
+use crate::workspace::Workspace;
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// The manifest field an edge comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    Prod,
+    Dev,
+    Optional,
+    Peer,
+}
+
+impl fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            EdgeKind::Prod => "prod",
+            EdgeKind::Dev => "dev",
+            EdgeKind::Optional => "optional",
+            EdgeKind::Peer => "peer",
+        };
+        f.write_str(kind)
+    }
+}
+
+impl EdgeKind {
+    /// The `package.json` field declaring edges of this kind.
+    pub fn manifest_field(&self) -> &'static str {
+        match self {
+            EdgeKind::Prod => "dependencies",
+            EdgeKind::Dev => "devDependencies",
+            EdgeKind::Optional => "optionalDependencies",
+            EdgeKind::Peer => "peerDependencies",
+        }
+    }
+}
+
+/// The dependency types left out of `node_modules`, following npm: `--omit`
+/// defaults to `dev` when `NODE_ENV` is `production`, and `--include` takes
+/// precedence over both.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Omit {
+    kinds: Vec<EdgeKind>,
+}
+
+impl Omit {
+    pub fn new(omit: &[EdgeKind], include: &[EdgeKind], node_env: Option<&str>) -> Self {
+        let requested: &[EdgeKind] = if omit.is_empty() && node_env == Some("production") {
+            &[EdgeKind::Dev]
+        } else {
+            omit
+        };
+
+        let mut kinds = Vec::new();
+        for kind in requested {
+            if !include.contains(kind) && !kinds.contains(kind) {
+                kinds.push(*kind);
+            }
+        }
+        Self { kinds }
+    }
+
+    /// Reads `NODE_ENV` from the environment.
+    pub fn from_env(omit: &[EdgeKind], include: &[EdgeKind]) -> Self {
+        Self::new(omit, include, std::env::var("NODE_ENV").ok().as_deref())
+    }
+
+    pub fn includes(&self, kind: EdgeKind) -> bool {
+        !self.kinds.contains(&kind)
+    }
+
+    pub fn kinds(&self) -> &[EdgeKind] {
+        &self.kinds
+    }
+}
+
+/// A dependency declared by a manifest or a locked package.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub name: String,
+    pub range: String,
+    pub kind: EdgeKind,
+}
+
+/// Where dependency paths start: the project itself or a workspace member.
+#[derive(Debug, Clone)]
+pub struct Importer {
+    pub name: String,
+    pub version: String,
+    pub edges: Vec<Edge>,
+}
+
+impl Importer {
+    pub fn from_manifest(manifest: &PackageJson) -> Self {
+        let mut edges = Vec::new();
+        collect_edges(&mut edges, manifest.dependencies.as_ref(), EdgeKind::Prod);
+        collect_edges(&mut edges, manifest.dev_dependencies.as_ref(), EdgeKind::Dev);
+        collect_edges(&mut edges, manifest.optional_dependencies.as_ref(), EdgeKind::Optional);
+        collect_edges(&mut edges, manifest.peer_dependencies.as_ref(), EdgeKind::Peer);
+
+        Self {
+            name: manifest.name.clone(),
+            version: manifest.version.clone(),
+            edges,
+        }
+    }
+}
+
+impl Importer {
+    /// Edges resolved against the registry, with the registry package name
+    /// (which differs for `npm:` aliases) and range. Git, path and
+    /// workspace-local dependencies are left out.
+    pub fn registry_edges(&self, workspace: Option<&Workspace>) -> Vec<(&Edge, String, VersionReq)> {
+        self.edges
+            .iter()
+            .filter(|edge| workspace.is_none_or(|ws| ws.resolve_local(&edge.name, &edge.range).is_none()))
+            .filter_map(|edge| match DependencySource::parse(&edge.name, &edge.range) {
+                Ok(DependencySource::Registry(version_req)) => Some((edge, edge.name.clone(), version_req)),
+                Ok(DependencySource::Alias { package, version_req }) => Some((edge, package, version_req)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The root project followed by every workspace member.
+pub fn importers(manifest: &PackageJson, workspace: Option<&Workspace>) -> Vec<Importer> {
+    let mut importers = vec![Importer::from_manifest(manifest)];
+    if let Some(workspace) = workspace {
+        importers.extend(workspace.members.iter().map(|m| Importer::from_manifest(&m.manifest)));
+    }
+    importers
+}
+
+fn collect_edges(edges: &mut Vec<Edge>, group: Option<&HashMap<String, String>>, kind: EdgeKind) {
+    let mut group: Vec<_> = group.into_iter().flatten().collect();
+    group.sort();
+    edges.extend(group.into_iter().map(|(name, range)| Edge {
+        name: name.clone(),
+        range: range.clone(),
+        kind,
+    }));
+}
+
+fn locked_edges(entry: &LockedDependency) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    collect_edges(&mut edges, entry.requires(), EdgeKind::Prod);
+    collect_edges(&mut edges, entry.optional_requires(), EdgeKind::Optional);
+    collect_edges(&mut edges, entry.peer_requires(), EdgeKind::Peer);
+    edges
+}
+
+/// A copy of a package in `node_modules`, as the chain of names leading to
+/// it from the top level.
+pub type NodePath = Vec<String>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Parent {
+    Importer(usize),
+    Node(NodePath),
+}
+
+/// The installed dependency tree recorded in a lockfile, together with the
+/// manifests that depend on it.
+pub struct InstalledTree<'a> {
+    lockfile: &'a LockFile,
+    importers: Vec<Importer>,
+}
+
+impl<'a> InstalledTree<'a> {
+    pub fn new(lockfile: &'a LockFile, importers: Vec<Importer>) -> Self {
+        Self { lockfile, importers }
+    }
+
+    fn scope(&self, path: &[String]) -> Option<&'a HashMap<String, LockedDependency>> {
+        let mut scope = self.lockfile.dependencies();
+        for name in path {
+            scope = scope.get(name)?.dependencies()?;
+        }
+        Some(scope)
+    }
+
+    fn entry(&self, path: &[String]) -> Option<&'a LockedDependency> {
+        self.lockfile.entry(path)
+    }
+
+    /// The copy of `name` visible from `from`, following Node's lookup: the
+    /// package's own `node_modules` first, then each ancestor's.
+    fn locate(&self, from: &[String], name: &str) -> Option<NodePath> {
+        (0..=from.len()).rev().find_map(|depth| {
+            self.scope(&from[..depth])?.get(name)?;
+            let mut path = from[..depth].to_vec();
+            path.push(name.to_string());
+            Some(path)
+        })
+    }
+
+    fn nodes(&self) -> Vec<(NodePath, &'a LockedDependency)> {
+        self.lockfile.nodes()
+    }
+
+    /// Where the importer at `index` looks its dependencies up from: a
+    /// linked workspace member or a fetched package finds copies in its own
+    /// `node_modules` before the top level.
+    fn importer_path(&self, index: usize) -> NodePath {
+        let name = &self.importers[index].name;
+        match self.lockfile.dependencies().get(name) {
+            Some(entry) if index > 0 && (entry.is_link() || entry.from().is_some()) => vec![name.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the lockfile can be installed as it is: every range the
+    /// importers and the locked packages declare finds a copy that satisfies
+    /// it, or the override replacing it, and nothing locked is left over.
+    /// Optional dependencies may be missing, as they are when they failed to
+    /// resolve; peers are up to the dependent.
+    pub fn is_current(&self, workspace: Option<&Workspace>, overrides: &HashMap<String, String>) -> bool {
+        let mut edges: Vec<(NodePath, Edge)> = Vec::new();
+        for (index, importer) in self.importers.iter().enumerate() {
+            let from = self.importer_path(index);
+            edges.extend(
+                importer
+                    .edges
+                    .iter()
+                    .filter(|edge| workspace.is_none_or(|ws| ws.resolve_local(&edge.name, &edge.range).is_none()))
+                    .map(|edge| (from.clone(), edge.clone())),
+            );
+        }
+        for (path, entry) in self.nodes() {
+            edges.extend(locked_edges(entry).into_iter().map(|edge| (path.clone(), edge)));
+        }
+
+        let satisfied = edges.iter().filter(|(_, edge)| edge.kind != EdgeKind::Peer).all(|(from, edge)| {
+            let range = overrides.get(&edge.name).unwrap_or(&edge.range);
+            match self.locate(from, &edge.name).and_then(|path| self.entry(&path)) {
+                Some(entry) => satisfies(&edge.name, range, entry.version()),
+                None => {
+                    edge.kind == EdgeKind::Optional
+                        || !matches!(
+                            DependencySource::parse(&edge.name, range),
+                            Ok(DependencySource::Registry(_) | DependencySource::Alias { .. })
+                        )
+                }
+            }
+        });
+        satisfied && self.extraneous().is_empty()
+    }
+
+    /// Every edge leading to each installed copy, keyed by the copy.
+    fn parents(&self) -> HashMap<NodePath, Vec<(Parent, Edge)>> {
+        let mut parents: HashMap<NodePath, Vec<(Parent, Edge)>> = HashMap::new();
+
+        for (index, importer) in self.importers.iter().enumerate() {
+            let from = self.importer_path(index);
+            for edge in &importer.edges {
+                if let Some(child) = self.locate(&from, &edge.name) {
+                    parents.entry(child).or_default().push((Parent::Importer(index), edge.clone()));
+                }
+            }
+        }
+
+        for (path, entry) in self.nodes() {
+            for edge in locked_edges(entry) {
+                if let Some(child) = self.locate(&path, &edge.name) {
+                    if child != path {
+                        parents.entry(child).or_default().push((Parent::Node(path.clone()), edge));
+                    }
+                }
+            }
+        }
+
+        parents
+    }
+
+    /// Installed copies reachable from the importers through edges accepted
+    /// by `include`. Installed packages never have dev edges, so leaving out
+    /// `Dev` only affects the importers' own devDependencies, while leaving
+    /// out `Optional` drops optional dependencies at every level.
+    pub fn reachable(&self, include: impl Fn(EdgeKind) -> bool) -> HashSet<NodePath> {
+        let mut reachable = HashSet::new();
+        let mut stack: Vec<NodePath> = Vec::new();
+
+        for (index, importer) in self.importers.iter().enumerate() {
+            // Workspace members are linked at the top level.
+            if self.lockfile.dependencies().get(&importer.name).is_some_and(LockedDependency::is_link) {
+                stack.push(vec![importer.name.clone()]);
+            }
+            let from = self.importer_path(index);
+            stack.extend(
+                importer
+                    .edges
+                    .iter()
+                    .filter(|edge| include(edge.kind))
+                    .filter_map(|edge| self.locate(&from, &edge.name)),
+            );
+        }
+
+        while let Some(path) = stack.pop() {
+            let Some(entry) = self.entry(&path) else {
+                continue;
+            };
+            if !reachable.insert(path.clone()) {
+                continue;
+            }
+            stack.extend(
+                locked_edges(entry)
+                    .iter()
+                    .filter(|edge| include(edge.kind))
+                    .filter_map(|edge| self.locate(&path, &edge.name)),
+            );
+        }
+
+        reachable
+    }
+
+    /// Every installed copy nothing depends on, e.g. left over after a
+    /// dependency was removed from `package.json`.
+    pub fn extraneous(&self) -> Vec<NodePath> {
+        self.unreachable(|_| true)
+    }
+
+    /// Every installed copy not reachable through edges accepted by
+    /// `include`, sorted.
+    pub fn unreachable(&self, include: impl Fn(EdgeKind) -> bool) -> Vec<NodePath> {
+        let reachable = self.reachable(include);
+        let mut unreachable: Vec<NodePath> = self
+            .nodes()
+            .into_iter()
+            .map(|(path, _)| path)
+            .filter(|path| !reachable.contains(path))
+            .collect();
+        unreachable.sort();
+        unreachable
+    }
+
+    /// The dependency tree of each importer as installed, for `rpm list`.
+    /// Extraneous packages are listed under the first importer.
+    pub fn list(&self, options: &ListOptions) -> Vec<ListTree> {
+        let mut seen = HashSet::new();
+        let mut trees: Vec<ListTree> = self
+            .importers
+            .iter()
+            .enumerate()
+            .map(|(index, importer)| ListTree {
+                name: importer.name.clone(),
+                version: importer.version.clone(),
+                dependencies: importer
+                    .edges
+                    .iter()
+                    .filter(|edge| options.includes(edge.kind))
+                    .filter_map(|edge| self.list_edge(&self.importer_path(index), edge, 0, options, &mut seen))
+                    .collect(),
+            })
+            .collect();
+
+        let extraneous: Vec<ListNode> = self
+            .extraneous()
+            .into_iter()
+            .filter_map(|path| {
+                let entry = self.entry(&path)?;
+                Some(ListNode {
+                    name: path.last()?.clone(),
+                    version: Some(entry.version().to_string()),
+                    location: Some(location(&path)),
+                    extraneous: true,
+                    ..Default::default()
+                })
+            })
+            .collect();
+        if let Some(first) = trees.first_mut() {
+            first.dependencies.extend(extraneous);
+        }
+
+        trees
+    }
+
+    fn list_edge(
+        &self,
+        from: &[String],
+        edge: &Edge,
+        depth: usize,
+        options: &ListOptions,
+        seen: &mut HashSet<NodePath>,
+    ) -> Option<ListNode> {
+        let mut node = ListNode {
+            name: edge.name.clone(),
+            required: Some(edge.range.clone()),
+            kind: Some(edge.kind),
+            ..Default::default()
+        };
+
+        let Some(path) = self.locate(from, &edge.name) else {
+            // Optional dependencies are allowed to be absent.
+            if edge.kind == EdgeKind::Optional {
+                return None;
+            }
+            node.missing = true;
+            return Some(node);
+        };
+        let entry = self.entry(&path)?;
+
+        node.version = Some(entry.version().to_string());
+        node.location = Some(location(&path));
+        node.invalid = !satisfies(&edge.name, &edge.range, entry.version());
+
+        if !seen.insert(path.clone()) {
+            node.deduped = true;
+            return Some(node);
+        }
+        if options.depth.is_some_and(|max| depth >= max) {
+            return Some(node);
+        }
+
+        node.dependencies = locked_edges(entry)
+            .iter()
+            .filter_map(|child| self.list_edge(&path, child, depth + 1, options, seen))
+            .collect();
+        Some(node)
+    }
+
+    /// Every path from an importer to each installed copy of `package`.
+    pub fn explain(&self, package: &str) -> Vec<Explanation> {
+        let parents = self.parents();
+        let mut explanations: Vec<Explanation> = self
+            .nodes()
+            .into_iter()
+            .filter(|(path, _)| path.last().is_some_and(|name| name == package))
+            .map(|(path, entry)| {
+                let mut paths = Vec::new();
+                self.walk_back(&path, &parents, &mut Vec::new(), &mut paths);
+                paths.sort_by_key(|path| path.to_string());
+
+                Explanation {
+                    name: package.to_string(),
+                    version: entry.version().to_string(),
+                    location: location(&path),
+                    paths,
+                }
+            })
+            .collect();
+
+        explanations.sort_by(|a, b| a.location.cmp(&b.location));
+        explanations
+    }
+
+    /// Follows edges backwards from `node` until an importer is reached.
+    /// `chain` holds the nodes and incoming edges walked so far, deepest
+    /// first; nodes already on it are skipped to break cycles.
+    fn walk_back(
+        &self,
+        node: &NodePath,
+        parents: &HashMap<NodePath, Vec<(Parent, Edge)>>,
+        chain: &mut Vec<(NodePath, Edge)>,
+        paths: &mut Vec<DependencyPath>,
+    ) {
+        for (parent, edge) in parents.get(node).into_iter().flatten() {
+            if let Parent::Node(parent) = parent {
+                if parent == node || chain.iter().any(|(visited, _)| visited == parent) {
+                    continue;
+                }
+            }
+
+            chain.push((node.clone(), edge.clone()));
+            match parent {
+                Parent::Importer(index) => paths.push(DependencyPath {
+                    importer: self.importers[*index].name.clone(),
+                    steps: chain
+                        .iter()
+                        .rev()
+                        .map(|(path, edge)| PathStep {
+                            name: edge.name.clone(),
+                            version: self.entry(path).map(|e| e.version().to_string()).unwrap_or_default(),
+                            range: edge.range.clone(),
+                            kind: edge.kind,
+                        })
+                        .collect(),
+                }),
+                Parent::Node(parent) => self.walk_back(parent, parents, chain, paths),
+            }
+            chain.pop();
+        }
+    }
+
+    /// Registry packages installed more than once, sorted by name.
+    pub fn duplicated(&self) -> Vec<String> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (mut path, _) in self.nodes() {
+            if let Some(name) = path.pop() {
+                *counts.entry(name).or_default() += 1;
+            }
+        }
+
+        let mut names: Vec<String> = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Works out which copies of `name` can be dropped because their
+    /// dependents are just as happy with the copy they'd find next. One
+    /// installed copy is kept at the top level: the one letting the most
+    /// other copies go, then the highest version. A nested copy can only be
+    /// hoisted when it has no `node_modules` of its own and its dependencies
+    /// are met from the top level. Links and git, tarball and path
+    /// dependencies are left alone.
+    pub fn collapse(&self, name: &str) -> Option<Collapse> {
+        let copies: Vec<(NodePath, &'a LockedDependency)> = self
+            .nodes()
+            .into_iter()
+            .filter(|(path, _)| path.last().is_some_and(|last| last == name))
+            .collect();
+        if copies.len() < 2 || copies.iter().any(|(_, entry)| entry.is_link() || entry.from().is_some()) {
+            return None;
+        }
+
+        let parents = self.parents();
+        let top = copies.iter().find(|(path, _)| path.len() == 1);
+        copies
+            .iter()
+            .filter(|(path, entry)| match top {
+                Some((top_path, top_entry)) => {
+                    path == top_path || (entry.version() != top_entry.version() && self.hoistable(entry))
+                }
+                None => self.hoistable(entry),
+            })
+            .filter_map(|keep| self.collapse_into(&copies, keep, &parents))
+            .max_by_key(|collapse| (collapse.remove.len(), Version::parse(&collapse.version).ok()))
+    }
+
+    /// Whether `entry` would still find what it needs if it were moved to the
+    /// top level.
+    fn hoistable(&self, entry: &LockedDependency) -> bool {
+        entry.dependencies().is_none()
+            && locked_edges(entry).iter().all(|edge| {
+                match self.locate(&[], &edge.name).and_then(|path| self.entry(&path)) {
+                    Some(dependency) => satisfies(&edge.name, &edge.range, dependency.version()),
+                    None => edge.kind != EdgeKind::Prod,
+                }
+            })
+    }
+
+    /// The copies that can go if `keep` ends up at the top level. Shallow
+    /// copies are decided first since deeper ones may fall back on them.
+    fn collapse_into(
+        &self,
+        copies: &[(NodePath, &'a LockedDependency)],
+        keep: &(NodePath, &'a LockedDependency),
+        parents: &HashMap<NodePath, Vec<(Parent, Edge)>>,
+    ) -> Option<Collapse> {
+        let (keep_path, keep_entry) = keep;
+        let name = keep_path.last()?;
+        let inside = |path: &[String], removed: &[&NodePath]| removed.iter().any(|copy| path.starts_with(copy));
+
+        // The version dependents of the copy at `path` get once it's gone:
+        // the next copy up that stays, or the kept one at the top level.
+        let fallback = |path: &NodePath, removed: &[&NodePath]| -> &'a str {
+            let scope = &path[..path.len() - 1];
+            (1..scope.len())
+                .rev()
+                .find_map(|depth| {
+                    let mut candidate = scope[..depth].to_vec();
+                    candidate.push(name.clone());
+                    if &candidate == keep_path || inside(&candidate, removed) {
+                        return None;
+                    }
+                    self.entry(&candidate).map(LockedDependency::version)
+                })
+                .unwrap_or(keep_entry.version())
+        };
+        let removable = |path: &NodePath, removed: &[&NodePath]| {
+            let version = fallback(path, removed);
+            parents
+                .get(path)
+                .into_iter()
+                .flatten()
+                .filter(|(parent, _)| !matches!(parent, Parent::Node(parent) if inside(parent, removed)))
+                .all(|(_, edge)| satisfies(&edge.name, &edge.range, version))
+        };
+
+        let mut removed: Vec<&NodePath> = Vec::new();
+        // Hoisting replaces whatever is at the top level.
+        if keep_path.len() > 1 {
+            if let Some((top_path, _)) = copies.iter().find(|(path, _)| path.len() == 1) {
+                removed.push(top_path);
+                if inside(keep_path, &removed) || !removable(top_path, &removed) {
+                    return None;
+                }
+            }
+        }
+
+        let mut rest: Vec<&NodePath> = copies
+            .iter()
+            .map(|(path, _)| path)
+            .filter(|path| *path != keep_path && path.len() > 1)
+            .collect();
+        rest.sort_by_key(|path| path.len());
+        for path in rest {
+            if inside(path, &removed) {
+                continue;
+            }
+            removed.push(path);
+            if !removable(path, &removed) {
+                removed.pop();
+            }
+        }
+
+        if removed.is_empty() {
+            return None;
+        }
+        Some(Collapse {
+            name: name.clone(),
+            version: keep_entry.version().to_string(),
+            keep: keep_path.clone(),
+            remove: removed
+                .into_iter()
+                .filter_map(|path| Some((path.clone(), self.entry(path)?.version().to_string())))
+                .collect(),
+        })
+    }
+}
+
+/// Copies of a package to drop in favour of ones already installed, with
+/// the kept top-level copy moved up if it's nested now.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collapse {
+    pub name: String,
+    pub version: String,
+    /// Where the copy kept at the top level is installed now.
+    pub keep: NodePath,
+    /// The copies to remove, with their versions.
+    pub remove: Vec<(NodePath, String)>,
+}
+
+/// Whether an installed version satisfies a declared range. Only registry
+/// ranges can be checked; git, path and `workspace:` specifiers always pass.
+fn satisfies(name: &str, range: &str, version: &str) -> bool {
+    match (DependencySource::parse(name, range), Version::parse(version)) {
+        (Ok(DependencySource::Registry(version_req)), Ok(version)) => version_req.matches(&version),
+        (Ok(DependencySource::Alias { version_req, .. }), Ok(version)) => version_req.matches(&version),
+        _ => true,
+    }
+}
+
+/// `node_modules/a/node_modules/b` for the path `[a, b]`.
+pub fn location(path: &[String]) -> String {
+    format!("node_modules/{}", path.join("/node_modules/"))
+}
+
+/// Why an installed copy of a package is there.
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    pub name: String,
+    pub version: String,
+    pub location: String,
+    pub paths: Vec<DependencyPath>,
+}
+
+/// A chain of dependency edges from an importer to a package.
+#[derive(Debug, Serialize)]
+pub struct DependencyPath {
+    pub importer: String,
+    pub steps: Vec<PathStep>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathStep {
+    pub name: String,
+    pub version: String,
+    /// The range the edge into this package declares.
+    pub range: String,
+    #[serde(rename = "type")]
+    pub kind: EdgeKind,
+}
+
+impl fmt::Display for DependencyPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.importer)?;
+        for step in &self.steps {
+            write!(f, " > {}@{} ({}", step.name, step.version, step.range)?;
+            if step.kind != EdgeKind::Prod {
+                write!(f, ", {}", step.kind)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListOptions {
+    /// How many levels below the importers' own dependencies to show.
+    pub depth: Option<usize>,
+    /// Only the importers' dependencies, optionalDependencies and
+    /// peerDependencies.
+    pub prod: bool,
+    /// Only the importers' devDependencies.
+    pub dev: bool,
+}
+
+impl ListOptions {
+    fn includes(&self, kind: EdgeKind) -> bool {
+        match kind {
+            EdgeKind::Dev => !self.prod,
+            _ => !self.dev,
+        }
+    }
+}
+
+/// An importer and the dependencies installed for it.
+#[derive(Debug, Serialize)]
+pub struct ListTree {
+    pub name: String,
+    pub version: String,
+    pub dependencies: Vec<ListNode>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ListNode {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The range the dependent declared.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<EdgeKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// Already shown elsewhere in the tree, so its dependencies are omitted.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub deduped: bool,
+    /// Installed but not required by anything.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub extraneous: bool,
+    /// Required but not installed.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub missing: bool,
+    /// Installed at a version outside the required range.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub invalid: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<ListNode>,
+}
+
+impl ListTree {
+    /// Locations of every package shown, relative to the project root, for
+    /// `--parseable` output.
+    pub fn locations(&self) -> Vec<&str> {
+        let mut locations = Vec::new();
+        let mut stack: Vec<&ListNode> = self.dependencies.iter().rev().collect();
+
+        while let Some(node) = stack.pop() {
+            if let (Some(location), false) = (&node.location, node.deduped) {
+                locations.push(location.as_str());
+            }
+            stack.extend(node.dependencies.iter().rev());
+        }
+
+        locations
+    }
+}
+
+impl fmt::Display for ListTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}@{}", self.name, self.version)?;
+        write_nodes(f, &self.dependencies, "")
+    }
+}
+
+fn write_nodes(f: &mut fmt::Formatter<'_>, nodes: &[ListNode], prefix: &str) -> fmt::Result {
+    for (index, node) in nodes.iter().enumerate() {
+        let last = index + 1 == nodes.len();
+        let (branch, indent) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+
+        write!(f, "{}{}", prefix, branch)?;
+        if node.missing {
+            write!(f, "{}@{} missing", node.name, node.required.as_deref().unwrap_or("*"))?;
+        } else {
+            write!(f, "{}@{}", node.name, node.version.as_deref().unwrap_or_default())?;
+        }
+        match node.kind {
+            Some(EdgeKind::Prod) | None => {}
+            Some(kind) => write!(f, " ({})", kind)?,
+        }
+        if node.invalid {
+            write!(f, " invalid: \"{}\"", node.required.as_deref().unwrap_or_default())?;
+        }
+        if node.deduped {
+            write!(f, " deduped")?;
+        }
+        if node.extraneous {
+            write!(f, " extraneous")?;
+        }
+        writeln!(f)?;
+
+        write_nodes(f, &node.dependencies, &format!("{}{}", prefix, indent))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installed() -> serde_json::Value {
+        serde_json::json!({
+            "express": {
+                "version": "4.18.2",
+                "resolved": "https://registry.npmjs.org/express/-/express-4.18.2.tgz",
+                "integrity": "",
+                "requires": {"debug": "2.6.9", "body-parser": "1.20.1"},
+                "dependencies": {
+                    "debug": {
+                        "version": "2.6.9",
+                        "resolved": "https://registry.npmjs.org/debug/-/debug-2.6.9.tgz",
+                        "integrity": "",
+                        "requires": null
+                    },
+                    "body-parser": {
+                        "version": "1.20.1",
+                        "resolved": "https://registry.npmjs.org/body-parser/-/body-parser-1.20.1.tgz",
+                        "integrity": "",
+                        "requires": {"debug": "2.6.9"}
+                    }
+                }
+            },
+            "debug": {
+                "version": "4.3.4",
+                "resolved": "https://registry.npmjs.org/debug/-/debug-4.3.4.tgz",
+                "integrity": "",
+                "requires": null
+            }
+        })
+    }
+
+    fn lockfile() -> LockFile {
+        crate::testing::lockfile(installed())
+    }
+
+    /// `lockfile()` plus an outdated lodash and an extraneous left-pad.
+    fn listed_lockfile() -> LockFile {
+        let mut installed = installed();
+        installed["lodash"] = serde_json::json!({
+            "version": "4.17.15",
+            "resolved": "https://registry.npmjs.org/lodash/-/lodash-4.17.15.tgz",
+            "integrity": "",
+            "requires": null
+        });
+        installed["left-pad"] = serde_json::json!({
+            "version": "1.3.0",
+            "resolved": "https://registry.npmjs.org/left-pad/-/left-pad-1.3.0.tgz",
+            "integrity": "",
+            "requires": null
+        });
+        crate::testing::lockfile(installed)
+    }
+
+    fn manifest() -> PackageJson {
+        serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": {"express": "^4.18.0"},
+            "devDependencies": {"debug": "^4.3.0"}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_explain_lists_every_path() {
+        let lockfile = lockfile();
+        let tree = InstalledTree::new(&lockfile, importers(&manifest(), None));
+
+        let explanations = tree.explain("debug");
+        assert_eq!(explanations.len(), 2);
+
+        assert_eq!(explanations[0].location, "node_modules/debug");
+        let paths: Vec<String> = explanations[0].paths.iter().map(|p| p.to_string()).collect();
+        assert_eq!(paths, vec!["app > debug@4.3.4 (^4.3.0, dev)"]);
+
+        // body-parser is nested next to the debug copy it resolves to.
+        assert_eq!(explanations[1].location, "node_modules/express/node_modules/debug");
+        let paths: Vec<String> = explanations[1].paths.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "app > express@4.18.2 (^4.18.0) > body-parser@1.20.1 (1.20.1) > debug@2.6.9 (2.6.9)",
+                "app > express@4.18.2 (^4.18.0) > debug@2.6.9 (2.6.9)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_members_find_their_own_copies() {
+        let mut installed = installed();
+        installed["web"] = serde_json::json!({
+            "version": "1.0.0",
+            "resolved": "file:packages/web",
+            "integrity": "",
+            "requires": {"debug": "^2.6.0"},
+            "link": true,
+            "dependencies": {
+                "debug": {
+                    "version": "2.6.9",
+                    "resolved": "https://registry.npmjs.org/debug/-/debug-2.6.9.tgz",
+                    "integrity": "",
+                    "requires": null
+                }
+            }
+        });
+        let lockfile = crate::testing::lockfile(installed);
+        let web: PackageJson = serde_json::from_value(serde_json::json!({
+            "name": "web",
+            "version": "1.0.0",
+            "dependencies": {"debug": "^2.6.0"}
+        }))
+        .unwrap();
+        let tree = InstalledTree::new(
+            &lockfile,
+            vec![Importer::from_manifest(&manifest()), Importer::from_manifest(&web)],
+        );
+
+        assert!(tree.extraneous().is_empty());
+        let explanations = tree.explain("debug");
+        assert_eq!(explanations[2].location, "node_modules/web/node_modules/debug");
+        let paths: Vec<String> = explanations[2].paths.iter().map(|p| p.to_string()).collect();
+        assert_eq!(paths, vec!["web > debug@2.6.9 (^2.6.0)"]);
+    }
+
+    #[test]
+    fn test_is_current() {
+        let lockfile = lockfile();
+        let no_overrides = HashMap::new();
+        assert!(InstalledTree::new(&lockfile, importers(&manifest(), None)).is_current(None, &no_overrides));
+
+        // A range the locked copy no longer satisfies, directly or through an
+        // override.
+        let mut raised = manifest();
+        raised.dev_dependencies = Some(HashMap::from([("debug".to_string(), "^4.4.0".to_string())]));
+        assert!(!InstalledTree::new(&lockfile, importers(&raised, None)).is_current(None, &no_overrides));
+        let overrides = HashMap::from([("debug".to_string(), "^3.0.0".to_string())]);
+        assert!(!InstalledTree::new(&lockfile, importers(&manifest(), None)).is_current(None, &overrides));
+
+        // A removed dependency leaves its copy over.
+        let mut removed = manifest();
+        removed.dev_dependencies = None;
+        assert!(!InstalledTree::new(&lockfile, importers(&removed, None)).is_current(None, &no_overrides));
+    }
+
+    #[test]
+    fn test_explain_unknown_package() {
+        let lockfile = lockfile();
+        let tree = InstalledTree::new(&lockfile, importers(&manifest(), None));
+
+        assert!(tree.explain("left-pad").is_empty());
+    }
+
+    #[test]
+    fn test_explain_extraneous_package() {
+        let lockfile = listed_lockfile();
+        let tree = InstalledTree::new(&lockfile, importers(&manifest(), None));
+
+        // Installed, but nothing leads to it.
+        let explanations = tree.explain("left-pad");
+        assert_eq!(explanations.len(), 1);
+        assert_eq!(explanations[0].location, "node_modules/left-pad");
+        assert!(explanations[0].paths.is_empty());
+    }
+
+    #[test]
+    fn test_list_markers() {
+        let lockfile = listed_lockfile();
+        let mut manifest = manifest();
+        manifest.add_dependency("lodash", "^4.17.21", false);
+        manifest.add_dependency("react", "^18.0.0", false);
+        let tree = InstalledTree::new(&lockfile, importers(&manifest, None));
+
+        let trees = tree.list(&ListOptions::default());
+        let nodes = &trees[0].dependencies;
+        let find = |name: &str| nodes.iter().find(|node| node.name == name).unwrap();
+
+        assert!(find("react").missing);
+        assert!(find("lodash").invalid);
+        assert!(find("left-pad").extraneous);
+
+        // body-parser is listed first and shows the nested debug in full.
+        let express = find("express");
+        let names: Vec<_> = express.dependencies.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, vec!["body-parser", "debug"]);
+        assert!(!express.dependencies[0].dependencies[0].deduped);
+        assert!(express.dependencies[1].deduped);
+
+        let prod = tree.list(&ListOptions { prod: true, ..Default::default() });
+        assert!(prod[0].dependencies.iter().all(|node| node.kind != Some(EdgeKind::Dev)));
+
+        let shallow = tree.list(&ListOptions { depth: Some(0), ..Default::default() });
+        assert!(shallow[0].dependencies.iter().all(|node| node.dependencies.is_empty()));
+    }
+
+    #[test]
+    fn test_omit() {
+        assert!(Omit::new(&[], &[], None).includes(EdgeKind::Dev));
+        assert!(!Omit::new(&[], &[], Some("production")).includes(EdgeKind::Dev));
+        assert!(Omit::new(&[], &[EdgeKind::Dev], Some("production")).includes(EdgeKind::Dev));
+
+        let omit = Omit::new(&[EdgeKind::Optional, EdgeKind::Optional], &[], Some("production"));
+        assert_eq!(omit.kinds(), &[EdgeKind::Optional]);
+        assert!(omit.includes(EdgeKind::Dev));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::lockfile::LockFile;
+use crate::package::{Packument, PackageJson};
+use crate::registry::RegistryClient;
+use crate::tree::Importer;
+use crate::workspace::Workspace;
+use anyhow::{bail, Result};
+use futures::future::try_join_all;
+use semver::{Op, Version, VersionReq};
+
+/// How far `rpm update` may move a dependency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdatePolicy {
+    /// The highest version the declared range allows.
+    Range,
+    /// The highest release the range allows with the same major and minor
+    /// version.
+    Patch,
+    /// The highest release the range allows with the same major version.
+    Minor,
+    /// The `latest` dist-tag, whatever the range says.
+    Latest,
+}
+
+/// A dependency bump, with the range to write back to `package.json`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedUpdate {
+    pub name: String,
+    /// The project or workspace member declaring the dependency.
+    pub workspace: String,
+    /// The manifest field declaring it, e.g. `devDependencies`.
+    pub field: &'static str,
+    pub from_range: String,
+    pub to_range: String,
+    pub from_version: Option<Version>,
+    pub to_version: Version,
+}
+
+impl PlannedUpdate {
+    pub fn row(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.workspace.clone(),
+            self.from_version.as_ref().map_or("-".to_string(), Version::to_string),
+            self.to_version.to_string(),
+            format!("{} -> {}", self.from_range, self.to_range),
+        ]
+    }
+}
+
+pub const UPDATE_HEADERS: [&str; 5] = ["Package", "Workspace", "Before", "After", "Range"];
+
+/// Works out the version each registry dependency should move to under
+/// `policy`. With `only` non-empty, other dependencies are left alone; each
+/// name in it has to be a registry dependency of one of `importers`.
+pub async fn plan_updates(
+    registry: &RegistryClient,
+    importers: &[Importer],
+    installed: &LockFile,
+    workspace: Option<&Workspace>,
+    policy: UpdatePolicy,
+    only: &[String],
+) -> Result<Vec<PlannedUpdate>> {
+    let candidates: Vec<_> = importers
+        .iter()
+        .flat_map(|importer| {
+            importer
+                .registry_edges(workspace)
+                .into_iter()
+                .filter(|(edge, _, _)| only.is_empty() || only.contains(&edge.name))
+                .map(move |(edge, package, version_req)| (importer, edge, package, version_req))
+        })
+        .collect();
+    for name in only {
+        if !candidates.iter().any(|(_, edge, _, _)| &edge.name == name) {
+            bail!("{} is not a registry dependency of the project", name);
+        }
+    }
+
+    let packuments = try_join_all(
+        candidates
+            .iter()
+            .map(|(_, _, package, _)| registry.fetch_packument(package)),
+    )
+    .await?;
+
+    let mut updates = Vec::new();
+    for ((importer, edge, _, version_req), packument) in candidates.into_iter().zip(packuments) {
+        let installed_version = installed
+            .get_dependency(&edge.name)
+            .and_then(|entry| Version::parse(entry.version()).ok());
+        let baseline = installed_version.clone().or_else(|| range_floor(&version_req));
+
+        let Some(target) = select_version(&packument, &version_req, baseline.as_ref(), policy) else {
+            continue;
+        };
+        if baseline.as_ref().is_some_and(|baseline| &target <= baseline) {
+            continue;
+        }
+
+        updates.push(PlannedUpdate {
+            name: edge.name.clone(),
+            workspace: importer.name.clone(),
+            field: edge.kind.manifest_field(),
+            from_range: edge.range.clone(),
+            to_range: rewrite_range(&edge.range, &target),
+            from_version: installed_version,
+            to_version: target,
+        });
+    }
+
+    updates.sort_by(|a, b| (&a.workspace, &a.name).cmp(&(&b.workspace, &b.name)));
+    Ok(updates)
+}
+
+/// Writes the planned ranges for `manifest` into it. Returns whether anything
+/// changed.
+pub fn apply_updates(manifest: &mut PackageJson, updates: &[PlannedUpdate]) -> bool {
+    let name = manifest.name.clone();
+    let mut changed = false;
+    for update in updates.iter().filter(|update| update.workspace == name) {
+        changed |= manifest.set_range(update.field, &update.name, &update.to_range);
+    }
+    changed
+}
+
+fn select_version(
+    packument: &Packument,
+    version_req: &VersionReq,
+    baseline: Option<&Version>,
+    policy: UpdatePolicy,
+) -> Option<Version> {
+    let highest_where = |keep: &dyn Fn(&Version) -> bool| {
+        packument
+            .versions
+            .keys()
+            .filter(|version| version.pre.is_empty() && keep(version))
+            .max()
+            .cloned()
+    };
+
+    match (policy, baseline) {
+        (UpdatePolicy::Range, _) => packument.max_satisfying(version_req).map(|p| p.version.clone()),
+        (UpdatePolicy::Latest, _) => packument.latest().map(|p| p.version.clone()),
+        (UpdatePolicy::Patch, Some(base)) => {
+            highest_where(&|v| version_req.matches(v) && v.major == base.major && v.minor == base.minor)
+        }
+        (UpdatePolicy::Minor, Some(base)) => highest_where(&|v| version_req.matches(v) && v.major == base.major),
+        (_, None) => None,
+    }
+}
+
+/// The lowest version a range admits, used as the starting point when the
+/// dependency isn't installed.
+fn range_floor(version_req: &VersionReq) -> Option<Version> {
+    version_req
+        .comparators
+        .iter()
+        .filter(|c| matches!(c.op, Op::Exact | Op::GreaterEq | Op::Tilde | Op::Caret | Op::Wildcard))
+        .map(|c| Version::new(c.major, c.minor.unwrap_or(0), c.patch.unwrap_or(0)))
+        .max()
+}
+
+/// Points `range` at `version` while keeping its operator, e.g. `^4.17.1`
+/// becomes `^4.17.21` and `~1.2` becomes `~1.3.0`. Ranges without a single
+/// leading operator, like `*` or `>=1 <2`, become a caret range unless they
+/// already admit `version`.
+pub(crate) fn rewrite_range(range: &str, version: &Version) -> String {
+    if let Some((alias, inner)) = range.strip_prefix("npm:").and_then(|rest| rest.rsplit_once('@')) {
+        return format!("npm:{}@{}", alias, rewrite_range(inner, version));
+    }
+
+    let trimmed = range.trim();
+    let operator_end = trimmed
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (operator, rest) = trimmed.split_at(operator_end);
+    let simple = !rest.is_empty()
+        && !rest.contains([' ', ',', '|', '*', 'x', 'X'])
+        && ["", "^", "~", "=", ">=", "v"].contains(&operator);
+
+    if simple {
+        let operator = if operator == "v" { "" } else { operator };
+        return format!("{}{}", operator, version);
+    }
+
+    match VersionReq::parse(trimmed) {
+        Ok(version_req) if trimmed != "*" && version_req.matches(version) => range.to_string(),
+        _ if trimmed == "*" || trimmed == "latest" || trimmed.is_empty() => range.to_string(),
+        _ => format!("^{}", version),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::mock::{packument, MockRegistry};
+    use crate::testing::{locked, lockfile};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rewrite_range_keeps_prefix() {
+        let version = Version::new(4, 17, 21);
+        assert_eq!(rewrite_range("^4.17.1", &version), "^4.17.21");
+        assert_eq!(rewrite_range("~4.17", &version), "~4.17.21");
+        assert_eq!(rewrite_range("4.17.15", &version), "4.17.21");
+        assert_eq!(rewrite_range(">=4.0.0", &version), ">=4.17.21");
+        assert_eq!(rewrite_range("*", &version), "*");
+        assert_eq!(rewrite_range(">=3, <4", &version), "^4.17.21");
+        assert_eq!(rewrite_range("npm:lodash-es@^4.0.0", &version), "npm:lodash-es@^4.17.21");
+    }
+
+    #[tokio::test]
+    async fn test_plan_updates_by_policy() {
+        let documents = HashMap::from([(
+            "/lodash".to_string(),
+            packument("lodash", &[("4.17.15", &[]), ("4.17.21", &[]), ("4.18.0", &[]), ("5.0.0", &[])]),
+        )]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let client = RegistryClient::with_url(registry.url.clone());
+
+        let manifest: PackageJson = serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": {"lodash": "~4.17.0"}
+        }))
+        .unwrap();
+        let installed = lockfile(serde_json::json!({
+            "lodash": locked("4.17.15")
+        }));
+        let importers = vec![Importer::from_manifest(&manifest)];
+
+        // Only --latest leaves the declared range.
+        let mut targets = Vec::new();
+        for policy in [UpdatePolicy::Range, UpdatePolicy::Patch, UpdatePolicy::Minor, UpdatePolicy::Latest] {
+            let updates = plan_updates(&client, &importers, &installed, None, policy, &[]).await.unwrap();
+            targets.push(updates[0].to_range.clone());
+        }
+        assert_eq!(targets, vec!["~4.17.21", "~4.17.21", "~4.17.21", "~5.0.0"]);
+
+        // Within a caret range, --patch and --minor cap the bump.
+        let caret: PackageJson = serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "version": "1.0.0",
+            "dependencies": {"lodash": "^4.17.0"}
+        }))
+        .unwrap();
+        let caret_importers = vec![Importer::from_manifest(&caret)];
+        let mut targets = Vec::new();
+        for policy in [UpdatePolicy::Range, UpdatePolicy::Patch, UpdatePolicy::Minor] {
+            let updates = plan_updates(&client, &caret_importers, &installed, None, policy, &[]).await.unwrap();
+            targets.push(updates[0].to_range.clone());
+        }
+        assert_eq!(targets, vec!["^4.18.0", "^4.17.21", "^4.18.0"]);
+
+        let mut manifest = caret;
+        let updates = plan_updates(&client, &caret_importers, &installed, None, UpdatePolicy::Patch, &[]).await.unwrap();
+        assert!(apply_updates(&mut manifest, &updates));
+        assert_eq!(manifest.dependencies.unwrap()["lodash"], "^4.17.21");
+
+        let only = vec!["lodahs".to_string()];
+        let error = plan_updates(&client, &importers, &installed, None, UpdatePolicy::Range, &only).await.unwrap_err();
+        assert_eq!(error.to_string(), "lodahs is not a registry dependency of the project");
+    }
+}
@@ -0,0 +1,250 @@
+use crate::outdated::OutdatedDependency;
+use crate::package::Repository;
+use crate::registry::RegistryClient;
+use crate::table;
+use crate::update::{rewrite_range, PlannedUpdate};
+use anyhow::{bail, Result};
+use dialoguer::{theme::ColorfulTheme, MultiSelect};
+use futures::future::join_all;
+use semver::{Comparator, Op, Version, VersionReq};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, IsTerminal};
+
+/// How disruptive a bump is expected to be. Levels follow caret ranges, so
+/// on `0.x` a minor bump already counts as major.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BumpLevel {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl BumpLevel {
+    pub fn between(from: &Version, to: &Version) -> Self {
+        let compatible = VersionReq {
+            comparators: vec![Comparator {
+                op: Op::Caret,
+                major: from.major,
+                minor: Some(from.minor),
+                patch: Some(from.patch),
+                pre: from.pre.clone(),
+            }],
+        };
+
+        if !compatible.matches(to) {
+            BumpLevel::Major
+        } else if (from.major, from.minor) == (to.major, to.minor) {
+            BumpLevel::Patch
+        } else {
+            BumpLevel::Minor
+        }
+    }
+
+    fn heading(self) -> &'static str {
+        match self {
+            BumpLevel::Patch => "Patch updates (backwards-compatible fixes)",
+            BumpLevel::Minor => "Minor updates (backwards-compatible features)",
+            BumpLevel::Major => "Major updates (potentially breaking changes)",
+        }
+    }
+}
+
+/// An outdated dependency offered for upgrade to its `latest` version.
+#[derive(Debug, Clone)]
+pub struct UpgradeCandidate {
+    pub dependency: OutdatedDependency,
+    pub target: Version,
+    pub level: BumpLevel,
+    /// Where to read about the changes, derived from `repository`.
+    pub changelog: Option<String>,
+}
+
+impl UpgradeCandidate {
+    /// The manifest change picking this candidate makes.
+    pub fn planned(&self) -> PlannedUpdate {
+        PlannedUpdate {
+            name: self.dependency.name.clone(),
+            workspace: self.dependency.workspace.clone(),
+            field: self.dependency.kind,
+            from_range: self.dependency.range.clone(),
+            to_range: rewrite_range(&self.dependency.range, &self.target),
+            from_version: self.dependency.current.clone(),
+            to_version: self.target.clone(),
+        }
+    }
+}
+
+/// Turns `rpm outdated` results into upgrade candidates. Dependencies that
+/// aren't installed, or are already at or past `latest`, are left out since a
+/// plain install takes care of them.
+pub async fn upgrade_candidates(
+    registry: &RegistryClient,
+    outdated: Vec<OutdatedDependency>,
+) -> Vec<UpgradeCandidate> {
+    let upgradable: Vec<_> = outdated
+        .into_iter()
+        .filter_map(|dependency| {
+            let current = dependency.current.as_ref()?;
+            let target = dependency.latest.clone().filter(|latest| latest > current)?;
+            let level = BumpLevel::between(current, &target);
+            Some((dependency, target, level))
+        })
+        .collect();
+
+    // Abbreviated packuments leave out `repository`, so it's read from each
+    // target version's own document. A missing link isn't worth failing over.
+    let targets: BTreeSet<(String, String)> = upgradable
+        .iter()
+        .map(|(dependency, target, _)| (dependency.package.clone(), target.to_string()))
+        .collect();
+    let documents = join_all(
+        targets
+            .iter()
+            .map(|(package, version)| registry.fetch_package_info(package, Some(version))),
+    )
+    .await;
+    let repositories: HashMap<&(String, String), Repository> = targets
+        .iter()
+        .zip(documents)
+        .filter_map(|(key, document)| Some((key, document.ok()?.repository?)))
+        .collect();
+
+    upgradable
+        .into_iter()
+        .map(|(dependency, target, level)| {
+            let key = (dependency.package.clone(), target.to_string());
+            let changelog = repositories.get(&key).and_then(changelog_url);
+            UpgradeCandidate { dependency, target, level, changelog }
+        })
+        .collect()
+}
+
+/// Shows one checklist per bump level and returns the picked candidates, or
+/// `None` when the prompt is cancelled.
+pub fn choose_upgrades(candidates: &[UpgradeCandidate]) -> Result<Option<Vec<&UpgradeCandidate>>> {
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        bail!("upgrade-interactive needs a terminal; use `rpm update` in scripts");
+    }
+
+    let labels = labels(candidates);
+    let theme = ColorfulTheme::default();
+    let mut chosen = Vec::new();
+
+    for level in [BumpLevel::Patch, BumpLevel::Minor, BumpLevel::Major] {
+        let group: Vec<usize> = (0..candidates.len())
+            .filter(|&index| candidates[index].level == level)
+            .collect();
+        if group.is_empty() {
+            continue;
+        }
+
+        let items: Vec<&str> = group.iter().map(|&index| labels[index].as_str()).collect();
+        let Some(picked) = MultiSelect::with_theme(&theme)
+            .with_prompt(level.heading())
+            .items(&items)
+            .interact_opt()?
+        else {
+            return Ok(None);
+        };
+        chosen.extend(picked.into_iter().map(|position| &candidates[group[position]]));
+    }
+
+    Ok(Some(chosen))
+}
+
+/// One aligned checklist line per candidate, so columns line up across the
+/// level groups.
+fn labels(candidates: &[UpgradeCandidate]) -> Vec<String> {
+    let rows: Vec<Vec<String>> = candidates
+        .iter()
+        .map(|candidate| {
+            let current = candidate.dependency.current.as_ref().map_or("-".to_string(), Version::to_string);
+            vec![
+                candidate.dependency.name.clone(),
+                candidate.dependency.workspace.clone(),
+                format!("{} -> {}", current, candidate.target),
+                candidate.changelog.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    table::align(&rows)
+}
+
+/// The release notes page for hosts that have one, otherwise the repository
+/// itself.
+fn changelog_url(repository: &Repository) -> Option<String> {
+    let web_url = repository.web_url()?;
+    if web_url.starts_with("https://github.com/") {
+        Some(format!("{}/releases", web_url))
+    } else if web_url.starts_with("https://gitlab.com/") {
+        Some(format!("{}/-/releases", web_url))
+    } else {
+        Some(web_url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::mock::MockRegistry;
+    use std::time::Duration;
+
+    fn version(s: &str) -> Version {
+        Version::parse(s).unwrap()
+    }
+
+    fn outdated(name: &str, current: &str, latest: &str) -> OutdatedDependency {
+        OutdatedDependency {
+            name: name.to_string(),
+            package: name.to_string(),
+            current: Some(version(current)),
+            wanted: Some(version(current)),
+            latest: Some(version(latest)),
+            kind: "dependencies",
+            workspace: "app".to_string(),
+            range: format!("^{}", current),
+        }
+    }
+
+    #[test]
+    fn test_bump_level() {
+        assert_eq!(BumpLevel::between(&version("4.17.15"), &version("4.17.21")), BumpLevel::Patch);
+        assert_eq!(BumpLevel::between(&version("4.17.15"), &version("4.18.0")), BumpLevel::Minor);
+        assert_eq!(BumpLevel::between(&version("17.0.2"), &version("18.2.0")), BumpLevel::Major);
+        assert_eq!(BumpLevel::between(&version("0.2.1"), &version("0.2.5")), BumpLevel::Patch);
+        assert_eq!(BumpLevel::between(&version("0.2.1"), &version("0.3.0")), BumpLevel::Major);
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_candidates() {
+        let documents = HashMap::from([(
+            "/react/18.2.0".to_string(),
+            serde_json::json!({
+                "name": "react",
+                "version": "18.2.0",
+                "repository": {"type": "git", "url": "git+https://github.com/facebook/react.git", "directory": "packages/react"},
+                "dist": {"tarball": "https://registry.example/react/-/react-18.2.0.tgz", "shasum": "0000"}
+            })
+            .to_string(),
+        )]);
+        let registry = MockRegistry::start(documents, Duration::ZERO).await;
+        let client = RegistryClient::with_url(registry.url.clone());
+
+        let mut missing = outdated("jest", "29.0.0", "29.7.0");
+        missing.current = None;
+        let dependencies = vec![
+            outdated("lodash", "4.17.15", "4.17.21"),
+            outdated("react", "17.0.2", "18.2.0"),
+            missing,
+        ];
+
+        let candidates = upgrade_candidates(&client, dependencies).await;
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].level, BumpLevel::Patch);
+        assert_eq!(candidates[0].changelog, None);
+        assert_eq!(candidates[1].level, BumpLevel::Major);
+        assert_eq!(candidates[1].changelog.as_deref(), Some("https://github.com/facebook/react/releases"));
+        assert_eq!(candidates[1].planned().to_range, "^18.2.0");
+    }
+}
@@ -1,10 +1,38 @@
-use sha2::{Digest, Sha256};
+use crate::date::parse_date;
+use crate::package::Package;
+use crate::registry::RegistryClient;
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use p256::pkcs8::DecodePublicKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
+/// Where a registry publishes the keys it signs packages with.
+pub const KEYS_PATH: &str = "/-/npm/v1/keys";
+
+/// How long fetched keys are used before asking the registry again.
+const KEYS_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
 #[derive(Error, Debug)]
 pub enum VerificationError {
     #[error("Package checksum verification failed: expected {expected}, got {actual}")]
     ChecksumMismatch { expected: String, actual: String },
+
+    #[error("Package integrity verification failed: expected {expected}, got {actual}")]
+    IntegrityMismatch { expected: String, actual: String },
+
+    #[error("Unsupported integrity {0}")]
+    UnsupportedIntegrity(String),
+
+    #[error("Invalid registry signature for {package}: {reason}")]
+    InvalidSignature { package: String, reason: String },
+
+    #[error("{0} has no registry signature, though the registry signs packages")]
+    MissingSignature(String),
 }
 
 pub struct ChecksumIntegrityChecker;
@@ -32,6 +60,193 @@ impl Verification for ChecksumIntegrityChecker {
     }
 }
 
+/// Checks `package_data` against a Subresource Integrity string such as
+/// `sha512-<base64>`, the form of `dist.integrity`. With several hashes, one
+/// matching is enough; algorithms other than SHA-512 and SHA-256 are skipped.
+pub fn verify_integrity(package_data: &[u8], integrity: &str) -> Result<(), VerificationError> {
+    let mut first_mismatch = None;
+    for hash in integrity.split_whitespace() {
+        let actual = match hash.split_once('-') {
+            Some(("sha512", _)) => format!("sha512-{}", STANDARD.encode(Sha512::digest(package_data))),
+            Some(("sha256", _)) => format!("sha256-{}", STANDARD.encode(Sha256::digest(package_data))),
+            _ => continue,
+        };
+        // Options such as `?foo` may follow the digest.
+        if hash.split('?').next() == Some(actual.as_str()) {
+            return Ok(());
+        }
+        first_mismatch.get_or_insert(VerificationError::IntegrityMismatch { expected: hash.to_string(), actual });
+    }
+    Err(first_mismatch.unwrap_or_else(|| VerificationError::UnsupportedIntegrity(integrity.to_string())))
+}
+
+/// The response of `/-/npm/v1/keys`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryKeys {
+    pub keys: Vec<RegistryKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryKey {
+    /// e.g. `SHA256:jl3bwswu80PjjokCgh0o2w5c2U4LhQAE57gj9cz1kzA`, matched
+    /// against the `keyid` of signatures.
+    pub keyid: String,
+    #[serde(default)]
+    pub keytype: String,
+    #[serde(default)]
+    pub scheme: String,
+    /// A base64 DER SubjectPublicKeyInfo.
+    pub key: String,
+    /// When the registry stopped signing with the key. Signatures by an
+    /// expired key only verify for versions published before that.
+    #[serde(default)]
+    pub expires: Option<String>,
+}
+
+/// The outcome of checking one version's `dist.signatures`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignatureStatus {
+    Verified { keyid: String },
+    Unsigned,
+    Invalid(String),
+}
+
+impl RegistryKeys {
+    /// The registry's keys, read from the cache under `cache_dir` while
+    /// they're fresh. `None` when the registry doesn't publish keys. A stale
+    /// copy is still used when the registry can't be reached.
+    pub async fn load(registry: &RegistryClient, cache_dir: &Path) -> Result<Option<Self>> {
+        let url = registry.registry_url();
+        let host = match url.port() {
+            Some(port) => format!("{}_{}", url.host_str().unwrap_or("registry"), port),
+            None => url.host_str().unwrap_or("registry").to_string(),
+        };
+        let path = cache_dir.join("keys").join(format!("{}.json", host));
+
+        let cached = Self::read_cached(&path).await;
+        if let Some((keys, age)) = &cached {
+            if *age < KEYS_MAX_AGE {
+                return Ok(Some(keys.clone()));
+            }
+        }
+
+        match registry.fetch_keys().await {
+            Ok(Some(keys)) => {
+                tokio::fs::create_dir_all(cache_dir.join("keys")).await?;
+                tokio::fs::write(&path, serde_json::to_vec(&keys)?).await?;
+                Ok(Some(keys))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => match cached {
+                Some((keys, _)) => {
+                    log::warn!("Using cached registry keys, fetching them failed: {}", e);
+                    Ok(Some(keys))
+                }
+                None => Err(e.into()),
+            },
+        }
+    }
+
+    async fn read_cached(path: &Path) -> Option<(Self, Duration)> {
+        let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+        let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+        let keys = serde_json::from_slice(&tokio::fs::read(path).await.ok()?).ok()?;
+        Some((keys, age))
+    }
+
+    /// Like `verify`, looking up when the package was published if it's
+    /// signed with a key that has expired since.
+    pub async fn verify_published(&self, registry: &RegistryClient, package: &Package) -> Result<SignatureStatus> {
+        let expired_key = package.dist.signatures.iter().any(|signature| {
+            self.keys
+                .iter()
+                .any(|key| key.keyid == signature.keyid && !key.valid_at(None))
+        });
+        let published = if expired_key {
+            registry.fetch_publish_time(&package.name, &package.version).await?
+        } else {
+            None
+        };
+        Ok(self.verify(package, published.as_deref()))
+    }
+
+    /// Checks the registry's signature over `name@version:integrity`. Any
+    /// one signature from a known key verifying is enough. A key past its
+    /// `expires` only counts when `published`, the version's publish time,
+    /// is before it.
+    pub fn verify(&self, package: &Package, published: Option<&str>) -> SignatureStatus {
+        let signatures = &package.dist.signatures;
+        if signatures.is_empty() {
+            return SignatureStatus::Unsigned;
+        }
+        let Some(integrity) = &package.dist.integrity else {
+            return SignatureStatus::Invalid("signed but has no integrity".to_string());
+        };
+        let message = format!("{}@{}:{}", package.name, package.version, integrity);
+
+        let mut reason = String::new();
+        for signature in signatures {
+            let Some(key) = self.keys.iter().find(|key| key.keyid == signature.keyid) else {
+                reason = format!("signed with unknown key {}", signature.keyid);
+                continue;
+            };
+            if !key.valid_at(published) {
+                reason = format!("signed with key {} after it expired", key.keyid);
+                continue;
+            }
+            match key.verifies(&message, &signature.sig) {
+                Ok(()) => return SignatureStatus::Verified { keyid: key.keyid.clone() },
+                Err(e) => reason = e,
+            }
+        }
+        SignatureStatus::Invalid(reason)
+    }
+}
+
+impl RegistryKey {
+    /// Whether the key was still in use at `time`, or now when that's not
+    /// known. An `expires` that can't be read counts as past.
+    fn valid_at(&self, time: Option<&str>) -> bool {
+        let Some(expires) = &self.expires else {
+            return true;
+        };
+        let Some(expires) = parse_timestamp(expires) else {
+            return false;
+        };
+        let time = match time {
+            Some(time) => parse_timestamp(time),
+            None => SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|elapsed| elapsed.as_secs() as i64),
+        };
+        time.is_some_and(|time| time < expires)
+    }
+
+    fn verifies(&self, message: &str, sig: &str) -> Result<(), String> {
+        let der = STANDARD.decode(&self.key).map_err(|e| format!("key {} is not base64: {}", self.keyid, e))?;
+        let key = VerifyingKey::from_public_key_der(&der)
+            .map_err(|e| format!("key {} is not a P-256 public key: {}", self.keyid, e))?;
+        let signature = STANDARD
+            .decode(sig)
+            .ok()
+            .and_then(|der| Signature::from_der(&der).ok())
+            .ok_or_else(|| "malformed signature".to_string())?;
+        key.verify(message.as_bytes(), &signature)
+            .map_err(|_| format!("signature doesn't match key {}", self.keyid))
+    }
+}
+
+/// A UTC timestamp like `2025-01-29T00:00:00.000Z` as seconds since the
+/// Unix epoch.
+fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let (date, time) = timestamp.split_once('T')?;
+    let time = time.strip_suffix('Z')?;
+    let time = time.split_once('.').map_or(time, |(seconds, _)| seconds);
+    let mut parts = time.splitn(3, ':');
+    let (hours, minutes, seconds): (i64, i64, i64) =
+        (parts.next()?.parse().ok()?, parts.next()?.parse().ok()?, parts.next()?.parse().ok()?);
+    let days = parse_date(date)?;
+    Some(days * 86_400 + hours * 3_600 + minutes * 60 + seconds)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -74,4 +289,98 @@ mod tests {
 
         assert!(ChecksumIntegrityChecker::verify_package(package_data, expected_shasum).is_ok());
     }
+
+    #[test]
+    fn test_verify_integrity() {
+        let data = b"test package data";
+        let sha512 = format!("sha512-{}", STANDARD.encode(Sha512::digest(data)));
+
+        assert!(verify_integrity(data, &sha512).is_ok());
+        assert!(verify_integrity(data, &format!("sha1-AAAA {}", sha512)).is_ok());
+        assert!(matches!(
+            verify_integrity(b"tampered", &sha512),
+            Err(VerificationError::IntegrityMismatch { .. })
+        ));
+        assert!(matches!(
+            verify_integrity(data, "sha1-AAAA"),
+            Err(VerificationError::UnsupportedIntegrity(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        use p256::ecdsa::{signature::Signer, SigningKey};
+        use p256::pkcs8::EncodePublicKey;
+
+        let signing_key = SigningKey::from_slice(&[7; 32]).unwrap();
+        let other_key = SigningKey::from_slice(&[9; 32]).unwrap();
+        let public_key = signing_key.verifying_key().to_public_key_der().unwrap();
+        let keys = RegistryKeys {
+            keys: vec![RegistryKey {
+                keyid: "SHA256:test".to_string(),
+                keytype: "ecdsa-sha2-nistp256".to_string(),
+                scheme: "ecdsa-sha2-nistp256".to_string(),
+                key: STANDARD.encode(public_key.as_bytes()),
+                expires: None,
+            }],
+        };
+
+        let integrity = "sha512-deadbeef";
+        let sign = |key: &SigningKey, message: &str| {
+            let signature: Signature = key.sign(message.as_bytes());
+            STANDARD.encode(signature.to_der().as_bytes())
+        };
+        let package = |keyid: &str, sig: String| -> Package {
+            serde_json::from_value(serde_json::json!({
+                "name": "left-pad",
+                "version": "1.3.0",
+                "dist": {
+                    "tarball": "https://registry.example/left-pad/-/left-pad-1.3.0.tgz",
+                    "shasum": "0000",
+                    "integrity": integrity,
+                    "signatures": [{"keyid": keyid, "sig": sig}]
+                }
+            }))
+            .unwrap()
+        };
+
+        let signed = package("SHA256:test", sign(&signing_key, "left-pad@1.3.0:sha512-deadbeef"));
+        assert_eq!(keys.verify(&signed, None), SignatureStatus::Verified { keyid: "SHA256:test".to_string() });
+
+        // Signed over another version, by another key, or by a key the
+        // registry doesn't list.
+        let replayed = package("SHA256:test", sign(&signing_key, "left-pad@1.2.0:sha512-deadbeef"));
+        assert!(matches!(keys.verify(&replayed, None), SignatureStatus::Invalid(_)));
+        let forged = package("SHA256:test", sign(&other_key, "left-pad@1.3.0:sha512-deadbeef"));
+        assert!(matches!(keys.verify(&forged, None), SignatureStatus::Invalid(_)));
+        let unknown = package("SHA256:other", sign(&signing_key, "left-pad@1.3.0:sha512-deadbeef"));
+        assert_eq!(
+            keys.verify(&unknown, None),
+            SignatureStatus::Invalid("signed with unknown key SHA256:other".to_string())
+        );
+
+        let mut unsigned = signed.clone();
+        unsigned.dist.signatures.clear();
+        assert_eq!(keys.verify(&unsigned, None), SignatureStatus::Unsigned);
+
+        // Once the key has expired, only versions published before that
+        // verify.
+        let mut expired = keys.clone();
+        expired.keys[0].expires = Some("2025-01-29T00:00:00.000Z".to_string());
+        let status = expired.verify(&signed, Some("2021-03-01T12:30:00.000Z"));
+        assert_eq!(status, SignatureStatus::Verified { keyid: "SHA256:test".to_string() });
+        assert_eq!(
+            expired.verify(&signed, Some("2025-02-01T08:00:00.000Z")),
+            SignatureStatus::Invalid("signed with key SHA256:test after it expired".to_string())
+        );
+        assert!(matches!(expired.verify(&signed, None), SignatureStatus::Invalid(_)));
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-02T00:00:01Z"), Some(86_401));
+        assert_eq!(parse_timestamp("2025-01-29T00:00:00.000Z"), Some(1_738_108_800));
+        assert_eq!(parse_timestamp("2025-01-29"), None);
+        assert_eq!(parse_timestamp("2025-01-29T00:00:00+01:00"), None);
+    }
 }
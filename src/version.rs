@@ -1,5 +1,7 @@
-use semver::{Version, VersionReq};
+use semver::{Comparator, Op, Prerelease, Version, VersionReq};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,10 +10,33 @@ pub enum VersionError {
     InvalidRequirement(String),
     #[error("No compatible version found for {package} (requirement: {requirement})")]
     NoCompatibleVersion { package: String, requirement: String },
+    #[error("No version of {package} satisfies all requirements: {}", format_constraints(.constraints))]
+    ConflictingConstraints { package: String, constraints: Vec<Constraint> },
+}
+
+/// A requirement on a package together with whoever imposed it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub requirement: VersionReq,
+    pub required_by: String,
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (required by {})", self.requirement, self.required_by)
+    }
+}
+
+fn format_constraints(constraints: &[Constraint]) -> String {
+    constraints
+        .iter()
+        .map(Constraint::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 pub struct VersionManager {
-    version_constraints: HashMap<String, VersionReq>,
+    version_constraints: HashMap<String, Vec<Constraint>>,
     resolved_versions: HashMap<String, Version>,
 }
 
@@ -23,26 +48,57 @@ impl VersionManager {
         }
     }
 
-    pub fn add_constraint(&mut self, package: String, requirement: &str) -> Result<(), VersionError> {
+    /// Adds a requirement on `package`. Requirements accumulate: a resolved
+    /// version has to satisfy every one of them.
+    pub fn add_constraint(&mut self, package: String, requirement: &str, required_by: &str) -> Result<(), VersionError> {
         let req = VersionReq::parse(requirement)
             .map_err(|_| VersionError::InvalidRequirement(requirement.to_string()))?;
-        self.version_constraints.insert(package, req);
+        self.version_constraints.entry(package).or_default().push(Constraint {
+            requirement: req,
+            required_by: required_by.to_string(),
+        });
         Ok(())
     }
 
+    pub fn constraints(&self, package: &str) -> &[Constraint] {
+        self.version_constraints
+            .get(package)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Whether `requirement` can be satisfied together with every constraint
+    /// already recorded for `package`. When it can't, the package has to be
+    /// installed twice and the new copy nested under its dependent.
+    pub fn is_compatible(&self, package: &str, requirement: &VersionReq) -> bool {
+        self.constraints(package)
+            .iter()
+            .all(|constraint| ranges_intersect(&constraint.requirement, requirement))
+    }
+
+    /// Picks the highest of `available_versions` satisfying every constraint
+    /// on `package`.
     pub fn resolve_version(&mut self, package: &str, available_versions: &[Version]) -> Result<Version, VersionError> {
-        let requirement = self.version_constraints.get(package)
-            .ok_or_else(|| VersionError::NoCompatibleVersion {
+        let constraints = self.constraints(package);
+        if constraints.is_empty() {
+            return Err(VersionError::NoCompatibleVersion {
                 package: package.to_string(),
                 requirement: "*".to_string(),
-            })?;
+            });
+        }
 
         let compatible_version = available_versions.iter()
-            .filter(|v| requirement.matches(v))
+            .filter(|v| constraints.iter().all(|c| c.requirement.matches(v)))
             .max()
-            .ok_or_else(|| VersionError::NoCompatibleVersion {
-                package: package.to_string(),
-                requirement: requirement.to_string(),
+            .ok_or_else(|| match constraints {
+                [constraint] => VersionError::NoCompatibleVersion {
+                    package: package.to_string(),
+                    requirement: constraint.requirement.to_string(),
+                },
+                _ => VersionError::ConflictingConstraints {
+                    package: package.to_string(),
+                    constraints: constraints.to_vec(),
+                },
             })?;
 
         let version = compatible_version.clone();
@@ -53,4 +109,325 @@ impl VersionManager {
     pub fn get_resolved_version(&self, package: &str) -> Option<&Version> {
         self.resolved_versions.get(package)
     }
-} 
\ No newline at end of file
+}
+
+impl Default for VersionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether some release version satisfies both requirements. Every
+/// `VersionReq` is a conjunction of comparators, i.e. a single interval, so
+/// this is an interval overlap test. Prereleases are not considered, in line
+/// with `VersionReq::matches` excluding them by default.
+pub fn ranges_intersect(a: &VersionReq, b: &VersionReq) -> bool {
+    let mut interval = Interval::unbounded();
+    for comparator in a.comparators.iter().chain(&b.comparators) {
+        interval = interval.intersect(&Interval::from_comparator(comparator));
+    }
+    !interval.is_empty()
+}
+
+/// An npm range as advisories write them: `||`-separated alternatives of
+/// space-separated comparators, e.g. `>=2.0.0 <2.0.3 || <1.4.1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionRange {
+    alternatives: Vec<VersionReq>,
+}
+
+impl VersionRange {
+    pub fn parse(range: &str) -> Result<Self, VersionError> {
+        let alternatives = range
+            .split("||")
+            .map(|alternative| {
+                let alternative = alternative.trim();
+                let req = match alternative.split_once(" - ") {
+                    Some((low, high)) => format!(">={}, <={}", low.trim(), high.trim()),
+                    None => join_comparators(alternative),
+                };
+                let req = if req.is_empty() { "*" } else { req.as_str() };
+                VersionReq::parse(req).map_err(|_| VersionError::InvalidRequirement(range.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { alternatives })
+    }
+
+    /// Whether `version` is in the range. Unlike `VersionReq::matches`,
+    /// prereleases count like any other version, so `<2.0.0` covers
+    /// `2.0.0-beta.1`.
+    pub fn matches(&self, version: &Version) -> bool {
+        self.alternatives.iter().any(|req| {
+            req.comparators
+                .iter()
+                .all(|comparator| Interval::from_comparator(comparator).contains(version))
+        })
+    }
+}
+
+/// Turns `>= 1.2.0 <2` into `>=1.2.0, <2`, the form `VersionReq` parses.
+/// A bare version is exact in npm ranges but a caret requirement to
+/// `VersionReq`, so it gets an explicit `=`.
+fn join_comparators(alternative: &str) -> String {
+    let mut comparators = Vec::new();
+    let mut operator = String::new();
+    for token in alternative.split_whitespace() {
+        if token.chars().all(|c| "<>=~^".contains(c)) {
+            operator.push_str(token);
+        } else {
+            let mut operator = std::mem::take(&mut operator);
+            let bare = token.starts_with(|c: char| c.is_ascii_digit());
+            if operator.is_empty() && bare && !token.contains(['x', 'X', '*']) {
+                operator.push('=');
+            }
+            comparators.push(format!("{}{}", operator, token));
+        }
+    }
+    comparators.join(", ")
+}
+
+#[derive(Debug, Clone)]
+struct Bound {
+    version: Version,
+    inclusive: bool,
+}
+
+/// Versions between `lower` and `upper`; `None` means unbounded.
+#[derive(Debug, Clone)]
+struct Interval {
+    lower: Option<Bound>,
+    upper: Option<Bound>,
+}
+
+impl Interval {
+    fn unbounded() -> Self {
+        Self { lower: None, upper: None }
+    }
+
+    fn from_comparator(comparator: &Comparator) -> Self {
+        let major = comparator.major;
+        let minor = comparator.minor;
+        let patch = comparator.patch;
+
+        let at = |minor: Option<u64>, patch: Option<u64>| {
+            let mut version = Version::new(major, minor.unwrap_or(0), patch.unwrap_or(0));
+            if minor.is_some() && patch.is_some() {
+                version.pre = comparator.pre.clone();
+            }
+            version
+        };
+        // The first version past the precision given, e.g. `1.2` -> 1.3.0.
+        let next = || match (minor, patch) {
+            (None, _) => Version::new(major + 1, 0, 0),
+            (Some(minor), None) => Version::new(major, minor + 1, 0),
+            (Some(minor), Some(patch)) => Version::new(major, minor, patch + 1),
+        };
+        let inclusive = |version| Some(Bound { version, inclusive: true });
+        let exclusive = |version| Some(Bound { version, inclusive: false });
+
+        match comparator.op {
+            Op::Exact | Op::Wildcard => match (minor, patch) {
+                (Some(_), Some(_)) => Self { lower: inclusive(at(minor, patch)), upper: inclusive(at(minor, patch)) },
+                _ => Self { lower: inclusive(at(minor, patch)), upper: exclusive(next()) },
+            },
+            Op::Greater => match (minor, patch) {
+                (Some(_), Some(_)) => Self { lower: exclusive(at(minor, patch)), upper: None },
+                _ => Self { lower: inclusive(next()), upper: None },
+            },
+            Op::GreaterEq => Self { lower: inclusive(at(minor, patch)), upper: None },
+            Op::Less => Self { lower: None, upper: exclusive(at(minor, patch)) },
+            Op::LessEq => match (minor, patch) {
+                (Some(_), Some(_)) => Self { lower: None, upper: inclusive(at(minor, patch)) },
+                _ => Self { lower: None, upper: exclusive(next()) },
+            },
+            Op::Tilde => {
+                let upper = match minor {
+                    Some(minor) => Version::new(major, minor + 1, 0),
+                    None => Version::new(major + 1, 0, 0),
+                };
+                Self { lower: inclusive(at(minor, patch)), upper: exclusive(upper) }
+            }
+            Op::Caret => {
+                let upper = match (major, minor, patch) {
+                    (0, Some(0), Some(patch)) => Version::new(0, 0, patch + 1),
+                    (0, Some(minor), _) => Version::new(0, minor + 1, 0),
+                    _ => Version::new(major + 1, 0, 0),
+                };
+                Self { lower: inclusive(at(minor, patch)), upper: exclusive(upper) }
+            }
+            _ => Self::unbounded(),
+        }
+    }
+
+    fn intersect(&self, other: &Interval) -> Interval {
+        let lower = match (&self.lower, &other.lower) {
+            (Some(a), Some(b)) => Some(tighter(a, b, Ordering::Greater)),
+            (a, b) => a.clone().or_else(|| b.clone()),
+        };
+        let upper = match (&self.upper, &other.upper) {
+            (Some(a), Some(b)) => Some(tighter(a, b, Ordering::Less)),
+            (a, b) => a.clone().or_else(|| b.clone()),
+        };
+        Interval { lower, upper }
+    }
+
+    fn contains(&self, version: &Version) -> bool {
+        let above = self.lower.as_ref().is_none_or(|bound| match version.cmp(&bound.version) {
+            Ordering::Greater => true,
+            Ordering::Equal => bound.inclusive,
+            Ordering::Less => false,
+        });
+        let below = self.upper.as_ref().is_none_or(|bound| match version.cmp(&bound.version) {
+            Ordering::Less => true,
+            Ordering::Equal => bound.inclusive,
+            Ordering::Greater => false,
+        });
+        above && below
+    }
+
+    /// Whether no release version lies in the interval.
+    fn is_empty(&self) -> bool {
+        let (Some(lower), Some(upper)) = (&self.lower, &self.upper) else {
+            return false;
+        };
+        let lower = lower.to_release(true);
+        let upper = upper.to_release(false);
+
+        match lower.version.cmp(&upper.version) {
+            Ordering::Greater => true,
+            Ordering::Equal => !(lower.inclusive && upper.inclusive),
+            Ordering::Less => false,
+        }
+    }
+}
+
+impl Bound {
+    /// The equivalent bound when only release versions count: releases at or
+    /// above `1.2.3-beta` start at 1.2.3, releases below it end before 1.2.3.
+    fn to_release(&self, is_lower: bool) -> Bound {
+        if self.version.pre == Prerelease::EMPTY {
+            return self.clone();
+        }
+        let mut version = self.version.clone();
+        version.pre = Prerelease::EMPTY;
+        Bound {
+            version,
+            inclusive: is_lower,
+        }
+    }
+}
+
+/// The more restrictive of two bounds: the one further in `direction`, or the
+/// exclusive one when both name the same version.
+fn tighter(a: &Bound, b: &Bound, direction: Ordering) -> Bound {
+    match a.version.cmp(&b.version) {
+        Ordering::Equal => Bound {
+            version: a.version.clone(),
+            inclusive: a.inclusive && b.inclusive,
+        },
+        ordering if ordering == direction => a.clone(),
+        _ => b.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(s: &str) -> VersionReq {
+        VersionReq::parse(s).unwrap()
+    }
+
+    fn versions(list: &[&str]) -> Vec<Version> {
+        list.iter().map(|v| Version::parse(v).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_constraints_accumulate() {
+        let mut manager = VersionManager::new();
+        manager.add_constraint("lodash".to_string(), "^4.0.0", "express@4.18.2").unwrap();
+        manager.add_constraint("lodash".to_string(), "<4.17.21", "app").unwrap();
+
+        let available = versions(&["3.10.1", "4.17.15", "4.17.20", "4.17.21"]);
+        let version = manager.resolve_version("lodash", &available).unwrap();
+
+        assert_eq!(version, Version::parse("4.17.20").unwrap());
+        assert_eq!(manager.get_resolved_version("lodash"), Some(&version));
+    }
+
+    #[test]
+    fn test_conflict_lists_every_requirement() {
+        let mut manager = VersionManager::new();
+        manager.add_constraint("react".to_string(), "^17.0.0", "app").unwrap();
+        manager.add_constraint("react".to_string(), "^18.0.0", "some-ui@2.0.0").unwrap();
+
+        let result = manager.resolve_version("react", &versions(&["17.0.2", "18.2.0"]));
+
+        match result {
+            Err(VersionError::ConflictingConstraints { package, constraints }) => {
+                assert_eq!(package, "react");
+                assert_eq!(constraints.len(), 2);
+                assert_eq!(constraints[1].required_by, "some-ui@2.0.0");
+            }
+            other => panic!("Expected ConflictingConstraints, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ranges_intersect() {
+        assert!(ranges_intersect(&req("^1.2.0"), &req("~1.4.1")));
+        assert!(ranges_intersect(&req(">=1.0.0, <2.0.0"), &req("1.9")));
+        assert!(ranges_intersect(&req("<=1.2"), &req(">=1.2.9")));
+        assert!(ranges_intersect(&req("*"), &req("^0.0.3")));
+        assert!(!ranges_intersect(&req("^1.0.0"), &req("^2.0.0")));
+        assert!(!ranges_intersect(&req("^0.2.0"), &req("^0.3.0")));
+        assert!(!ranges_intersect(&req("<1.2.3"), &req(">=1.2.3")));
+        assert!(!ranges_intersect(&req(">1.2.3"), &req("=1.2.3")));
+        assert!(!ranges_intersect(&req(">=2.0.0-alpha"), &req("<2.0.0")));
+    }
+
+    #[test]
+    fn test_version_range() {
+        let matches = |range: &str, version: &str| {
+            VersionRange::parse(range).unwrap().matches(&Version::parse(version).unwrap())
+        };
+
+        assert!(matches("<4.17.21", "4.17.20"));
+        assert!(!matches("<4.17.21", "4.17.21"));
+        assert!(matches(">=2.0.0 <2.0.3 || <1.4.1", "2.0.2"));
+        assert!(matches(">=2.0.0 <2.0.3 || <1.4.1", "1.4.0"));
+        assert!(!matches(">=2.0.0 <2.0.3 || <1.4.1", "1.5.0"));
+        assert!(matches(">= 3.0.0 < 3.1.2", "3.1.1"));
+        assert!(matches("1.2.0 - 1.4.0", "1.4.0"));
+        assert!(matches("*", "0.0.1"));
+        assert!(matches("<2.0.0", "2.0.0-beta.1"));
+        assert!(!matches("<=1.0.0", "1.0.1-rc.1"));
+        assert!(VersionRange::parse(">=1.0.0 <nope").is_err());
+    }
+
+    #[test]
+    fn test_version_range_bare_versions_are_exact() {
+        let matches = |range: &str, version: &str| {
+            VersionRange::parse(range).unwrap().matches(&Version::parse(version).unwrap())
+        };
+
+        assert!(matches("1.2.3", "1.2.3"));
+        assert!(!matches("1.2.3", "1.4.0"));
+        assert!(matches("1.0.0 || 1.0.2", "1.0.2"));
+        assert!(!matches("1.0.0 || 1.0.2", "1.0.1"));
+        assert!(matches("1.2", "1.2.9"));
+        assert!(!matches("1.2", "1.3.0"));
+        assert!(matches("1.x", "1.9.0"));
+        assert!(matches("^1.2.0", "1.4.0"));
+    }
+
+    #[test]
+    fn test_is_compatible() {
+        let mut manager = VersionManager::new();
+        manager.add_constraint("debug".to_string(), "^2.6.0", "express@4.18.2").unwrap();
+
+        assert!(manager.is_compatible("debug", &req("~2.6.9")));
+        assert!(!manager.is_compatible("debug", &req("^4.0.0")));
+        assert!(manager.is_compatible("unconstrained", &req("^4.0.0")));
+    }
+}
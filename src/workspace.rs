@@ -0,0 +1,318 @@
+use crate::error::WorkspaceError;
+use crate::package::PackageJson;
+use semver::{Version, VersionReq};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Prefix of the `workspace:` protocol, which always resolves to a local member.
+const WORKSPACE_PROTOCOL: &str = "workspace:";
+
+#[derive(Debug, Clone)]
+pub struct WorkspaceMember {
+    pub name: String,
+    pub version: String,
+    pub path: PathBuf,
+    /// Location relative to the workspace root, as recorded in the lockfile.
+    pub relative_path: PathBuf,
+    pub manifest: PackageJson,
+}
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub manifest: PackageJson,
+    pub members: Vec<WorkspaceMember>,
+}
+
+impl Workspace {
+    /// Loads the workspace rooted at `root`. Returns `None` when there is no
+    /// root `package.json` or it has no `workspaces` field.
+    pub async fn discover(root: &Path) -> Result<Option<Self>, WorkspaceError> {
+        let manifest_path = root.join("package.json");
+        if !manifest_path.is_file() {
+            return Ok(None);
+        }
+
+        let manifest = load_manifest(&manifest_path).await?;
+        let patterns = match &manifest.workspaces {
+            Some(workspaces) => workspaces.patterns().to_vec(),
+            None => return Ok(None),
+        };
+
+        let members = collect_members(root, &patterns).await?;
+
+        Ok(Some(Self {
+            root: root.to_path_buf(),
+            manifest,
+            members,
+        }))
+    }
+
+    /// Looks a member up by package name or by its path relative to the root.
+    pub fn member(&self, name: &str) -> Option<&WorkspaceMember> {
+        self.members
+            .iter()
+            .find(|m| m.name == name || m.relative_path == Path::new(name))
+    }
+
+    /// Members targeted by `--workspace <name>` / `--workspaces`. An empty
+    /// result means the command applies to the root project only.
+    pub fn select(&self, names: &[String], all: bool) -> Result<Vec<&WorkspaceMember>, WorkspaceError> {
+        if all {
+            return Ok(self.members.iter().collect());
+        }
+
+        names
+            .iter()
+            .map(|name| {
+                self.member(name)
+                    .ok_or_else(|| WorkspaceError::UnknownMember(name.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the member that satisfies `name@spec`, if any. The `workspace:`
+    /// protocol always resolves locally, plain ranges only when the member's
+    /// version matches.
+    pub fn resolve_local(&self, name: &str, spec: &str) -> Option<&WorkspaceMember> {
+        let member = self.members.iter().find(|m| m.name == name)?;
+
+        if spec.starts_with(WORKSPACE_PROTOCOL) {
+            return Some(member);
+        }
+
+        let req = VersionReq::parse(spec).ok()?;
+        let version = Version::parse(&member.version).ok()?;
+        req.matches(&version).then_some(member)
+    }
+
+    /// Manifests whose dependencies are installed for the given selection,
+    /// paired with their directories: the root first, then the selected
+    /// members, or every member when none is selected.
+    pub fn manifests<'a>(&'a self, selected: &[&'a WorkspaceMember]) -> Vec<(&'a Path, &'a PackageJson)> {
+        let members: Vec<&WorkspaceMember> = if selected.is_empty() {
+            self.members.iter().collect()
+        } else {
+            selected.to_vec()
+        };
+
+        let mut manifests = vec![(self.root.as_path(), &self.manifest)];
+        manifests.extend(members.into_iter().map(|m| (m.path.as_path(), &m.manifest)));
+        manifests
+    }
+
+    /// Maps each member to the members it depends on, through either
+    /// `dependencies` or `devDependencies`.
+    pub fn dependency_graph(&self) -> HashMap<String, Vec<String>> {
+        self.members
+            .iter()
+            .map(|member| {
+                let manifest = &member.manifest;
+                let mut deps: Vec<String> = manifest
+                    .dependencies
+                    .iter()
+                    .chain(manifest.dev_dependencies.iter())
+                    .flatten()
+                    .filter(|(name, spec)| self.resolve_local(name, spec).is_some())
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                deps.sort();
+                deps.dedup();
+                (member.name.clone(), deps)
+            })
+            .collect()
+    }
+
+    /// Symlinks every member into the root `node_modules` so that requiring a
+    /// member by name resolves to its sources.
+    pub async fn link_members(&self) -> Result<(), WorkspaceError> {
+        let node_modules = self.root.join("node_modules");
+
+        for member in &self.members {
+            let link = node_modules.join(&member.name);
+            let parent = link.parent().unwrap_or(&node_modules);
+            fs::create_dir_all(parent).await?;
+
+            match fs::symlink_metadata(&link).await {
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&link).await?,
+                Ok(_) => fs::remove_file(&link).await?,
+                Err(_) => {}
+            }
+
+            // Relative links keep the tree valid when the repository is moved.
+            let depth = parent.strip_prefix(&self.root).map_or(1, |p| p.components().count());
+            let mut target = PathBuf::new();
+            for _ in 0..depth {
+                target.push("..");
+            }
+            target.push(&member.relative_path);
+
+            symlink_dir(&target, &link).await?;
+            log::debug!("Linked workspace {} -> {}", member.name, target.display());
+        }
+
+        Ok(())
+    }
+}
+
+async fn collect_members(root: &Path, patterns: &[String]) -> Result<Vec<WorkspaceMember>, WorkspaceError> {
+    let escaped_root = glob::Pattern::escape(&root.to_string_lossy());
+    let mut included = Vec::new();
+    let mut excluded = HashSet::new();
+
+    for pattern in patterns {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, pattern.as_str()),
+        };
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        let full_pattern = format!("{}/{}", escaped_root, pattern);
+
+        let paths = glob::glob(&full_pattern).map_err(|e| WorkspaceError::InvalidPattern {
+            pattern: pattern.to_string(),
+            details: e.to_string(),
+        })?;
+
+        for path in paths {
+            let path = path.map_err(|e| WorkspaceError::IoError(e.into()))?;
+            if negated {
+                excluded.insert(path);
+            } else if !included.contains(&path) {
+                included.push(path);
+            }
+        }
+    }
+
+    let mut members = Vec::new();
+    let mut seen = HashSet::new();
+
+    for path in included {
+        let manifest_path = path.join("package.json");
+        if excluded.contains(&path) || !manifest_path.is_file() {
+            continue;
+        }
+
+        let manifest = load_manifest(&manifest_path).await?;
+        if !seen.insert(manifest.name.clone()) {
+            return Err(WorkspaceError::DuplicateMember(manifest.name));
+        }
+
+        members.push(WorkspaceMember {
+            name: manifest.name.clone(),
+            version: manifest.version.clone(),
+            relative_path: path.strip_prefix(root).unwrap_or(&path).to_path_buf(),
+            path,
+            manifest,
+        });
+    }
+
+    members.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(members)
+}
+
+async fn load_manifest(path: &Path) -> Result<PackageJson, WorkspaceError> {
+    PackageJson::load_from(path)
+        .await
+        .map_err(|e| WorkspaceError::ManifestError {
+            path: path.to_path_buf(),
+            details: e.to_string(),
+        })
+}
+
+#[cfg(unix)]
+pub(crate) async fn symlink_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    fs::symlink(target, link).await
+}
+
+#[cfg(windows)]
+pub(crate) async fn symlink_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    fs::symlink_dir(target, link).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_manifest;
+    use tempfile::tempdir;
+
+    async fn create_monorepo(root: &Path) {
+        write_manifest(
+            root,
+            r#"{"name": "monorepo", "version": "0.0.0", "workspaces": ["packages/*", "!packages/ignored"]}"#,
+        )
+        .await;
+        write_manifest(
+            &root.join("packages/core"),
+            r#"{"name": "@acme/core", "version": "1.2.0"}"#,
+        )
+        .await;
+        write_manifest(
+            &root.join("packages/app"),
+            r#"{"name": "app", "version": "0.1.0", "dependencies": {"@acme/core": "^1.0.0"}}"#,
+        )
+        .await;
+        write_manifest(
+            &root.join("packages/ignored"),
+            r#"{"name": "ignored", "version": "0.1.0"}"#,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_discover_members() {
+        let temp_dir = tempdir().unwrap();
+        create_monorepo(temp_dir.path()).await;
+
+        let workspace = Workspace::discover(temp_dir.path()).await.unwrap().unwrap();
+        let names: Vec<_> = workspace.members.iter().map(|m| m.name.as_str()).collect();
+
+        assert_eq!(names, vec!["@acme/core", "app"]);
+        assert_eq!(
+            workspace.member("packages/app").unwrap().name,
+            "app"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_local() {
+        let temp_dir = tempdir().unwrap();
+        create_monorepo(temp_dir.path()).await;
+        let workspace = Workspace::discover(temp_dir.path()).await.unwrap().unwrap();
+
+        assert!(workspace.resolve_local("@acme/core", "^1.0.0").is_some());
+        assert!(workspace.resolve_local("@acme/core", "workspace:*").is_some());
+        assert!(workspace.resolve_local("@acme/core", "^2.0.0").is_none());
+        assert!(workspace.resolve_local("lodash", "^4.0.0").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_select_unknown_member() {
+        let temp_dir = tempdir().unwrap();
+        create_monorepo(temp_dir.path()).await;
+        let workspace = Workspace::discover(temp_dir.path()).await.unwrap().unwrap();
+
+        assert_eq!(workspace.select(&[], true).unwrap().len(), 2);
+        assert!(matches!(
+            workspace.select(&["missing".to_string()], false),
+            Err(WorkspaceError::UnknownMember(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_link_members() {
+        let temp_dir = tempdir().unwrap();
+        create_monorepo(temp_dir.path()).await;
+        let workspace = Workspace::discover(temp_dir.path()).await.unwrap().unwrap();
+
+        workspace.link_members().await.unwrap();
+
+        let link = temp_dir.path().join("node_modules/@acme/core");
+        assert_eq!(
+            fs::read_link(&link).await.unwrap(),
+            PathBuf::from("../../packages/core")
+        );
+        assert!(link.join("package.json").is_file());
+    }
+}
@@ -1,66 +1,84 @@
 use anyhow::Result;
+use clap::Parser;
 use rpm::{
+    cache::{CacheConfig, PackageCache},
     cli::Cli,
     package::PackageJson,
-    security::SecurityChecker,
+    profiling::MemoryProfile,
+    project::ProjectRoot,
+    AppContext,
 };
-use std::path::PathBuf;
 use tempfile::tempdir;
-use tokio;
 
-async fn setup_test_environment() -> Result<tempfile::TempDir> {
+/// A project in a temporary directory, with its own package cache.
+async fn setup_test_environment() -> Result<(tempfile::TempDir, AppContext)> {
     let temp_dir = tempdir()?;
-    std::env::set_current_dir(&temp_dir)?;
-    Ok(temp_dir)
+    let cache_config = CacheConfig {
+        cache_dir: temp_dir.path().join(".cache"),
+        ..CacheConfig::default()
+    };
+    let context = AppContext {
+        project: ProjectRoot::at(temp_dir.path().to_path_buf()),
+        memory_profile: MemoryProfile::new(1024 * 1024 * 1024),
+        package_cache: PackageCache::new(cache_config).await?,
+    };
+    Ok((temp_dir, context))
+}
+
+fn manifest(dependencies: &[(&str, &str)]) -> PackageJson {
+    let mut manifest = PackageJson::default();
+    manifest.name = "test-project".to_string();
+    manifest.version = "1.0.0".to_string();
+    manifest.dependencies = Some(
+        dependencies
+            .iter()
+            .map(|(name, range)| (name.to_string(), range.to_string()))
+            .collect(),
+    );
+    manifest
 }
 
 #[tokio::test]
+#[ignore = "needs access to registry.npmjs.org"]
 async fn test_package_installation() -> Result<()> {
-    let _temp_dir = setup_test_environment().await?;
+    let (temp_dir, context) = setup_test_environment().await?;
+    let root = temp_dir.path();
 
     // Create a minimal package.json
-    let package_json = PackageJson {
-        name: "test-project".to_string(),
-        version: "1.0.0".to_string(),
-        dependencies: Some([("express".to_string(), "^4.17.1".to_string())].into()),
-        dev_dependencies: None,
-    };
-    package_json.save().await?;
+    let package_json = manifest(&[("express", "^4.17.1")]);
+    package_json.save(root).await?;
 
     // Test installation
-    let cli = Cli::parse_from(&["rpm", "install", "express"]);
-    cli.execute().await?;
+    let cli = Cli::parse_from(["rpm", "install", "express"]);
+    cli.execute(context.clone()).await?;
 
     // Verify installation
-    assert!(PathBuf::from("node_modules/express").exists());
-    assert!(PathBuf::from("node_modules/express/package.json").exists());
+    assert!(root.join("node_modules/express").exists());
+    assert!(root.join("node_modules/express/package.json").exists());
 
     Ok(())
 }
 
 #[tokio::test]
+#[ignore = "needs access to registry.npmjs.org"]
 async fn test_security_audit() -> Result<()> {
-    let _temp_dir = setup_test_environment().await?;
+    let (temp_dir, context) = setup_test_environment().await?;
+    let root = temp_dir.path();
 
     // Create a package.json with a known vulnerable package
-    let package_json = PackageJson {
-        name: "test-project".to_string(),
-        version: "1.0.0".to_string(),
-        dependencies: Some([("lodash".to_string(), "4.17.15".to_string())].into()),
-        dev_dependencies: None,
-    };
-    package_json.save().await?;
+    let package_json = manifest(&[("lodash", "4.17.15")]);
+    package_json.save(root).await?;
 
     // Test audit
-    let cli = Cli::parse_from(&["rpm", "audit"]);
-    cli.execute().await?;
+    let cli = Cli::parse_from(["rpm", "audit"]);
+    cli.execute(context.clone()).await?;
 
     // Test audit --fix
-    let cli = Cli::parse_from(&["rpm", "audit", "--fix"]);
-    cli.execute().await?;
+    let cli = Cli::parse_from(["rpm", "audit", "--fix"]);
+    cli.execute(context.clone()).await?;
 
     // Verify fix
-    let updated_package_json = PackageJson::load().await?;
+    let updated_package_json = PackageJson::load(root).await?;
     let deps = updated_package_json.dependencies.unwrap();
     assert_ne!(deps.get("lodash").unwrap(), "4.17.15");
 
@@ -68,55 +86,43 @@ async fn test_security_audit() -> Result<()> {
 }
 
 #[tokio::test]
+#[ignore = "needs access to registry.npmjs.org"]
 async fn test_parallel_installation() -> Result<()> {
-    let _temp_dir = setup_test_environment().await?;
+    let (temp_dir, context) = setup_test_environment().await?;
+    let root = temp_dir.path();
 
     // Create package.json with multiple dependencies
-    let package_json = PackageJson {
-        name: "test-project".to_string(),
-        version: "1.0.0".to_string(),
-        dependencies: Some([
-            ("express".to_string(), "^4.17.1".to_string()),
-            ("lodash".to_string(), "^4.17.21".to_string()),
-            ("react".to_string(), "^17.0.2".to_string()),
-        ].into()),
-        dev_dependencies: None,
-    };
-    package_json.save().await?;
+    let package_json = manifest(&[("express", "^4.17.1"), ("lodash", "^4.17.21"), ("react", "^17.0.2")]);
+    package_json.save(root).await?;
 
     // Test parallel installation
-    let cli = Cli::parse_from(&["rpm", "install", "express", "lodash", "react"]);
-    cli.execute().await?;
+    let cli = Cli::parse_from(["rpm", "install", "express", "lodash", "react"]);
+    cli.execute(context.clone()).await?;
 
     // Verify all packages are installed
-    assert!(PathBuf::from("node_modules/express").exists());
-    assert!(PathBuf::from("node_modules/lodash").exists());
-    assert!(PathBuf::from("node_modules/react").exists());
+    assert!(root.join("node_modules/express").exists());
+    assert!(root.join("node_modules/lodash").exists());
+    assert!(root.join("node_modules/react").exists());
 
     Ok(())
 }
 
 #[tokio::test]
+#[ignore = "needs access to registry.npmjs.org"]
 async fn test_update_command() -> Result<()> {
-    let _temp_dir = setup_test_environment().await?;
+    let (temp_dir, context) = setup_test_environment().await?;
+    let root = temp_dir.path();
 
     // Create package.json with an old version
-    let package_json = PackageJson {
-        name: "test-project".to_string(),
-        version: "1.0.0".to_string(),
-        dependencies: Some([
-            ("lodash".to_string(), "4.17.15".to_string()),
-        ].into()),
-        dev_dependencies: None,
-    };
-    package_json.save().await?;
+    let package_json = manifest(&[("lodash", "4.17.15")]);
+    package_json.save(root).await?;
 
     // Test update
-    let cli = Cli::parse_from(&["rpm", "update"]);
-    cli.execute().await?;
+    let cli = Cli::parse_from(["rpm", "update"]);
+    cli.execute(context.clone()).await?;
 
     // Verify update
-    let updated_package_json = PackageJson::load().await?;
+    let updated_package_json = PackageJson::load(root).await?;
     let deps = updated_package_json.dependencies.unwrap();
     assert_ne!(deps.get("lodash").unwrap(), "4.17.15");
 
@@ -124,30 +130,25 @@ async fn test_update_command() -> Result<()> {
 }
 
 #[tokio::test]
+#[ignore = "needs access to registry.npmjs.org"]
 async fn test_remove_command() -> Result<()> {
-    let _temp_dir = setup_test_environment().await?;
+    let (temp_dir, context) = setup_test_environment().await?;
+    let root = temp_dir.path();
 
     // Create package.json and install a package
-    let package_json = PackageJson {
-        name: "test-project".to_string(),
-        version: "1.0.0".to_string(),
-        dependencies: Some([
-            ("express".to_string(), "^4.17.1".to_string()),
-        ].into()),
-        dev_dependencies: None,
-    };
-    package_json.save().await?;
+    let package_json = manifest(&[("express", "^4.17.1")]);
+    package_json.save(root).await?;
 
-    let cli = Cli::parse_from(&["rpm", "install", "express"]);
-    cli.execute().await?;
+    let cli = Cli::parse_from(["rpm", "install", "express"]);
+    cli.execute(context.clone()).await?;
 
     // Test remove
-    let cli = Cli::parse_from(&["rpm", "remove", "express"]);
-    cli.execute().await?;
+    let cli = Cli::parse_from(["rpm", "remove", "express"]);
+    cli.execute(context.clone()).await?;
 
     // Verify removal
-    assert!(!PathBuf::from("node_modules/express").exists());
-    let updated_package_json = PackageJson::load().await?;
+    assert!(!root.join("node_modules/express").exists());
+    let updated_package_json = PackageJson::load(root).await?;
     assert!(updated_package_json.dependencies.unwrap().is_empty());
 
     Ok(())
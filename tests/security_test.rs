@@ -1,17 +1,11 @@
-use rpm::security::{SecurityChecker, Vulnerability};
+use rpm::security::{Advisory, Severity};
 use anyhow::Result;
 use mockall::automock;
 use semver::Version;
 
 #[automock]
 trait SecurityCheck {
-    async fn check_package(&mut self, name: &str, version: &Version) -> Result<Vec<Vulnerability>>;
-    async fn find_safe_version(
-        &mut self,
-        name: &str,
-        current_version: &Version,
-        available_versions: &[Version],
-    ) -> Result<Version>;
+    async fn check_package(&mut self, name: &str, version: &Version) -> Result<Vec<Advisory>>;
 }
 
 #[tokio::test]
@@ -21,13 +15,14 @@ async fn test_security_checker_with_mocks() -> Result<()> {
     // Setup expectations
     mock.expect_check_package()
         .returning(|_, _| {
-            Ok(vec![Vulnerability {
+            Ok(vec![Advisory {
                 id: "CVE-2021-1234".to_string(),
+                url: String::new(),
                 title: "Test vulnerability".to_string(),
-                description: "Test description".to_string(),
-                severity: "high".to_string(),
-                affected_versions: "<=4.17.15".to_string(),
-                patched_version: Some("4.17.16".to_string()),
+                severity: Severity::High,
+                vulnerable_versions: "<=4.17.15".to_string(),
+                cwe: Vec::new(),
+                cvss: None,
             }])
         });
 
@@ -37,7 +32,7 @@ async fn test_security_checker_with_mocks() -> Result<()> {
     
     assert_eq!(vulns.len(), 1);
     assert_eq!(vulns[0].id, "CVE-2021-1234");
-    assert_eq!(vulns[0].severity, "high");
+    assert_eq!(vulns[0].severity, Severity::High);
 
     Ok(())
 } 
\ No newline at end of file